@@ -1,16 +1,6 @@
-use std::fs;
-use std::sync::Mutex;
-
-use anyhow::bail;
 use gosub_html5::node::{NodeData, NodeId};
-use gosub_html5::parser::document::Document;
-use gosub_html5::parser::document::DocumentBuilder;
-use gosub_html5::parser::Html5Parser;
-use gosub_shared::bytes::{CharIterator, Confidence, Encoding};
-use gosub_styling::css_colors::RgbColor;
 use gosub_styling::css_values::CssValue;
-use gosub_styling::render_tree::{generate_render_tree, RenderTree, RenderTreeNode};
-use lazy_static::lazy_static;
+use gosub_styling::render_tree::{RenderTree, RenderTreeNode};
 use url::Url;
 use vello::kurbo::{Affine, Rect, Stroke};
 use vello::kurbo::RoundedRect;
@@ -18,14 +8,12 @@ use vello::peniko::Color;
 use vello::peniko::Fill;
 use vello::Scene;
 
-use gosub_rendering::text::TextRenderer;
-use gosub_rendering::WindowState;
-use gosub_rendering::image::ImageCache;
-
-
-lazy_static! {
-    static ref IMAGE_CACHE: Mutex<ImageCache> = Mutex::new(ImageCache::default());
-}
+use gosub_rendering_poc::text::TextRenderer;
+use gosub_rendering_poc::WindowState;
+use gosub_rendering_poc::color::parse_color;
+use gosub_rendering_poc::image::IMAGE_CACHE;
+use gosub_rendering_poc::loader::load_html_rendertree;
+use gosub_rendering_poc::units::LengthContext;
 
 fn main() -> anyhow::Result<()> {
     let args = clap::Command::new("Gosub Rendering Test")
@@ -38,14 +26,14 @@ fn main() -> anyhow::Result<()> {
         )
         .get_matches();
 
-    let url = args.get_one::<String>("url").unwrap();
-    let mut render_tree = load_html_rendertree(url)?;
+    let url = Url::parse(args.get_one::<String>("url").unwrap())?;
+    let mut render_tree = load_html_rendertree(&url)?;
 
     calculate_styles(&mut render_tree);
 
     println!("RT: {:#?}", render_tree);
 
-    let mut render_scene = |scene: &mut Scene, size: (usize, usize)| render_render_tree(scene, size, &render_tree);
+    let mut render_scene = |scene: &mut Scene, size: (usize, usize), _scale_factor: f64| render_render_tree(scene, size, &render_tree);
 
     let window = WindowState::new(&mut render_scene)?;
 
@@ -54,35 +42,6 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-fn load_html_rendertree(str_url: &str) -> anyhow::Result<RenderTree> {
-    let url = Url::parse(str_url)?;
-    let html = if url.scheme() == "http" || url.scheme() == "https" {
-        // Fetch the html from the url
-        let response = ureq::get(url.as_ref()).call()?;
-        if response.status() != 200 {
-            bail!(format!(
-                "Could not get url. Status code {}",
-                response.status()
-            ));
-        }
-        response.into_string()?
-    } else if url.scheme() == "file" {
-        fs::read_to_string(str_url.trim_start_matches("file://"))?
-    } else {
-        bail!("Unsupported url scheme: {}", url.scheme());
-    };
-
-    let mut chars = CharIterator::new();
-    chars.read_from_str(&html, Some(Encoding::UTF8));
-    chars.set_confidence(Confidence::Certain);
-
-    let doc_handle = DocumentBuilder::new_document(Some(url));
-    let _parse_errors =
-        Html5Parser::parse_document(&mut chars, Document::clone(&doc_handle), None)?;
-
-    generate_render_tree(Document::clone(&doc_handle))
-}
-
 fn render_render_tree(scene: &mut Scene, size: (usize, usize), render_tree: &RenderTree) {
     let bg = Rect::new(0.0, 0.0, size.0 as f64, size.1 as f64);
     scene.fill(Fill::NonZero, Affine::IDENTITY, Color::BLACK, None, &bg);
@@ -114,6 +73,8 @@ fn render_with_children(id: NodeId, render_tree: &RenderTree, scene: &mut Scene,
 
 
 fn render_node(id: NodeId, node: &RenderTreeNode, render_tree: &RenderTree, scene: &mut Scene, size: (usize, usize), mut parent_pos: (f64, f64), parent_id: NodeId) -> (f64, f64) {
+    let lengths = LengthContext::new((size.0 as f64, size.1 as f64));
+
     if let NodeData::Text(text) = &node.data {
         let text = &text.value;
 
@@ -138,11 +99,7 @@ fn render_node(id: NodeId, node: &RenderTreeNode, render_tree: &RenderTree, scen
             // prop.compute_value();
 
             fs = if let CssValue::String(fs) = prop.actual {
-                if fs.ends_with("px") {
-                    fs.trim_end_matches("px").parse::<f32>().unwrap_or(12.0)
-                } else {
-                    12.0
-                }
+                lengths.resolve_font_size(&fs).unwrap_or(12.0) as f32
             } else {
                 12.0
             };
@@ -153,21 +110,15 @@ fn render_node(id: NodeId, node: &RenderTreeNode, render_tree: &RenderTree, scen
 
         let renderer = TextRenderer::new(ff, fs);
 
-        let color;
+        let mut color = Color::BLACK;
 
         if let Some(mut prop) = render_tree.get_property(parent_id, "color") {
             // prop.compute_value();
 
-            color = if let CssValue::String(color) = prop.actual {
-                RgbColor::from(color.as_str())
-            } else {
-                RgbColor::new(255, 255, 255, 255)
-            };
-        } else {
-            color = RgbColor::new(255, 255, 255, 255)
-        };
-
-        let color = Color::rgba8(color.r, color.g, color.b, color.a);
+            if let CssValue::String(val) = prop.actual {
+                color = parse_color(&val, color).unwrap_or(color);
+            }
+        }
 
         parent_pos.1 += renderer.line_height as f64;
 
@@ -197,36 +148,28 @@ fn render_node(id: NodeId, node: &RenderTreeNode, render_tree: &RenderTree, scen
     if let Some(mut prop) = render_tree.get_property(id, "top") {
         // prop.compute_value();
         if let CssValue::String(val) = prop.actual {
-            if val.ends_with("px") {
-                top = val.trim_end_matches("px").parse().unwrap();
-            }
+            top = lengths.resolve_y(&val).unwrap_or(top);
         };
     };
 
     if let Some(mut prop) = render_tree.get_property(id, "left") {
         // prop.compute_value();
         if let CssValue::String(val) = prop.actual {
-            if val.ends_with("px") {
-                left = val.trim_end_matches("px").parse().unwrap();
-            }
+            left = lengths.resolve_x(&val).unwrap_or(left);
         };
     };
 
     if let Some(mut prop) = render_tree.get_property(id, "right") {
         // prop.compute_value();
         if let CssValue::String(val) = prop.actual {
-            if val.ends_with("px") {
-                right = val.trim_end_matches("px").parse().unwrap();
-            }
+            right = lengths.resolve_x(&val).unwrap_or(right);
         };
     };
 
     if let Some(mut prop) = render_tree.get_property(id, "bottom") {
         // prop.compute_value();
         if let CssValue::String(val) = prop.actual {
-            if val.ends_with("px") {
-                bottom = val.trim_end_matches("px").parse().unwrap();
-            }
+            bottom = lengths.resolve_y(&val).unwrap_or(bottom);
         };
     };
 
@@ -248,29 +191,24 @@ fn render_node(id: NodeId, node: &RenderTreeNode, render_tree: &RenderTree, scen
     if let Some(mut prop) = render_tree.get_property(id, "width") {
         // prop.compute_value();
         if let CssValue::String(val) = prop.actual {
-            if val.ends_with("px") {
-                width = val.trim_end_matches("px").parse().unwrap();
-            }
+            width = lengths.resolve_x(&val).unwrap_or(width);
         };
     };
 
     if let Some(mut prop) = render_tree.get_property(id, "height") {
         // prop.compute_value();
         if let CssValue::String(val) = prop.actual {
-            if val.ends_with("px") {
-                height = val.trim_end_matches("px").parse().unwrap();
-            }
+            height = lengths.resolve_y(&val).unwrap_or(height);
         };
     };
 
-    let mut color = RgbColor::new(0, 0, 0, 0);
+    let mut color = Color::TRANSPARENT;
 
     if let Some(mut prop) = render_tree
         .get_property(id, "background-color") {
         // prop.compute_value();
         if let CssValue::String(clr) = prop.actual {
-            let clr = RgbColor::from(clr.as_str());
-            color = clr;
+            color = parse_color(&clr, Color::BLACK).unwrap_or(color);
         }
     }
 
@@ -280,13 +218,7 @@ fn render_node(id: NodeId, node: &RenderTreeNode, render_tree: &RenderTree, scen
         // prop.compute_value();
         if let CssValue::String(val) = prop.actual {
             let val = val.split(' ');
-            let mut vals = val.map(|v| {
-                if v.ends_with("px") {
-                    v.trim_end_matches("px").parse::<f64>().unwrap()
-                } else {
-                    0.0
-                }
-            });
+            let mut vals = val.map(|v| lengths.resolve_x(v).unwrap_or(0.0));
             let top_left = vals.next().unwrap_or(0.0);
             let top_right = vals.next().unwrap_or(top_left);
             let bottom_right = vals.next().unwrap_or(top_left);
@@ -296,7 +228,6 @@ fn render_node(id: NodeId, node: &RenderTreeNode, render_tree: &RenderTree, scen
         };
     }
 
-    let color = Color::rgba8(color.r, color.g, color.b, color.a);
     if width == 0.0 || height == 0.0 {
         return parent_pos;
     }
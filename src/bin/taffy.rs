@@ -1,31 +1,46 @@
-use std::fs;
-use std::sync::Mutex;
+use std::cell::{OnceCell, RefCell};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::time::Instant;
 
-use anyhow::bail;
+use arboard::Clipboard;
 use gosub_html5::node::NodeId;
-use gosub_html5::parser::document::{Document, DocumentBuilder};
-use gosub_html5::parser::Html5Parser;
-use gosub_rendering::layout::generate_taffy_tree;
-use gosub_shared::bytes::{CharIterator, Confidence, Encoding};
-use gosub_styling::css_colors::RgbColor;
-use gosub_styling::css_values::CssValue;
-use gosub_styling::render_tree::{generate_render_tree, RenderNodeData, RenderTree};
-use lazy_static::lazy_static;
-use taffy::{AvailableSpace, NodeId as TaffyID, PrintTree, Size, TaffyTree, TraversePartialTree};
+use gosub_styling::render_tree::{RenderNodeData, RenderTree};
+use taffy::{Layout, NodeId as TaffyID, PrintTree};
 use url::Url;
-use vello::kurbo::{Affine, Rect, RoundedRect};
-use vello::peniko::{Color, Fill};
+use vello::kurbo::{Affine, Rect, RoundedRect, RoundedRectRadii, Vec2};
+use vello::peniko::{BlendMode, Color, Compose, Fill, Mix};
 use vello::Scene;
 
-use gosub_rendering_poc::image::ImageCache;
-use gosub_rendering_poc::text::TextRenderer;
-use gosub_rendering_poc::WindowState;
+use gosub_rendering_poc::compositing::{Compositing, UNBOUNDED};
+use gosub_rendering_poc::cursor::{Cursor, CursorIcon};
+use gosub_rendering_poc::image::IMAGE_CACHE;
+use gosub_rendering_poc::find::{Find, FindOptions};
+use gosub_rendering_poc::input::{ButtonState, InputEvent, Key, KeyCode, KeyEvent, MouseButton, NamedKey, PhysicalKey};
+use gosub_rendering_poc::layout::{LayoutDriver, Viewport};
+use gosub_rendering_poc::page::Page;
+use gosub_rendering_poc::overflow::{draw_scrollbars, ScrollOffsets};
+use gosub_rendering_poc::scroll::draw_document_scrollbars;
+use gosub_rendering_poc::selection::highlight_rects;
+use gosub_rendering_poc::shadow::{blur_kernel, draw_box_shadows, draw_inset_shadows};
+use gosub_rendering_poc::stacking::{Clip, PaintItem, StackingContext};
+use gosub_rendering_poc::text::{resolve_font_families, TextRenderer};
+use gosub_rendering_poc::{RedrawHandle, WindowState};
 use gosub_rendering_poc::tree::print_tree;
 
-lazy_static! {
-    static ref IMAGE_CACHE: Mutex<ImageCache> = Mutex::new(ImageCache::default());
-}
+/// Background of the find matches, the current one stands out in orange
+const MATCH_COLOR: Color = Color::rgb8(0xff, 0xff, 0x00);
+const CURRENT_MATCH_COLOR: Color = Color::rgb8(0xff, 0x96, 0x32);
+
+const FIND_BAR_HEIGHT: f64 = 32.0;
 
+/// The find bar along the bottom of the window, it takes the typed text while it is open
+#[derive(Debug, Default)]
+struct FindBar {
+    open: bool,
+    query: String,
+    options: FindOptions,
+}
 
 fn main() -> anyhow::Result<()> {
     let args = clap::Command::new("Gosub Rendering Test")
@@ -36,95 +51,409 @@ fn main() -> anyhow::Result<()> {
                 .required(true)
                 .index(1),
         )
+        .arg(
+            clap::Arg::new("smooth-scrolling")
+                .long("smooth-scrolling")
+                .help("Animate scrolling with the keyboard, even if the page doesn't ask for it")
+                .action(clap::ArgAction::SetTrue),
+        )
         .get_matches();
 
-    let url = args.get_one::<String>("url").unwrap();
+    let url = Url::parse(args.get_one::<String>("url").unwrap())?;
 
-    let mut render_tree = load_html_rendertree(url)?;
+    let mut page = Page::load(url)?;
+    page.document_scroll.smooth = args.get_flag("smooth-scrolling");
+    page.update(Viewport::from_logical((1920.0, 1080.0), 1.0));
 
-    let (mut taffy_tree, root) = generate_taffy_tree(&mut render_tree)?;
+    print_tree(page.driver().tree(), page.driver().root(), page.render_tree());
 
-    taffy_tree.compute_layout(root, Size {
-        width: AvailableSpace::Definite(1920.0),
-        height: AvailableSpace::Definite(1080.0),
-    }).expect("Failed to compute layout");
+    let page = RefCell::new(page);
+    let find_bar = RefCell::new(FindBar::default());
+    let redraw: OnceCell<RedrawHandle> = OnceCell::new();
 
+    let mut render_scene = |scene: &mut Scene, size: (usize, usize), scale_factor: f64| {
+        let mut page = page.borrow_mut();
 
-    print_tree(&taffy_tree, root, &render_tree);
+        // only lays out again when the window size actually changed
+        page.update(Viewport::from_physical(size, scale_factor));
 
-    // return Ok(());
+        if page.document_scroll.tick(Instant::now()) {
+            if let Some(redraw) = redraw.get() {
+                redraw.request_redraw();
+            }
+        }
 
+        let highlights = Highlights {
+            selection: page.selection_highlights(),
+            matches: page.find_highlights(),
+        };
+        // css pixels to device pixels, with the page and pinch zoom
+        let window = Affine::scale(scale_factor);
+        let transform = window * page.css_to_window();
+        render_render_tree(scene, page.render_tree(), page.driver(), &page.scroll, page.document_scroll.offset(), &highlights, transform);
+
+        // the scrollbars are zoomed with the page but not magnified by pinching
+        draw_document_scrollbars(scene, window * Affine::scale(page.zoom()), &page.document_scroll);
+
+        let bar = find_bar.borrow();
+        if bar.open {
+            let size = (size.0 as f64 / scale_factor, size.1 as f64 / scale_factor);
+            draw_find_bar(scene, window, size, &bar, page.find_state());
+        }
 
-    let last_size = (0, 0);
-    let mut render_scene = |scene: &mut Scene, size: (usize, usize)| {
-        if size != last_size {
-            let size = Size {
-                width: AvailableSpace::Definite(size.0 as f32),
-                height: AvailableSpace::Definite(size.1 as f32),
-            };
-            taffy_tree.compute_layout(root, size).expect("Failed to compute layout");
+        // winit can't show custom cursors, so they are painted on top of everything
+        if let (Some(pointer), Cursor::Image { image, hotspot }) = (page.pointer(), page.cursor()) {
+            let pointer = page.css_to_window() * pointer;
+            scene.draw_image(image, window * Affine::translate(pointer.to_vec2() - *hotspot));
         }
-        render_render_tree(scene, size, &render_tree, &taffy_tree, root);
     };
 
-    let window = WindowState::new(&mut render_scene)?;
+    let mut window = WindowState::new(&mut render_scene)?;
+    let _ = redraw.set(window.redraw_handle());
+
+    let cursor = window.cursor_handle();
+    let mut shown_cursor = Some(CursorIcon::Default);
+    // on some platforms the copied text is only available while the clipboard is alive
+    let mut clipboard = Clipboard::new()
+        .map_err(|e| eprintln!("Failed to open the clipboard: {e:?}"))
+        .ok();
+    let page_cell = &page;
+    let find_bar_cell = &find_bar;
+
+    window.set_input_handler(move |event: &InputEvent| {
+        let mut page = page_cell.borrow_mut();
+        let changed = handle_input(&mut page, &mut find_bar_cell.borrow_mut(), clipboard.as_mut(), event);
+
+        let icon = page.cursor().system_icon();
+        if icon != shown_cursor {
+            shown_cursor = icon;
+            cursor.set_cursor(icon);
+        }
+
+        changed
+    });
 
     window.start()?;
 
     Ok(())
 }
 
+/// Handles the input of the window, returns whether the page has to be redrawn
+fn handle_input(page: &mut Page, find_bar: &mut FindBar, clipboard: Option<&mut Clipboard>, event: &InputEvent) -> bool {
+    if let InputEvent::Key(key) = event {
+        if let Some(redraw) = handle_find_key(page, find_bar, key) {
+            return redraw;
+        }
+    }
+
+    if let Some(redraw) = handle_zoom(page, event) {
+        return redraw;
+    }
+
+    // the page works in css pixels, which are zoomed
+    let event = &event.transformed(page.css_to_window().inverse());
+
+    match event {
+        InputEvent::Wheel { delta, position, .. } => {
+            if !page.scroll_at(*position, *delta) {
+                return false;
+            }
+
+            // the content under the pointer moved
+            page.pointer_moved(*position);
+            true
+        }
+        InputEvent::CursorMoved { position } => page.pointer_moved(*position),
+        InputEvent::CursorLeft => page.pointer_left(),
+        InputEvent::MouseButton { button: MouseButton::Left, state: ButtonState::Pressed, position, .. } => {
+            page.pointer_pressed(*position)
+        }
+        InputEvent::MouseButton { button: MouseButton::Left, state: ButtonState::Released, position, .. } => {
+            let released = page.pointer_released();
+
+            // selecting the text of a link doesn't follow it
+            if page.selected_text().is_some() {
+                return released;
+            }
+
+            match page.link_at(*position) {
+                Some(url) => navigate(page, url) || released,
+                None => released,
+            }
+        }
+        InputEvent::Key(key) if key.state == ButtonState::Pressed && key.modifiers.shortcut() => match key.code {
+            PhysicalKey::Code(KeyCode::KeyA) => page.select_all(),
+            PhysicalKey::Code(KeyCode::KeyC) => {
+                if let (Some(text), Some(clipboard)) = (page.selected_text(), clipboard) {
+                    if let Err(e) = clipboard.set_text(text) {
+                        eprintln!("Failed to copy the selection: {e:?}");
+                    }
+                }
+
+                false
+            }
+            _ => page.handle_key(key),
+        },
+        InputEvent::Key(key) if key.state == ButtonState::Pressed && key.modifiers.alt => {
+            let result = match &key.key {
+                Key::Named(NamedKey::ArrowLeft) => page.back(),
+                Key::Named(NamedKey::ArrowRight) => page.forward(),
+                _ => return false,
+            };
+
+            result.unwrap_or_else(|e| {
+                eprintln!("Failed to load history entry: {e:?}");
+                false
+            })
+        }
+        InputEvent::Key(key) if key.state == ButtonState::Pressed && key.key == Key::Named(NamedKey::Tab) => {
+            page.focus_next(key.modifiers.shift)
+        }
+        InputEvent::Key(key) if key.state == ButtonState::Pressed && key.key == Key::Named(NamedKey::Enter) => {
+            match page.focused_link() {
+                Some(url) => navigate(page, url),
+                None => false,
+            }
+        }
+        InputEvent::Key(key) => page.handle_key(key),
+        _ => false,
+    }
+}
+
+/// Ctrl+Plus and Ctrl+Minus or Ctrl+wheel zoom the page, Ctrl+0 resets the zoom and pinching
+/// magnifies the page without laying it out again. Returns `None` for input that doesn't zoom.
+fn handle_zoom(page: &mut Page, event: &InputEvent) -> Option<bool> {
+    match event {
+        InputEvent::Wheel { delta, modifiers, .. } if modifiers.shortcut() => {
+            Some(delta.y != 0.0 && page.step_zoom(delta.y > 0.0))
+        }
+        InputEvent::Pinch { scale, position } => Some(page.pinch(*position, *scale)),
+        InputEvent::Key(key) if key.state == ButtonState::Pressed && key.modifiers.shortcut() => {
+            let zoom_key = match &key.key {
+                Key::Character(c) => c.as_str(),
+                _ => "",
+            };
+
+            match (zoom_key, key.code) {
+                ("+" | "=", _) | (_, PhysicalKey::Code(KeyCode::NumpadAdd)) => Some(page.step_zoom(false)),
+                ("-", _) | (_, PhysicalKey::Code(KeyCode::NumpadSubtract)) => Some(page.step_zoom(true)),
+                ("0", _) | (_, PhysicalKey::Code(KeyCode::Numpad0)) => Some(page.reset_zoom()),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Handles the keys of find in page: Ctrl+F opens the find bar, F3 and Enter go to the next match
+/// and Shift+F3 and Shift+Enter to the previous one. While the bar is open typed text goes into the
+/// query, Alt+C toggles matching case and Alt+D matching accents. Returns `None` for keys that are
+/// not for the find bar.
+fn handle_find_key(page: &mut Page, bar: &mut FindBar, key: &KeyEvent) -> Option<bool> {
+    if key.state != ButtonState::Pressed {
+        return None;
+    }
+
+    if key.modifiers.shortcut() && key.code == PhysicalKey::Code(KeyCode::KeyF) {
+        let opened = !bar.open;
+        bar.open = true;
+        return Some(opened);
+    }
+
+    if key.key == Key::Named(NamedKey::F3) {
+        if bar.query.is_empty() {
+            bar.open = true;
+            return Some(true);
+        }
+
+        return Some(page.find_next(key.modifiers.shift));
+    }
+
+    if !bar.open {
+        return None;
+    }
+
+    match &key.key {
+        Key::Named(NamedKey::Escape) => {
+            bar.open = false;
+            page.stop_finding();
+            Some(true)
+        }
+        Key::Named(NamedKey::Enter) => Some(page.find_next(key.modifiers.shift)),
+        Key::Named(NamedKey::Backspace) => {
+            bar.query.pop();
+            page.find(&bar.query, bar.options);
+            Some(true)
+        }
+        _ if key.modifiers.alt => {
+            match key.code {
+                PhysicalKey::Code(KeyCode::KeyC) => bar.options.match_case = !bar.options.match_case,
+                PhysicalKey::Code(KeyCode::KeyD) => bar.options.match_diacritics = !bar.options.match_diacritics,
+                _ => return None,
+            }
+
+            page.find(&bar.query, bar.options);
+            Some(true)
+        }
+        _ if key.modifiers.shortcut() => None,
+        _ => {
+            let text = key.text.as_deref().filter(|t| !t.chars().any(char::is_control))?;
 
-fn load_html_rendertree(str_url: &str) -> anyhow::Result<RenderTree> {
-    let url = Url::parse(str_url)?;
-    let html = if url.scheme() == "http" || url.scheme() == "https" {
-        // Fetch the html from the url
-        let response = ureq::get(url.as_ref()).call()?;
-        if response.status() != 200 {
-            bail!(format!(
-                "Could not get url. Status code {}",
-                response.status()
-            ));
+            bar.query.push_str(text);
+            page.find(&bar.query, bar.options);
+            Some(true)
         }
-        response.into_string()?
-    } else if url.scheme() == "file" {
-        fs::read_to_string(str_url.trim_start_matches("file://"))?
-    } else {
-        bail!("Unsupported url scheme: {}", url.scheme());
+    }
+}
+
+fn draw_find_bar(scene: &mut Scene, transform: Affine, viewport: (f64, f64), bar: &FindBar, find: &Find) {
+    let top = viewport.1 - FIND_BAR_HEIGHT;
+    scene.fill(Fill::NonZero, transform, Color::rgb8(0x30, 0x30, 0x30), None, &Rect::new(0.0, top, viewport.0, viewport.1));
+
+    let status = match (find.current_index(), find.matches().len()) {
+        (Some(current), count) => format!("{} of {count}", current + 1),
+        _ if !bar.query.is_empty() => String::from("no matches"),
+        _ => String::new(),
     };
 
-    let mut chars = CharIterator::new();
-    chars.read_from_str(&html, Some(Encoding::UTF8));
-    chars.set_confidence(Confidence::Certain);
+    let mut label = format!("Find: {}_   {status}", bar.query);
+    if bar.options.match_case {
+        label.push_str("   [match case]");
+    }
+    if bar.options.match_diacritics {
+        label.push_str("   [match accents]");
+    }
 
-    let doc_handle = DocumentBuilder::new_document(Some(url));
-    let _parse_errors =
-        Html5Parser::parse_document(&mut chars, Document::clone(&doc_handle), None)?;
+    let renderer = TextRenderer::new(resolve_font_families("sans-serif"), 16.0);
+    renderer.render_text(&label, scene, Color::WHITE, transform * Affine::translate((8.0, top + 21.0)), Fill::NonZero, None);
+}
 
-    generate_render_tree(Document::clone(&doc_handle))
+/// Loads a link target, returns whether the page changed
+fn navigate(page: &mut Page, url: Url) -> bool {
+    if let Err(e) = page.navigate(url.clone()) {
+        eprintln!("Failed to load {url}: {e:?}");
+        return false;
+    }
+
+    true
+}
+
+
+/// The parts of text nodes that are painted highlighted
+struct Highlights {
+    /// The selected byte range of the text nodes that are part of the selection
+    selection: HashMap<NodeId, Range<usize>>,
+    /// The find matches in each text node, with whether it is the current match
+    matches: HashMap<NodeId, Vec<(Range<usize>, bool)>>,
 }
 
+/// Paints the document, `transform` maps css pixels of the viewport to device pixels
+fn render_render_tree(scene: &mut Scene, render_tree: &RenderTree, driver: &LayoutDriver, scroll: &ScrollOffsets, document_scroll: Vec2, selection: &Highlights, transform: Affine) {
+    let Some(viewport) = driver.viewport() else {
+        return;
+    };
+
+    let (layout, styles, root) = (driver.tree(), driver.styles(), driver.root());
+
+    let bg = Rect::new(0.0, 0.0, viewport.width, viewport.height);
+    scene.fill(Fill::NonZero, transform, Color::BLACK, None, &bg);
 
-fn render_render_tree(scene: &mut Scene, size: (usize, usize), render_tree: &RenderTree, layout: &TaffyTree<NodeId>, root: TaffyID) {
-    let bg = Rect::new(0.0, 0.0, size.0 as f64, size.1 as f64);
-    scene.fill(Fill::NonZero, Affine::IDENTITY, Color::BLACK, None, &bg);
+    // scrolling the document moves everything below the root
+    let transform = transform * Affine::translate(-document_scroll);
 
-    render_with_children(root, render_tree, layout, scene, (0.0,0.0));
+    let Some(context) = StackingContext::build(layout, render_tree, styles, root, scroll, transform) else {
+        eprintln!("Failed to build stacking context for the root node");
+        return;
+    };
+
+    paint_context(&context, render_tree, driver, selection, scene, 0);
 }
 
-fn render_with_children(id: TaffyID, render_tree: &RenderTree, layout: &TaffyTree<NodeId>, scene: &mut Scene, mut pos: (f64, f64)) {
-    let err = render_node(id, render_tree, layout, scene, &mut pos);
-    if let Err(e) = err {
+/// Paints a stacking context in the order of https://www.w3.org/TR/CSS2/zindex.html.
+/// `depth` is the number of clips that are already pushed by the enclosing contexts.
+fn paint_context(context: &StackingContext, render_tree: &RenderTree, driver: &LayoutDriver, selection: &Highlights, scene: &mut Scene, depth: usize) {
+    let styles = driver.styles();
+    let compositing = match styles.get(context.root.gosub_id) {
+        Some(style) if context.atomic => style.compositing,
+        _ => Compositing::default(),
+    };
+
+    if compositing.is_invisible() {
+        return;
+    }
+
+    let clips = push_clips(scene, &context.root.clips[depth..]);
+    let pushed = compositing.push(scene, Affine::IDENTITY, &UNBOUNDED);
+
+    let mut pos = context.root.origin;
+    if let Err(e) = render_node(context.root.id, render_tree, driver, selection, scene, &mut pos, context.root.transform) {
         eprintln!("Error rendering node: {:?}", e);
     }
 
-    for child in layout.child_ids(id) {
-        render_with_children(child, render_tree, layout, scene, pos);
+    let depth = context.root.clips.len();
+    for item in context.items() {
+        paint_item(item, render_tree, driver, selection, scene, depth);
+    }
+
+    compositing.pop(scene, pushed);
+    pop_clips(scene, clips);
+}
+
+fn paint_item(item: &PaintItem, render_tree: &RenderTree, driver: &LayoutDriver, selection: &Highlights, scene: &mut Scene, depth: usize) {
+    let (styles, layout) = (driver.styles(), driver.tree());
+    match item {
+        PaintItem::Node(node) => {
+            let clips = push_clips(scene, &node.clips[depth..]);
+
+            let mut pos = node.origin;
+            if let Err(e) = render_node(node.id, render_tree, driver, selection, scene, &mut pos, node.transform) {
+                eprintln!("Error rendering node: {:?}", e);
+            }
+
+            pop_clips(scene, clips);
+        }
+        PaintItem::Scrollbars(node, offset) => {
+            let clips = push_clips(scene, &node.clips[depth..]);
+
+            let node_layout = layout.get_final_layout(node.id);
+            let padding_box = padding_box(node_layout, node.position(layout));
+            let overflow = styles.get(node.gosub_id).map(|s| s.overflow).unwrap_or_default();
+
+            draw_scrollbars(scene, node.transform, padding_box, node_layout, &overflow, *offset);
+
+            pop_clips(scene, clips);
+        }
+        PaintItem::Context(context) => paint_context(context, render_tree, driver, selection, scene, depth),
+    }
+}
+
+fn push_clips(scene: &mut Scene, clips: &[Clip]) -> usize {
+    for clip in clips {
+        scene.push_layer(Mix::Clip, 1.0, clip.transform, &clip.shape);
     }
+
+    clips.len()
 }
 
+fn pop_clips(scene: &mut Scene, count: usize) {
+    for _ in 0..count {
+        scene.pop_layer();
+    }
+}
 
-fn render_node(id: TaffyID, render_tree: &RenderTree, layout: &TaffyTree<NodeId>, scene: &mut Scene, pos: &mut (f64, f64)) -> anyhow::Result<()> {
+fn padding_box(node_layout: &Layout, pos: (f64, f64)) -> Rect {
+    let border = node_layout.border;
+    Rect::new(
+        pos.0 + border.left as f64,
+        pos.1 + border.top as f64,
+        pos.0 + (node_layout.size.width - border.right) as f64,
+        pos.1 + (node_layout.size.height - border.bottom) as f64,
+    )
+}
+
+
+fn render_node(id: TaffyID, render_tree: &RenderTree, driver: &LayoutDriver, selection: &Highlights, scene: &mut Scene, pos: &mut (f64, f64), transform: Affine) -> anyhow::Result<()> {
+    let (styles, layout) = (driver.styles(), driver.tree());
     let Some(gosub_id) = layout.get_node_context(id) else {
         return Err(anyhow::anyhow!("Node context not found"));
     };
@@ -138,92 +467,82 @@ fn render_node(id: TaffyID, render_tree: &RenderTree, layout: &TaffyTree<NodeId>
 
     let node = render_tree.get_node(gosub_id).unwrap();
     if let RenderNodeData::Text(text) = &node.data {
-        let Some(parent) = layout.parent(id) else {
-            return Ok(())
-        };
-
-        let gosub_id = *layout.get_node_context(parent).unwrap();
-
-        let ff;
-        
-        if let Some(mut prop) = render_tree.get_property(gosub_id, "font-family") {
-            prop.compute_value();
-            ff = if let CssValue::String(font_family) = prop.actual {
-                font_family
-            } else {
-                String::from("D050000L")
-            };
-        } else {
-            ff = String::from("C059")
+        // text nodes inherit everything from their parent element
+        let Some(style) = styles.get(gosub_id) else {
+            return Err(anyhow::anyhow!("No computed style for text node"));
         };
 
-        let ff = ff.trim().split(',').map(|ff| ff.to_string()).collect::<Vec<String>>();
-
+        let renderer = TextRenderer::new(style.font.families.clone(), style.font.size as f32);
 
-        let fs;
+        let color = style.color;
 
+        let affine = transform * Affine::translate((
+            pos.0,
+            pos.1,
+        ));
 
-        if let Some(mut prop) = render_tree.get_property(gosub_id, "font-size") {
-            prop.compute_value();
-            fs = if let CssValue::String(fs) = prop.actual {
-                if fs.ends_with("px") {
-                    fs.trim_end_matches("px").parse::<f32>().unwrap_or(12.0)
-                } else {
-                    12.0
+        // the first shadow is the topmost one, so paint them back to front
+        for shadow in style.text_shadows.iter().rev() {
+            let offset = affine * Affine::translate((shadow.offset_x, shadow.offset_y));
+            let kernel = blur_kernel(shadow.blur);
+
+            // the samples of a blur are added up so their weights sum to the shadow color, painted
+            // over each other they would only reach part of its alpha. Glyphs can reach a bit
+            // outside of the box of the text, the layers leave them room.
+            let blurred = kernel.len() > 1;
+            let spread = shadow.blur * 2.0 + style.font.size;
+            let bounds = Rect::new(0.0, 0.0, node_layout.size.width as f64, node_layout.size.height as f64).inflate(spread, spread);
+
+            if blurred {
+                scene.push_layer(Mix::Normal, 1.0, offset, &bounds);
+            }
+
+            for ((dx, dy), weight) in kernel {
+                if blurred {
+                    scene.push_layer(BlendMode::new(Mix::Normal, Compose::Plus), 1.0, offset, &bounds);
                 }
-            } else {
-                12.0
-            };
-        } else {
-            fs = 12.0
-        };
 
+                renderer.show_text(text, scene, shadow.color.with_alpha_factor(weight), offset * Affine::translate((dx, dy)), Fill::NonZero, None);
 
-        let renderer = TextRenderer::new(ff, fs);
-
-        let color;
-
-        if let Some(mut prop) = render_tree.get_property(gosub_id, "color") {
-            prop.compute_value();
+                if blurred {
+                    scene.pop_layer();
+                }
+            }
 
-            color = if let CssValue::String(color) = prop.actual {
-                RgbColor::from(color.as_str())
-            } else {
-                RgbColor::new(255.0, 0.0, 0.0, 255.0)
-            };
-        } else {
-            color = RgbColor::new(0.0, 255.0, 0.0, 255.0)
-        };
+            if blurred {
+                scene.pop_layer();
+            }
+        }
 
-        let color = Color::rgba8(color.r as u8, color.g as u8, color.b as u8, color.a as u8);
+        for (range, current) in selection.matches.get(&gosub_id).into_iter().flatten() {
+            let color = if *current { CURRENT_MATCH_COLOR } else { MATCH_COLOR };
+            for rect in highlight_rects(text, &renderer, range) {
+                scene.fill(Fill::NonZero, affine, color, None, &rect);
+            }
+        }
 
-        let affine = Affine::translate((
-            pos.0,
-            pos.1,
-        ));
+        let highlights = selection.selection.get(&gosub_id).map(|range| highlight_rects(text, &renderer, range)).unwrap_or_default();
+        for rect in highlights.iter() {
+            scene.fill(Fill::NonZero, affine, style.selection.background, None, rect);
+        }
 
         renderer.show_text(text, scene, color, affine, Fill::NonZero, None);
-        return Ok(());
-    }
-
-    let mut color = RgbColor::new(0.0, 0.0, 0.0, 0.0);
 
-    if let Some(mut prop) = render_tree
-        .get_property(gosub_id, "background-color") {
-        // prop.compute_value();
-        if let CssValue::String(clr) = prop.actual {
-            let clr = RgbColor::from(clr.as_str());
-            color = clr;
+        // the selected glyphs are painted again in the selection color, clipped to the highlight
+        for rect in highlights.iter() {
+            scene.push_layer(Mix::Clip, 1.0, affine, rect);
+            renderer.show_text(text, scene, style.selection.color, affine, Fill::NonZero, None);
+            scene.pop_layer();
         }
-    }
 
-    let mut border_radius = 0.0;
+        return Ok(());
+    }
 
-    if let Some(mut prop) = render_tree.get_property(gosub_id, "border-radius") {
-        border_radius = prop.actual.unit_to_px() as f64;
+    let Some(style) = styles.get(gosub_id) else {
+        return Err(anyhow::anyhow!("No computed style for element"));
     };
 
-    let color = Color::rgba8(color.r as u8, color.g as u8, color.b as u8, color.a as u8);
+    let border_radius = style.border_radius(node_layout.size.width as f64);
 
     if let RenderNodeData::Element(e) = &node.data {
         if e.name == "img" {
@@ -242,19 +561,45 @@ fn render_node(id: TaffyID, render_tree: &RenderTree, layout: &TaffyTree<NodeId>
             };
 
             scene.draw_image(&img,
-                             Affine::translate((pos.0, pos.1)) * Affine::scale((node_layout.size.width / img.width as f32) as f64),
+                             transform * Affine::translate((pos.0, pos.1)) * Affine::scale((node_layout.size.width / img.width as f32) as f64),
             );
 
             return Ok(());
         }
     }
 
+    // inline elements get a box on every line they are on, only the first and last are rounded at
+    // their start and end
+    if let Some(fragments) = driver.inline_fragments(gosub_id) {
+        for fragment in fragments {
+            let rect = fragment.rect + Vec2::new(pos.0, pos.1);
+            let start = if fragment.first { border_radius } else { 0.0 };
+            let end = if fragment.last { border_radius } else { 0.0 };
+
+            draw_box_shadows(scene, transform, rect, start.min(end), &style.box_shadows);
+
+            let rect = RoundedRect::from_rect(rect, RoundedRectRadii::new(start, end, end, start));
+            scene.fill(Fill::NonZero, transform, style.background_color, None, &rect);
+        }
+
+        return Ok(());
+    }
+
     let x2 = node_layout.size.width as f64 + pos.0;
     let y2 = node_layout.size.height as f64 + pos.1;
 
-    let rect = RoundedRect::new(pos.0, pos.1, x2, y2, border_radius);
+    let border_box = Rect::new(pos.0, pos.1, x2, y2);
+
+    draw_box_shadows(scene, transform, border_box, border_radius, &style.box_shadows);
+
+    let rect = RoundedRect::from_rect(border_box, border_radius);
+
+    scene.fill(Fill::NonZero, transform, style.background_color, None, &rect);
+
+    let padding_box = padding_box(node_layout, *pos);
+    let inner_radius = (border_radius - node_layout.border.left.max(node_layout.border.top) as f64).max(0.0);
 
-    scene.fill(Fill::NonZero, Affine::IDENTITY, color, None, &rect);
+    draw_inset_shadows(scene, transform, padding_box, inner_radius, &style.box_shadows);
 
     Ok(())
 }
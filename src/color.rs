@@ -0,0 +1,1148 @@
+//! Parsing of css colors as described in https://drafts.csswg.org/css-color-4/ and
+//! https://drafts.csswg.org/css-color-5/#color-mix. All colors are converted through CIE XYZ and
+//! gamut mapped into sRGB, which is the only color space we can render to.
+
+use std::f64::consts::PI;
+
+use vello::peniko::Color;
+
+use crate::css::{split_top_level, tokens};
+use crate::transform::parse_angle;
+
+type Matrix = [[f64; 3]; 3];
+
+/// The css named colors, see https://drafts.csswg.org/css-color-4/#named-colors
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+/// The system colors of a light color scheme, see https://drafts.csswg.org/css-color-4/#css-system-colors
+const SYSTEM_COLORS: &[(&str, u32)] = &[
+    ("accentcolor", 0x0060df),
+    ("accentcolortext", 0xffffff),
+    ("activetext", 0xee0000),
+    ("buttonborder", 0x767676),
+    ("buttonface", 0xefefef),
+    ("buttontext", 0x000000),
+    ("canvas", 0xffffff),
+    ("canvastext", 0x000000),
+    ("field", 0xffffff),
+    ("fieldtext", 0x000000),
+    ("graytext", 0x6d6d6d),
+    ("highlight", 0x3390ff),
+    ("highlighttext", 0xffffff),
+    ("linktext", 0x0000ee),
+    ("mark", 0xffff00),
+    ("marktext", 0x000000),
+    ("selecteditem", 0x3390ff),
+    ("selecteditemtext", 0xffffff),
+    ("visitedtext", 0x551a8b),
+];
+
+const SRGB_TO_XYZ: Matrix = [
+    [0.41239079926595934, 0.357584339383878, 0.1804807884018343],
+    [0.21263900587151027, 0.715168678767756, 0.07219231536073371],
+    [0.01933081871559182, 0.11919477979462598, 0.9505321522496607],
+];
+
+const XYZ_TO_SRGB: Matrix = [
+    [3.2409699419045226, -1.537383177570094, -0.4986107602930034],
+    [-0.9692436362808796, 1.8759675015077202, 0.04155505740717559],
+    [0.05563007969699366, -0.20397695888897652, 1.0569715142428786],
+];
+
+const P3_TO_XYZ: Matrix = [
+    [0.4865709486482162, 0.26566769316909306, 0.1982172852343625],
+    [0.2289745640697488, 0.6917385218365064, 0.079286914093745],
+    [0.0, 0.04511338185890264, 1.043944368900976],
+];
+
+const A98_TO_XYZ: Matrix = [
+    [0.5766690429101305, 0.1855582379065463, 0.1882286462349947],
+    [0.29734497525053605, 0.6273635662554661, 0.07529145849399788],
+    [0.02703136138641234, 0.07068885253582723, 0.9913375368376388],
+];
+
+/// ProPhoto RGB is relative to D50
+const PROPHOTO_TO_XYZ_D50: Matrix = [
+    [0.7977604896723027, 0.13518583717574031, 0.0313493495815248],
+    [0.2880711282292934, 0.7118432178101014, 0.00008565396060525902],
+    [0.0, 0.0, 0.8251046025104601],
+];
+
+const REC2020_TO_XYZ: Matrix = [
+    [0.6369580483012914, 0.14461690358620832, 0.1688809751641721],
+    [0.2627002120112671, 0.6779980715188708, 0.05930171646986196],
+    [0.0, 0.028072693049087428, 1.060985057710791],
+];
+
+/// Bradford chromatic adaptation between the D50 and D65 white points
+const D50_TO_D65: Matrix = [
+    [0.9554734527042182, -0.023098536874261423, 0.0632593086610217],
+    [-0.028369706963208136, 1.0099954580058226, 0.021041398966943008],
+    [0.012314001688319899, -0.020507696433477912, 1.3303659366080753],
+];
+
+const D65_TO_D50: Matrix = [
+    [1.0479298208405488, 0.022946793341019088, -0.05019222954313557],
+    [0.029627815688159344, 0.990434484573249, -0.01707382502938514],
+    [-0.009243058152591178, 0.015055144896577895, 0.7518742899580008],
+];
+
+const D50_WHITE: [f64; 3] = [0.3457 / 0.3585, 1.0, (1.0 - 0.3457 - 0.3585) / 0.3585];
+
+const XYZ_TO_LMS: Matrix = [
+    [0.8190224379967030, 0.3619062600528904, -0.1288737815209879],
+    [0.0329836539323885, 0.9292868615863434, 0.0361446663506424],
+    [0.0481771893596242, 0.2642395317527308, 0.6335478284694309],
+];
+
+const LMS_TO_XYZ: Matrix = [
+    [1.2268798758459243, -0.5578149944602171, 0.2813910456659647],
+    [-0.0405757452148008, 1.1122868032803170, -0.0717110580655164],
+    [-0.0763729366746601, -0.4214933324022432, 1.5869240198367816],
+];
+
+const LMS_TO_OKLAB: Matrix = [
+    [0.2104542683093140, 0.7936177747023054, -0.0040720430116193],
+    [1.9779985324311684, -2.4285922420485799, 0.4505937096174110],
+    [0.0259040424655478, 0.7827717124575296, -0.8086757549230774],
+];
+
+const OKLAB_TO_LMS: Matrix = [
+    [1.0, 0.3963377773761749, 0.2158037573099136],
+    [1.0, -0.1055613458156586, -0.0638541728258133],
+    [1.0, -0.0894841775298119, -1.2914855480194092],
+];
+
+/// Parses any css color into an sRGB color. `current_color` is the value of the `color` property
+/// that `currentColor` refers to. Colors outside of the sRGB gamut are gamut mapped.
+pub fn parse_color(value: &str, current_color: Color) -> Option<Color> {
+    let color = CssColor::parse(value, current_color)?;
+    Some(color.to_peniko())
+}
+
+/// Serializes a color in the legacy `rgba()` syntax that every parser understands
+pub fn to_css_string(color: Color) -> String {
+    format!("rgba({}, {}, {}, {})", color.r, color.g, color.b, color.a as f64 / 255.0)
+}
+
+/// The color spaces css colors can be specified and mixed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    SrgbLinear,
+    DisplayP3,
+    A98Rgb,
+    ProphotoRgb,
+    Rec2020,
+    XyzD50,
+    XyzD65,
+    Lab,
+    Lch,
+    Oklab,
+    Oklch,
+    Hsl,
+    Hwb,
+}
+
+impl ColorSpace {
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "srgb" => ColorSpace::Srgb,
+            "srgb-linear" => ColorSpace::SrgbLinear,
+            "display-p3" => ColorSpace::DisplayP3,
+            "a98-rgb" => ColorSpace::A98Rgb,
+            "prophoto-rgb" => ColorSpace::ProphotoRgb,
+            "rec2020" => ColorSpace::Rec2020,
+            "xyz-d50" => ColorSpace::XyzD50,
+            "xyz" | "xyz-d65" => ColorSpace::XyzD65,
+            "lab" => ColorSpace::Lab,
+            "lch" => ColorSpace::Lch,
+            "oklab" => ColorSpace::Oklab,
+            "oklch" => ColorSpace::Oklch,
+            "hsl" => ColorSpace::Hsl,
+            "hwb" => ColorSpace::Hwb,
+            _ => return None,
+        })
+    }
+
+    /// The index of the hue component, for the cylindrical spaces
+    fn hue_index(self) -> Option<usize> {
+        match self {
+            ColorSpace::Hsl | ColorSpace::Hwb => Some(0),
+            ColorSpace::Lch | ColorSpace::Oklch => Some(2),
+            _ => None,
+        }
+    }
+
+    /// Converts the components of a color in this space to CIE XYZ relative to D65
+    fn to_xyz(self, c: [f64; 3]) -> [f64; 3] {
+        match self {
+            ColorSpace::Srgb => mul(SRGB_TO_XYZ, c.map(srgb_to_linear)),
+            ColorSpace::SrgbLinear => mul(SRGB_TO_XYZ, c),
+            ColorSpace::DisplayP3 => mul(P3_TO_XYZ, c.map(srgb_to_linear)),
+            ColorSpace::A98Rgb => mul(A98_TO_XYZ, c.map(|v| v.signum() * v.abs().powf(563.0 / 256.0))),
+            ColorSpace::ProphotoRgb => mul(D50_TO_D65, mul(PROPHOTO_TO_XYZ_D50, c.map(prophoto_to_linear))),
+            ColorSpace::Rec2020 => mul(REC2020_TO_XYZ, c.map(rec2020_to_linear)),
+            ColorSpace::XyzD50 => mul(D50_TO_D65, c),
+            ColorSpace::XyzD65 => c,
+            ColorSpace::Lab => mul(D50_TO_D65, lab_to_xyz_d50(c)),
+            ColorSpace::Lch => ColorSpace::Lab.to_xyz(polar_to_rect(c)),
+            ColorSpace::Oklab => oklab_to_xyz(c),
+            ColorSpace::Oklch => oklab_to_xyz(polar_to_rect(c)),
+            ColorSpace::Hsl => ColorSpace::Srgb.to_xyz(hsl_to_srgb(c)),
+            ColorSpace::Hwb => ColorSpace::Srgb.to_xyz(hwb_to_srgb(c)),
+        }
+    }
+
+    fn from_xyz(self, xyz: [f64; 3]) -> [f64; 3] {
+        match self {
+            ColorSpace::Srgb => mul(XYZ_TO_SRGB, xyz).map(linear_to_srgb),
+            ColorSpace::SrgbLinear => mul(XYZ_TO_SRGB, xyz),
+            ColorSpace::DisplayP3 => mul(invert(P3_TO_XYZ), xyz).map(linear_to_srgb),
+            ColorSpace::A98Rgb => mul(invert(A98_TO_XYZ), xyz).map(|v| v.signum() * v.abs().powf(256.0 / 563.0)),
+            ColorSpace::ProphotoRgb => mul(invert(PROPHOTO_TO_XYZ_D50), mul(D65_TO_D50, xyz)).map(linear_to_prophoto),
+            ColorSpace::Rec2020 => mul(invert(REC2020_TO_XYZ), xyz).map(linear_to_rec2020),
+            ColorSpace::XyzD50 => mul(D65_TO_D50, xyz),
+            ColorSpace::XyzD65 => xyz,
+            ColorSpace::Lab => xyz_d50_to_lab(mul(D65_TO_D50, xyz)),
+            ColorSpace::Lch => rect_to_polar(ColorSpace::Lab.from_xyz(xyz), 0.0015),
+            ColorSpace::Oklab => xyz_to_oklab(xyz),
+            ColorSpace::Oklch => rect_to_polar(xyz_to_oklab(xyz), 0.000004),
+            ColorSpace::Hsl => srgb_to_hsl(ColorSpace::Srgb.from_xyz(xyz)),
+            ColorSpace::Hwb => srgb_to_hwb(ColorSpace::Srgb.from_xyz(xyz)),
+        }
+    }
+}
+
+/// How hues are interpolated in `color-mix()`, see https://drafts.csswg.org/css-color-4/#hue-interpolation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HueInterpolation {
+    #[default]
+    Shorter,
+    Longer,
+    Increasing,
+    Decreasing,
+}
+
+/// A color in some color space. Missing components (`none`) are stored as NaN and treated as
+/// zero, except when mixing, where the component of the other color is used.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CssColor {
+    pub space: ColorSpace,
+    pub components: [f64; 3],
+    pub alpha: f64,
+}
+
+impl CssColor {
+    pub fn new(space: ColorSpace, components: [f64; 3], alpha: f64) -> Self {
+        Self {
+            space,
+            components,
+            alpha,
+        }
+    }
+
+    pub fn from_peniko(color: Color) -> Self {
+        let c = [color.r, color.g, color.b].map(|c| c as f64 / 255.0);
+        Self::new(ColorSpace::Srgb, c, color.a as f64 / 255.0)
+    }
+
+    pub fn parse(value: &str, current_color: Color) -> Option<Self> {
+        let value = value.trim();
+
+        if let Some(hex) = value.strip_prefix('#') {
+            return parse_hex(hex);
+        }
+
+        let Some((name, args)) = value.split_once('(') else {
+            return parse_keyword(value, current_color);
+        };
+
+        let args = args.trim_end().strip_suffix(')')?;
+        let name = name.trim().to_ascii_lowercase();
+
+        match name.as_str() {
+            "rgb" | "rgba" => parse_rgb(args),
+            "hsl" | "hsla" => parse_hsl(args),
+            "hwb" => parse_polar_function(args, ColorSpace::Hwb, [0.0, 100.0, 100.0], 0),
+            "lab" => parse_function(args, ColorSpace::Lab, [100.0, 125.0, 125.0]),
+            "oklab" => parse_function(args, ColorSpace::Oklab, [1.0, 0.4, 0.4]),
+            "lch" => parse_polar_function(args, ColorSpace::Lch, [100.0, 150.0, 0.0], 2),
+            "oklch" => parse_polar_function(args, ColorSpace::Oklch, [1.0, 0.4, 0.0], 2),
+            "color" => parse_color_function(args),
+            "color-mix" => parse_color_mix(args, current_color),
+            _ => None,
+        }
+    }
+
+    pub fn to_space(self, space: ColorSpace) -> Self {
+        if space == self.space {
+            return self;
+        }
+
+        let xyz = self.space.to_xyz(self.components.map(zero_if_missing));
+        Self::new(space, space.from_xyz(xyz), self.alpha)
+    }
+
+    pub fn is_in_srgb_gamut(self) -> bool {
+        let srgb = self.to_space(ColorSpace::Srgb).components.map(zero_if_missing);
+        srgb.iter().all(|c| (-0.0001..=1.0001).contains(c))
+    }
+
+    /// Maps the color into the sRGB gamut by reducing its chroma in OkLCh until it is in gamut or
+    /// the clipped color is indistinguishable from it, see https://drafts.csswg.org/css-color-4/#binsearch
+    pub fn to_srgb_gamut(self) -> [f64; 3] {
+        const JND: f64 = 0.02;
+        const EPSILON: f64 = 0.0001;
+
+        let srgb = self.to_space(ColorSpace::Srgb).components.map(zero_if_missing);
+        if self.is_in_srgb_gamut() {
+            return srgb.map(|c| c.clamp(0.0, 1.0));
+        }
+
+        let origin = self.to_space(ColorSpace::Oklch);
+        let [lightness, chroma, hue] = origin.components.map(zero_if_missing);
+
+        if lightness >= 1.0 {
+            return [1.0; 3];
+        }
+        if lightness <= 0.0 {
+            return [0.0; 3];
+        }
+
+        let clip = |color: CssColor| {
+            let srgb = color.to_space(ColorSpace::Srgb).components;
+            srgb.map(|c| zero_if_missing(c).clamp(0.0, 1.0))
+        };
+        let mut current = origin;
+        let mut clipped = clip(current);
+
+        if delta_eok(CssColor::new(ColorSpace::Srgb, clipped, 1.0), current) < JND {
+            return clipped;
+        }
+
+        let mut min = 0.0;
+        let mut max = chroma;
+        let mut min_in_gamut = true;
+
+        while max - min > EPSILON {
+            let chroma = (min + max) / 2.0;
+            current.components = [lightness, chroma, hue];
+
+            if min_in_gamut && current.is_in_srgb_gamut() {
+                min = chroma;
+                continue;
+            }
+
+            clipped = clip(current);
+            let e = delta_eok(CssColor::new(ColorSpace::Srgb, clipped, 1.0), current);
+
+            if e < JND {
+                if JND - e < EPSILON {
+                    return clipped;
+                }
+
+                min_in_gamut = false;
+                min = chroma;
+            } else {
+                max = chroma;
+            }
+        }
+
+        clipped
+    }
+
+    pub fn to_peniko(self) -> Color {
+        let [r, g, b] = self.to_srgb_gamut().map(|c| (c * 255.0).round() as u8);
+        let a = (zero_if_missing(self.alpha).clamp(0.0, 1.0) * 255.0).round() as u8;
+        Color::rgba8(r, g, b, a)
+    }
+
+    /// Mixes two colors in the given space, `p2` is the weight of the second color, see
+    /// https://drafts.csswg.org/css-color-5/#color-mix-result
+    pub fn mix(self, other: Self, p2: f64, space: ColorSpace, hue: HueInterpolation) -> Self {
+        let mut a = self.to_space(space);
+        let mut b = other.to_space(space);
+
+        // a missing component takes the value of the other color
+        for i in 0..3 {
+            if a.components[i].is_nan() {
+                a.components[i] = b.components[i];
+            } else if b.components[i].is_nan() {
+                b.components[i] = a.components[i];
+            }
+        }
+
+        let alpha_a = zero_if_missing(a.alpha);
+        let alpha_b = zero_if_missing(b.alpha);
+        let alpha = alpha_a * (1.0 - p2) + alpha_b * p2;
+
+        let hue_index = space.hue_index();
+
+        if let Some(h) = hue_index {
+            let (h1, h2) = fix_hues(zero_if_missing(a.components[h]), zero_if_missing(b.components[h]), hue);
+            a.components[h] = h1;
+            b.components[h] = h2;
+        }
+
+        let mut components = [0.0; 3];
+        for (i, c) in components.iter_mut().enumerate() {
+            let (ca, cb) = (zero_if_missing(a.components[i]), zero_if_missing(b.components[i]));
+
+            // hues are never premultiplied
+            *c = if Some(i) == hue_index {
+                ca * (1.0 - p2) + cb * p2
+            } else if alpha == 0.0 {
+                0.0
+            } else {
+                (ca * alpha_a * (1.0 - p2) + cb * alpha_b * p2) / alpha
+            };
+        }
+
+        if let Some(h) = hue_index {
+            components[h] = components[h].rem_euclid(360.0);
+        }
+
+        Self::new(space, components, alpha)
+    }
+}
+
+fn zero_if_missing(c: f64) -> f64 {
+    if c.is_nan() {
+        0.0
+    } else {
+        c
+    }
+}
+
+fn parse_keyword(value: &str, current_color: Color) -> Option<CssColor> {
+    let name = value.to_ascii_lowercase();
+
+    match name.as_str() {
+        "transparent" => return Some(CssColor::new(ColorSpace::Srgb, [0.0; 3], 0.0)),
+        "currentcolor" => return Some(CssColor::from_peniko(current_color)),
+        _ => {}
+    }
+
+    NAMED_COLORS
+        .iter()
+        .chain(SYSTEM_COLORS)
+        .find(|(n, _)| *n == name)
+        .map(|(_, rgb)| {
+            let c = [rgb >> 16, rgb >> 8, *rgb].map(|c| (c & 0xff) as f64 / 255.0);
+            CssColor::new(ColorSpace::Srgb, c, 1.0)
+        })
+}
+
+fn parse_hex(hex: &str) -> Option<CssColor> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let digit = |i: usize| u8::from_str_radix(&hex[i..=i], 16).ok().map(|d| d * 17);
+    let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+
+    let [r, g, b, a] = match hex.len() {
+        3 => [digit(0)?, digit(1)?, digit(2)?, 255],
+        4 => [digit(0)?, digit(1)?, digit(2)?, digit(3)?],
+        6 => [byte(0)?, byte(2)?, byte(4)?, 255],
+        8 => [byte(0)?, byte(2)?, byte(4)?, byte(6)?],
+        _ => return None,
+    };
+
+    let c = [r, g, b].map(|c| c as f64 / 255.0);
+    Some(CssColor::new(ColorSpace::Srgb, c, a as f64 / 255.0))
+}
+
+/// Splits the arguments of a color function into its components and the alpha value. Both the
+/// legacy comma separated syntax and the modern syntax with `/` before the alpha are accepted.
+fn split_args(args: &str) -> Option<(Vec<&str>, Option<&str>)> {
+    let commas = split_top_level(args, ',');
+
+    if commas.len() > 1 {
+        let mut components = commas;
+        let alpha = match components.len() {
+            3 => None,
+            4 => components.pop(),
+            _ => return None,
+        };
+        return Some((components, alpha));
+    }
+
+    let (components, alpha) = match split_top_level(args, '/').as_slice() {
+        [components] => (*components, None),
+        [components, alpha] => (*components, Some(*alpha)),
+        _ => return None,
+    };
+
+    Some((tokens(components), alpha))
+}
+
+/// Parses a number or percentage, where 100% equals `percent_ref`
+fn parse_component(value: &str, percent_ref: f64) -> Option<f64> {
+    let value = value.trim();
+
+    if value.eq_ignore_ascii_case("none") {
+        return Some(f64::NAN);
+    }
+
+    if let Some(percentage) = value.strip_suffix('%') {
+        return Some(percentage.trim().parse::<f64>().ok()? / 100.0 * percent_ref);
+    }
+
+    value.parse().ok()
+}
+
+/// Parses a hue into degrees, a plain number is in degrees as well
+fn parse_hue(value: &str) -> Option<f64> {
+    let value = value.trim();
+
+    if value.eq_ignore_ascii_case("none") {
+        return Some(f64::NAN);
+    }
+
+    if let Ok(degrees) = value.parse::<f64>() {
+        return Some(degrees);
+    }
+
+    parse_angle(value).map(|rad| rad * 180.0 / PI)
+}
+
+fn parse_alpha(alpha: Option<&str>) -> Option<f64> {
+    match alpha {
+        Some(alpha) => parse_component(alpha, 1.0).map(|a| if a.is_nan() { a } else { a.clamp(0.0, 1.0) }),
+        None => Some(1.0),
+    }
+}
+
+fn parse_rgb(args: &str) -> Option<CssColor> {
+    let (components, alpha) = split_args(args)?;
+    if components.len() != 3 {
+        return None;
+    }
+
+    let mut c = [0.0; 3];
+    for (c, value) in c.iter_mut().zip(&components) {
+        *c = parse_component(value, 255.0)? / 255.0;
+    }
+
+    Some(CssColor::new(ColorSpace::Srgb, c.map(|c| c.clamp(0.0, 1.0)), parse_alpha(alpha)?))
+}
+
+fn parse_hsl(args: &str) -> Option<CssColor> {
+    let (components, alpha) = split_args(args)?;
+    let [h, s, l] = components.as_slice() else {
+        return None;
+    };
+
+    // saturation and lightness are stored in the 0 to 1 range, a bare number is a percentage
+    let c = [parse_hue(h)?, parse_component(s, 100.0)? / 100.0, parse_component(l, 100.0)? / 100.0];
+
+    Some(CssColor::new(ColorSpace::Hsl, c, parse_alpha(alpha)?))
+}
+
+/// Parses a function with three components, where 100% of each component equals `percent_ref`
+fn parse_function(args: &str, space: ColorSpace, percent_ref: [f64; 3]) -> Option<CssColor> {
+    let (components, alpha) = split_args(args)?;
+    if components.len() != 3 {
+        return None;
+    }
+
+    let mut c = [0.0; 3];
+    for ((c, value), percent_ref) in c.iter_mut().zip(&components).zip(percent_ref) {
+        *c = parse_component(value, percent_ref)?;
+    }
+
+    if space == ColorSpace::Lab || space == ColorSpace::Lch {
+        c[0] = c[0].clamp(0.0, 100.0);
+    } else if space == ColorSpace::Oklab || space == ColorSpace::Oklch {
+        c[0] = c[0].clamp(0.0, 1.0);
+    }
+
+    Some(CssColor::new(space, c, parse_alpha(alpha)?))
+}
+
+/// Like [`parse_function`], but the component at `hue` is an angle
+fn parse_polar_function(args: &str, space: ColorSpace, percent_ref: [f64; 3], hue: usize) -> Option<CssColor> {
+    let (components, alpha) = split_args(args)?;
+    if components.len() != 3 {
+        return None;
+    }
+
+    let mut with_hue = components.clone();
+    with_hue[hue] = "0";
+
+    let mut color = parse_function(&with_hue.join(" "), space, percent_ref)?;
+    color.components[hue] = parse_hue(components[hue])?;
+    color.alpha = parse_alpha(alpha)?;
+
+    if space == ColorSpace::Hwb {
+        color.components[1] /= 100.0;
+        color.components[2] /= 100.0;
+    }
+
+    Some(color)
+}
+
+/// `color(<space> c1 c2 c3 [/ alpha])`, see https://drafts.csswg.org/css-color-4/#color-function
+fn parse_color_function(args: &str) -> Option<CssColor> {
+    let (components, alpha) = split_args(args)?;
+    let [space, c @ ..] = components.as_slice() else {
+        return None;
+    };
+
+    let space = ColorSpace::parse(space)?;
+    if space.hue_index().is_some() || matches!(space, ColorSpace::Lab | ColorSpace::Oklab | ColorSpace::Hsl) {
+        return None;
+    }
+
+    if c.len() != 3 {
+        return None;
+    }
+
+    let mut components = [0.0; 3];
+    for (component, value) in components.iter_mut().zip(c) {
+        *component = parse_component(value, 1.0)?;
+    }
+
+    Some(CssColor::new(space, components, parse_alpha(alpha)?))
+}
+
+/// `color-mix(in <space> [<hue> hue], <color> [<p1>], <color> [<p2>])`
+fn parse_color_mix(args: &str, current_color: Color) -> Option<CssColor> {
+    let parts = split_top_level(args, ',');
+    let [interpolation, first, second] = parts.as_slice() else {
+        return None;
+    };
+
+    let interpolation = tokens(interpolation);
+    let (space, hue) = match interpolation.as_slice() {
+        ["in", space] => (ColorSpace::parse(space)?, HueInterpolation::default()),
+        ["in", space, method, "hue"] => {
+            let hue = match *method {
+                "shorter" => HueInterpolation::Shorter,
+                "longer" => HueInterpolation::Longer,
+                "increasing" => HueInterpolation::Increasing,
+                "decreasing" => HueInterpolation::Decreasing,
+                _ => return None,
+            };
+            (ColorSpace::parse(space)?, hue)
+        }
+        _ => return None,
+    };
+
+    let (c1, p1) = parse_mix_color(first, current_color)?;
+    let (c2, p2) = parse_mix_color(second, current_color)?;
+
+    // https://drafts.csswg.org/css-color-5/#color-mix-percent-norm
+    let (p1, p2) = match (p1, p2) {
+        (None, None) => (0.5, 0.5),
+        (Some(p1), None) => (p1, 1.0 - p1),
+        (None, Some(p2)) => (1.0 - p2, p2),
+        (Some(p1), Some(p2)) => (p1, p2),
+    };
+
+    let sum = p1 + p2;
+    if sum <= 0.0 {
+        return None;
+    }
+
+    let mut mixed = c1.mix(c2, p2 / sum, space, hue);
+    if sum < 1.0 {
+        mixed.alpha *= sum;
+    }
+
+    Some(mixed)
+}
+
+/// A color in `color-mix()` with its optional percentage, in either order
+fn parse_mix_color(value: &str, current_color: Color) -> Option<(CssColor, Option<f64>)> {
+    let parts = tokens(value);
+
+    let (color, percentage) = match parts.as_slice() {
+        [color] => (*color, None),
+        [a, b] if a.ends_with('%') => (*b, Some(*a)),
+        [a, b] => (*a, Some(*b)),
+        _ => return None,
+    };
+
+    let percentage = match percentage {
+        Some(p) => {
+            let p = p.strip_suffix('%')?.parse::<f64>().ok()?;
+            if !(0.0..=100.0).contains(&p) {
+                return None;
+            }
+            Some(p / 100.0)
+        }
+        None => None,
+    };
+
+    Some((CssColor::parse(color, current_color)?, percentage))
+}
+
+/// Adjusts two hues for interpolation, see https://drafts.csswg.org/css-color-4/#hue-interpolation
+fn fix_hues(mut h1: f64, mut h2: f64, method: HueInterpolation) -> (f64, f64) {
+    h1 = h1.rem_euclid(360.0);
+    h2 = h2.rem_euclid(360.0);
+
+    match method {
+        HueInterpolation::Shorter => {
+            if h2 - h1 > 180.0 {
+                h1 += 360.0;
+            } else if h2 - h1 < -180.0 {
+                h2 += 360.0;
+            }
+        }
+        HueInterpolation::Longer => {
+            let d = h2 - h1;
+            if d > 0.0 && d < 180.0 {
+                h1 += 360.0;
+            } else if d > -180.0 && d <= 0.0 {
+                h2 += 360.0;
+            }
+        }
+        HueInterpolation::Increasing => {
+            if h2 < h1 {
+                h2 += 360.0;
+            }
+        }
+        HueInterpolation::Decreasing => {
+            if h1 < h2 {
+                h1 += 360.0;
+            }
+        }
+    }
+
+    (h1, h2)
+}
+
+/// The distance of two colors in OkLab, the deltaEOK of the gamut mapping algorithm
+fn delta_eok(a: CssColor, b: CssColor) -> f64 {
+    let a = a.to_space(ColorSpace::Oklab).components;
+    let b = b.to_space(ColorSpace::Oklab).components;
+
+    a.iter().zip(&b).map(|(a, b)| (a - b).powi(2)).sum::<f64>().sqrt()
+}
+
+fn mul(m: Matrix, v: [f64; 3]) -> [f64; 3] {
+    [
+        m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+        m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+        m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+    ]
+}
+
+fn invert(m: Matrix) -> Matrix {
+    let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+
+    let cofactor = |r1: usize, r2: usize, c1: usize, c2: usize| m[r1][c1] * m[r2][c2] - m[r1][c2] * m[r2][c1];
+
+    [
+        [cofactor(1, 2, 1, 2) / det, -cofactor(0, 2, 1, 2) / det, cofactor(0, 1, 1, 2) / det],
+        [-cofactor(1, 2, 0, 2) / det, cofactor(0, 2, 0, 2) / det, -cofactor(0, 1, 0, 2) / det],
+        [cofactor(1, 2, 0, 1) / det, -cofactor(0, 2, 0, 1) / det, cofactor(0, 1, 0, 1) / det],
+    ]
+}
+
+fn srgb_to_linear(c: f64) -> f64 {
+    if c.abs() <= 0.04045 {
+        c / 12.92
+    } else {
+        c.signum() * ((c.abs() + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f64) -> f64 {
+    if c.abs() <= 0.0031308 {
+        c * 12.92
+    } else {
+        c.signum() * (1.055 * c.abs().powf(1.0 / 2.4) - 0.055)
+    }
+}
+
+fn prophoto_to_linear(c: f64) -> f64 {
+    if c.abs() <= 16.0 / 512.0 {
+        c / 16.0
+    } else {
+        c.signum() * c.abs().powf(1.8)
+    }
+}
+
+fn linear_to_prophoto(c: f64) -> f64 {
+    if c.abs() >= 1.0 / 512.0 {
+        c.signum() * c.abs().powf(1.0 / 1.8)
+    } else {
+        c * 16.0
+    }
+}
+
+const REC2020_ALPHA: f64 = 1.09929682680944;
+const REC2020_BETA: f64 = 0.018053968510807;
+
+fn rec2020_to_linear(c: f64) -> f64 {
+    if c.abs() < REC2020_BETA * 4.5 {
+        c / 4.5
+    } else {
+        c.signum() * ((c.abs() + REC2020_ALPHA - 1.0) / REC2020_ALPHA).powf(1.0 / 0.45)
+    }
+}
+
+fn linear_to_rec2020(c: f64) -> f64 {
+    if c.abs() > REC2020_BETA {
+        c.signum() * (REC2020_ALPHA * c.abs().powf(0.45) - (REC2020_ALPHA - 1.0))
+    } else {
+        c * 4.5
+    }
+}
+
+const LAB_KAPPA: f64 = 24389.0 / 27.0;
+const LAB_EPSILON: f64 = 216.0 / 24389.0;
+
+fn lab_to_xyz_d50([l, a, b]: [f64; 3]) -> [f64; 3] {
+    let f1 = (l + 16.0) / 116.0;
+    let f0 = a / 500.0 + f1;
+    let f2 = f1 - b / 200.0;
+
+    let x = if f0.powi(3) > LAB_EPSILON { f0.powi(3) } else { (116.0 * f0 - 16.0) / LAB_KAPPA };
+    let y = if l > LAB_KAPPA * LAB_EPSILON { f1.powi(3) } else { l / LAB_KAPPA };
+    let z = if f2.powi(3) > LAB_EPSILON { f2.powi(3) } else { (116.0 * f2 - 16.0) / LAB_KAPPA };
+
+    [x * D50_WHITE[0], y * D50_WHITE[1], z * D50_WHITE[2]]
+}
+
+fn xyz_d50_to_lab(xyz: [f64; 3]) -> [f64; 3] {
+    let f = |i: usize| {
+        let v = xyz[i] / D50_WHITE[i];
+        if v > LAB_EPSILON {
+            v.cbrt()
+        } else {
+            (LAB_KAPPA * v + 16.0) / 116.0
+        }
+    };
+
+    let (fx, fy, fz) = (f(0), f(1), f(2));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn oklab_to_xyz(lab: [f64; 3]) -> [f64; 3] {
+    let lms = mul(OKLAB_TO_LMS, lab).map(|c| c.powi(3));
+    mul(LMS_TO_XYZ, lms)
+}
+
+fn xyz_to_oklab(xyz: [f64; 3]) -> [f64; 3] {
+    let lms = mul(XYZ_TO_LMS, xyz).map(f64::cbrt);
+    mul(LMS_TO_OKLAB, lms)
+}
+
+/// Lightness, chroma and hue in degrees to lightness, a and b
+fn polar_to_rect([l, c, h]: [f64; 3]) -> [f64; 3] {
+    let h = zero_if_missing(h).to_radians();
+    [l, c * h.cos(), c * h.sin()]
+}
+
+/// The hue is missing when the chroma is below `achromatic`
+fn rect_to_polar([l, a, b]: [f64; 3], achromatic: f64) -> [f64; 3] {
+    let c = (a * a + b * b).sqrt();
+    let h = if c < achromatic { f64::NAN } else { b.atan2(a).to_degrees().rem_euclid(360.0) };
+    [l, c, h]
+}
+
+/// Hue in degrees, saturation and lightness from 0 to 1
+fn hsl_to_srgb([h, s, l]: [f64; 3]) -> [f64; 3] {
+    let h = zero_if_missing(h).rem_euclid(360.0);
+
+    let f = |n: f64| {
+        let k = (n + h / 30.0) % 12.0;
+        let a = s * l.min(1.0 - l);
+        l - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+    };
+
+    [f(0.0), f(8.0), f(4.0)]
+}
+
+fn srgb_to_hsl([r, g, b]: [f64; 3]) -> [f64; 3] {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (min + max) / 2.0;
+    let d = max - min;
+
+    if d == 0.0 {
+        return [f64::NAN, 0.0, l];
+    }
+
+    let s = if l == 0.0 || l == 1.0 { 0.0 } else { (max - l) / l.min(1.0 - l) };
+
+    let h = if max == r {
+        (g - b) / d + if g < b { 6.0 } else { 0.0 }
+    } else if max == g {
+        (b - r) / d + 2.0
+    } else {
+        (r - g) / d + 4.0
+    };
+
+    [h * 60.0, s, l]
+}
+
+/// Hue in degrees, whiteness and blackness from 0 to 1
+fn hwb_to_srgb([h, w, b]: [f64; 3]) -> [f64; 3] {
+    if w + b >= 1.0 {
+        let gray = w / (w + b);
+        return [gray; 3];
+    }
+
+    hsl_to_srgb([h, 1.0, 0.5]).map(|c| c * (1.0 - w - b) + w)
+}
+
+fn srgb_to_hwb(rgb: [f64; 3]) -> [f64; 3] {
+    let [h, _, _] = srgb_to_hsl(rgb);
+    let w = rgb[0].min(rgb[1]).min(rgb[2]);
+    let b = 1.0 - rgb[0].max(rgb[1]).max(rgb[2]);
+    [h, w, b]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(value: &str) -> Option<Color> {
+        parse_color(value, Color::rgb8(1, 2, 3))
+    }
+
+    #[test]
+    fn parses_keywords_and_hex() {
+        assert_eq!(parse("red"), Some(Color::rgb8(255, 0, 0)));
+        assert_eq!(parse("RebeccaPurple"), Some(Color::rgb8(0x66, 0x33, 0x99)));
+        assert_eq!(parse("transparent"), Some(Color::rgba8(0, 0, 0, 0)));
+        assert_eq!(parse("currentColor"), Some(Color::rgb8(1, 2, 3)));
+        assert_eq!(parse("#0f08"), Some(Color::rgba8(0, 255, 0, 0x88)));
+        assert_eq!(parse("#336699"), Some(Color::rgb8(0x33, 0x66, 0x99)));
+        assert_eq!(parse("#12345"), None);
+        assert_eq!(parse("not-a-color"), None);
+    }
+
+    #[test]
+    fn parses_legacy_and_modern_syntax() {
+        assert_eq!(parse("rgb(255, 0, 0)"), Some(Color::rgb8(255, 0, 0)));
+        assert_eq!(parse("rgba(0, 0, 255, 0.5)"), Some(Color::rgba8(0, 0, 255, 128)));
+        assert_eq!(parse("rgb(0 100% 0 / 50%)"), Some(Color::rgba8(0, 255, 0, 128)));
+        assert_eq!(parse("hsl(120 100% 50%)"), Some(Color::rgb8(0, 255, 0)));
+        assert_eq!(parse("hsl(240deg, 100%, 50%)"), Some(Color::rgb8(0, 0, 255)));
+        assert_eq!(parse("rgb(1, 2)"), None);
+    }
+
+    #[test]
+    fn converts_between_spaces_and_back() {
+        let color = CssColor::new(ColorSpace::Srgb, [0.2, 0.4, 0.6], 1.0);
+        let spaces = [
+            ColorSpace::SrgbLinear,
+            ColorSpace::DisplayP3,
+            ColorSpace::A98Rgb,
+            ColorSpace::ProphotoRgb,
+            ColorSpace::Rec2020,
+            ColorSpace::XyzD50,
+            ColorSpace::XyzD65,
+            ColorSpace::Lab,
+            ColorSpace::Lch,
+            ColorSpace::Oklab,
+            ColorSpace::Oklch,
+            ColorSpace::Hsl,
+            ColorSpace::Hwb,
+        ];
+
+        for space in spaces {
+            let back = color.to_space(space).to_space(ColorSpace::Srgb);
+            for (a, b) in color.components.iter().zip(back.components) {
+                assert!((a - b).abs() < 1e-6, "{space:?}: {:?}", back.components);
+            }
+        }
+    }
+
+    #[test]
+    fn mixes_colors() {
+        assert_eq!(parse("color-mix(in srgb, red, blue)"), Some(Color::rgb8(128, 0, 128)));
+        assert_eq!(parse("color-mix(in srgb, red 25%, blue)"), Some(Color::rgb8(64, 0, 191)));
+        assert_eq!(parse("color-mix(in srgb, 75% blue, red)"), Some(Color::rgb8(64, 0, 191)));
+        // percentages that sum to less than 100% make the result transparent
+        assert_eq!(parse("color-mix(in srgb, red 20%, blue 20%)"), Some(Color::rgba8(128, 0, 128, 102)));
+        assert_eq!(parse("color-mix(in srgb, red, blue, green)"), None);
+    }
+
+    #[test]
+    fn mixing_a_color_with_itself_round_trips() {
+        for space in ["srgb", "srgb-linear", "display-p3", "lab", "lch", "oklab", "oklch", "hsl", "hwb", "xyz"] {
+            let mixed = parse(&format!("color-mix(in {space}, #336699, #336699 30%)"));
+            assert_eq!(mixed, Some(Color::rgb8(0x33, 0x66, 0x99)), "{space}");
+        }
+    }
+
+    #[test]
+    fn interpolates_hues() {
+        assert_eq!(parse("color-mix(in hsl, hsl(350 100% 50%), hsl(10 100% 50%))"), Some(Color::rgb8(255, 0, 0)));
+        assert_eq!(parse("color-mix(in hsl longer hue, hsl(350 100% 50%), hsl(10 100% 50%))"), Some(Color::rgb8(0, 255, 255)));
+        assert_eq!(parse("color-mix(in hsl increasing hue, hsl(350 100% 50%), hsl(10 100% 50%))"), Some(Color::rgb8(255, 0, 0)));
+        assert_eq!(parse("color-mix(in hsl decreasing hue, hsl(350 100% 50%), hsl(10 100% 50%))"), Some(Color::rgb8(0, 255, 255)));
+    }
+
+    #[test]
+    fn maps_colors_into_the_srgb_gamut() {
+        let p3 = CssColor::parse("color(display-p3 0 1 0)", Color::BLACK).unwrap();
+        assert!(!p3.is_in_srgb_gamut());
+
+        let [r, g, b] = p3.to_srgb_gamut();
+        assert!([r, g, b].iter().all(|c| (0.0..=1.0).contains(c)));
+        assert!(g > r && g > b);
+
+        assert_eq!(parse("oklch(100% 0.4 120)"), Some(Color::WHITE));
+        assert_eq!(parse("oklch(0% 0.4 120)"), Some(Color::BLACK));
+    }
+}
@@ -0,0 +1,117 @@
+use vello::kurbo::{Affine, Rect, Shape};
+use vello::peniko::{BlendMode, Compose, Mix};
+use vello::Scene;
+
+use crate::style::ComputedValues;
+
+/// Layers need a clip shape, but an element's group also contains all of its overflowing
+/// descendants, so group layers are clipped to a rect that is larger than any viewport.
+pub const UNBOUNDED: Rect = Rect::new(-1.0e6, -1.0e6, 1.0e6, 1.0e6);
+
+/// How an element and its descendants are composited onto their backdrop
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Compositing {
+    pub opacity: f32,
+    pub blend_mode: Mix,
+    pub isolate: bool,
+}
+
+impl Default for Compositing {
+    fn default() -> Self {
+        Self {
+            opacity: 1.0,
+            blend_mode: Mix::Normal,
+            isolate: false,
+        }
+    }
+}
+
+impl Compositing {
+    pub fn from_values(values: &ComputedValues) -> Self {
+        let opacity = values.get("opacity").and_then(parse_opacity).unwrap_or(1.0);
+
+        let blend_mode = values
+            .get("mix-blend-mode")
+            .and_then(parse_blend_mode)
+            .unwrap_or(Mix::Normal);
+
+        let isolate = values
+            .get("isolation")
+            .is_some_and(|i| i.trim() == "isolate");
+
+        Self {
+            opacity,
+            blend_mode,
+            isolate,
+        }
+    }
+
+    /// Whether the element has to be painted into its own group, see
+    /// https://drafts.fxtf.org/compositing/#isolation
+    pub fn needs_layer(&self) -> bool {
+        self.opacity < 1.0 || self.blend_mode != Mix::Normal || self.isolate
+    }
+
+    /// An element with an opacity of zero is still laid out, but neither it nor its descendants
+    /// are painted
+    pub fn is_invisible(&self) -> bool {
+        self.opacity <= 0.0
+    }
+
+    /// Pushes the group layer for this element, returns whether a layer was pushed and
+    /// [`Compositing::pop`] has to be called after the descendants are painted.
+    pub fn push(&self, scene: &mut Scene, transform: Affine, clip: &impl Shape) -> bool {
+        if !self.needs_layer() {
+            return false;
+        }
+
+        scene.push_layer(
+            BlendMode::new(self.blend_mode, Compose::SrcOver),
+            self.opacity,
+            transform,
+            clip,
+        );
+
+        true
+    }
+
+    pub fn pop(&self, scene: &mut Scene, pushed: bool) {
+        if pushed {
+            scene.pop_layer();
+        }
+    }
+}
+
+pub fn parse_opacity(value: &str) -> Option<f32> {
+    let value = value.trim();
+
+    let opacity = if let Some(percentage) = value.strip_suffix('%') {
+        percentage.trim().parse::<f32>().ok()? / 100.0
+    } else {
+        value.parse::<f32>().ok()?
+    };
+
+    Some(opacity.clamp(0.0, 1.0))
+}
+
+pub fn parse_blend_mode(value: &str) -> Option<Mix> {
+    Some(match value.trim() {
+        "normal" => Mix::Normal,
+        "multiply" => Mix::Multiply,
+        "screen" => Mix::Screen,
+        "overlay" => Mix::Overlay,
+        "darken" => Mix::Darken,
+        "lighten" => Mix::Lighten,
+        "color-dodge" => Mix::ColorDodge,
+        "color-burn" => Mix::ColorBurn,
+        "hard-light" => Mix::HardLight,
+        "soft-light" => Mix::SoftLight,
+        "difference" => Mix::Difference,
+        "exclusion" => Mix::Exclusion,
+        "hue" => Mix::Hue,
+        "saturation" => Mix::Saturation,
+        "color" => Mix::Color,
+        "luminosity" => Mix::Luminosity,
+        _ => return None,
+    })
+}
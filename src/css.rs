@@ -0,0 +1,113 @@
+use gosub_styling::css_values::CssValue;
+
+/// Turns a computed value into a plain css string, so multi token values like `box-shadow` can be
+/// parsed without caring how the styling crate split them up.
+pub fn value_to_string(value: &CssValue) -> Option<String> {
+    Some(match value {
+        CssValue::String(s) => s.clone(),
+        CssValue::Zero => String::from("0"),
+        CssValue::Number(n) => n.to_string(),
+        CssValue::Percentage(p) => format!("{p}%"),
+        CssValue::Unit(v, unit) => format!("{v}{unit}"),
+        CssValue::Color(c) => format!("rgba({}, {}, {}, {})", c.r, c.g, c.b, c.a / 255.0),
+        CssValue::List(list) => list.iter().filter_map(value_to_string).collect::<Vec<_>>().join(" "),
+        _ => return None,
+    })
+}
+
+/// Calls `f` with every char of `value` that is not nested in parentheses, brackets or a string
+pub fn for_each_top_level(value: &str, mut f: impl FnMut(usize, char)) {
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (i, c) in value.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            c if depth == 0 => f(i, c),
+            _ => {}
+        }
+    }
+}
+
+/// The position of the first `target` outside of strings, like the `]` that closes `[title="a]"]`
+pub fn find_unquoted(value: &str, target: char) -> Option<usize> {
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (i, c) in value.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == target => return Some(i),
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None => {}
+        }
+    }
+
+    None
+}
+
+/// Splits a value on the given separator, ignoring separators nested in parentheses, brackets or
+/// strings, e.g. `0 1px rgba(0, 0, 0, 0.5), 0 0 2px red` splits into two shadows.
+pub fn split_top_level(value: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+
+    for_each_top_level(value, |i, c| {
+        if c == sep {
+            parts.push(value[start..i].trim());
+            start = i + c.len_utf8();
+        }
+    });
+
+    parts.push(value[start..].trim());
+    parts.retain(|p| !p.is_empty());
+    parts
+}
+
+/// Splits a value into whitespace separated tokens, keeping functions like `rgb(0 0 0 / 50%)` intact
+pub fn tokens(value: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = None;
+
+    for (i, c) in value.char_indices() {
+        match c {
+            '(' => {
+                depth += 1;
+                start.get_or_insert(i);
+            }
+            ')' => depth = depth.saturating_sub(1),
+            c if c.is_whitespace() && depth == 0 => {
+                if let Some(s) = start.take() {
+                    parts.push(&value[s..i]);
+                }
+            }
+            _ => {
+                start.get_or_insert(i);
+            }
+        }
+    }
+
+    if let Some(s) = start {
+        parts.push(&value[s..]);
+    }
+
+    parts
+}
@@ -0,0 +1,165 @@
+use std::str::FromStr;
+
+use gosub_html5::node::NodeId;
+use gosub_styling::render_tree::{RenderNodeData, RenderTree};
+use url::Url;
+use vello::kurbo::Vec2;
+use vello::peniko::Image;
+
+use crate::css::{split_top_level, tokens};
+use crate::hit_test::Hit;
+use crate::image::IMAGE_CACHE;
+use crate::navigation::link_href;
+use crate::style::ComputedStyles;
+
+pub use winit::window::CursorIcon;
+
+/// The computed `cursor` property, e.g. `url(hand.png) 4 0, pointer`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CursorStyle {
+    /// Custom cursors in order of preference
+    pub images: Vec<CursorImage>,
+    pub keyword: CursorKeyword,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CursorImage {
+    pub url: String,
+    /// The point of the image that is at the pointer position, the top left when not given
+    pub hotspot: Option<Vec2>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CursorKeyword {
+    /// Text gets the text cursor, links the pointer and everything else the default arrow
+    #[default]
+    Auto,
+    Icon(CursorIcon),
+    None,
+}
+
+impl CursorStyle {
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = split_top_level(value, ',');
+        let keyword = match parts.pop()?.trim() {
+            "auto" => CursorKeyword::Auto,
+            "none" => CursorKeyword::None,
+            name => CursorKeyword::Icon(CursorIcon::from_str(name).ok()?),
+        };
+
+        let images = parts.into_iter().filter_map(parse_cursor_image).collect();
+
+        Some(Self { images, keyword })
+    }
+}
+
+fn parse_cursor_image(value: &str) -> Option<CursorImage> {
+    let tokens = tokens(value);
+    let (url, hotspot) = tokens.split_first()?;
+
+    let url = url
+        .strip_prefix("url(")?
+        .strip_suffix(')')?
+        .trim()
+        .trim_matches(|c| c == '"' || c == '\'');
+
+    let hotspot = match hotspot {
+        [x, y] => Some(Vec2::new(x.parse().ok()?, y.parse().ok()?)),
+        _ => None,
+    };
+
+    Some(CursorImage {
+        url: url.to_string(),
+        hotspot,
+    })
+}
+
+/// What the pointer looks like. Winit can only show the system cursors, custom images are painted
+/// into the scene while the system cursor is hidden.
+#[derive(Debug, Clone, Default)]
+pub enum Cursor {
+    #[default]
+    Default,
+    Icon(CursorIcon),
+    Image {
+        image: Image,
+        hotspot: Vec2,
+    },
+    Hidden,
+}
+
+impl Cursor {
+    /// The system cursor to show, `None` if it should be hidden
+    pub fn system_icon(&self) -> Option<CursorIcon> {
+        match self {
+            Cursor::Default => Some(CursorIcon::Default),
+            Cursor::Icon(icon) => Some(*icon),
+            Cursor::Image { .. } | Cursor::Hidden => None,
+        }
+    }
+}
+
+/// Resolves the cursor for what is under the pointer. Relative image urls are resolved against
+/// `base`, images that fail to load fall through to the next one and finally to the keyword.
+pub fn resolve_cursor(hit: Option<&Hit>, render_tree: &RenderTree, styles: &ComputedStyles, base: &Url) -> Cursor {
+    let Some(hit) = hit else {
+        return Cursor::Default;
+    };
+
+    // scrollbars always get the arrow
+    if hit.scrollbar {
+        return Cursor::Default;
+    }
+
+    let Some(style) = styles.get(hit.node) else {
+        return Cursor::Default;
+    };
+
+    for image in style.cursor.images.iter() {
+        let Ok(url) = base.join(&image.url) else {
+            continue;
+        };
+
+        let Ok(mut cache) = IMAGE_CACHE.try_lock() else {
+            break;
+        };
+
+        match cache.load(&url) {
+            Ok(loaded) => {
+                return Cursor::Image {
+                    image: loaded,
+                    hotspot: image.hotspot.unwrap_or_default(),
+                }
+            }
+            Err(e) => eprintln!("Failed to load cursor {url}: {e:?}"),
+        }
+    }
+
+    match style.cursor.keyword {
+        CursorKeyword::Icon(icon) => Cursor::Icon(icon),
+        CursorKeyword::None => Cursor::Hidden,
+        CursorKeyword::Auto => {
+            let in_link = styles.ancestors(hit.node).any(|id| link_href(render_tree, id).is_some());
+            let is_text = matches!(render_tree.get_node(hit.node).map(|n| &n.data), Some(RenderNodeData::Text(_)));
+
+            if in_link {
+                Cursor::Icon(CursorIcon::Pointer)
+            } else if is_text && is_selectable(hit.node, styles) {
+                Cursor::Icon(CursorIcon::Text)
+            } else {
+                Cursor::Default
+            }
+        }
+    }
+}
+
+/// `user-select: auto` takes the value of the parent, so the nearest value that is not `auto` counts
+pub fn is_selectable(node: NodeId, styles: &ComputedStyles) -> bool {
+    let value = styles
+        .ancestors(node)
+        .filter_map(|id| styles.values(id)?.get("user-select"))
+        .map(str::trim)
+        .find(|value| *value != "auto");
+
+    value != Some("none")
+}
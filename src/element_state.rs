@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+
+use gosub_html5::node::NodeId;
+use gosub_styling::render_tree::{RenderNodeData, RenderTree};
+
+/// The pseudo-classes that depend on user interaction instead of the document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PseudoClass {
+    Hover,
+    Active,
+    Focus,
+    FocusVisible,
+    FocusWithin,
+    Link,
+    Visited,
+}
+
+impl PseudoClass {
+    pub const ALL: [PseudoClass; 7] = [
+        PseudoClass::Hover,
+        PseudoClass::Active,
+        PseudoClass::Focus,
+        PseudoClass::FocusVisible,
+        PseudoClass::FocusWithin,
+        PseudoClass::Link,
+        PseudoClass::Visited,
+    ];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "hover" => PseudoClass::Hover,
+            "active" => PseudoClass::Active,
+            "focus" => PseudoClass::Focus,
+            "focus-visible" => PseudoClass::FocusVisible,
+            "focus-within" => PseudoClass::FocusWithin,
+            "link" | "any-link" => PseudoClass::Link,
+            "visited" => PseudoClass::Visited,
+            _ => return None,
+        })
+    }
+}
+
+/// Which elements are hovered, active, focused or visited links. Hover and active apply to the
+/// element under the pointer and all its ancestors, so those are stored as the chain from the
+/// element up to the root.
+#[derive(Debug, Clone, Default)]
+pub struct ElementStates {
+    hover: Vec<NodeId>,
+    active: Vec<NodeId>,
+    focus: Option<NodeId>,
+    /// Whether the focus should be indicated, which is the case when it was moved with the keyboard
+    focus_visible: bool,
+    focus_within: Vec<NodeId>,
+    visited: HashSet<NodeId>,
+}
+
+impl ElementStates {
+    pub fn is(&self, id: NodeId, pseudo: PseudoClass) -> bool {
+        match pseudo {
+            PseudoClass::Hover => self.hover.contains(&id),
+            PseudoClass::Active => self.active.contains(&id),
+            PseudoClass::Focus => self.focus == Some(id),
+            PseudoClass::FocusVisible => self.focus_visible && self.focus == Some(id),
+            PseudoClass::FocusWithin => self.focus_within.contains(&id),
+            PseudoClass::Link => !self.visited.contains(&id),
+            PseudoClass::Visited => self.visited.contains(&id),
+        }
+    }
+
+    pub fn hovered(&self) -> Option<NodeId> {
+        self.hover.first().copied()
+    }
+
+    pub fn focused(&self) -> Option<NodeId> {
+        self.focus
+    }
+
+    /// Sets the hovered element with its ancestors, returns the elements whose state changed
+    pub fn set_hover(&mut self, chain: Vec<NodeId>) -> Vec<NodeId> {
+        let changed = symmetric_difference(&self.hover, &chain);
+        self.hover = chain;
+        changed
+    }
+
+    /// Sets the active element with its ancestors, returns the elements whose state changed
+    pub fn set_active(&mut self, chain: Vec<NodeId>) -> Vec<NodeId> {
+        let changed = symmetric_difference(&self.active, &chain);
+        self.active = chain;
+        changed
+    }
+
+    /// Moves the focus to the first element of `chain`, the rest are its ancestors which match
+    /// `:focus-within`. Returns the elements whose state changed.
+    pub fn set_focus(&mut self, chain: Vec<NodeId>, visible: bool) -> Vec<NodeId> {
+        let mut changed = symmetric_difference(&self.focus_within, &chain);
+
+        let focus = chain.first().copied();
+        if focus != self.focus || visible != self.focus_visible {
+            changed.extend(self.focus);
+            changed.extend(focus);
+        }
+
+        self.focus = focus;
+        self.focus_visible = visible && focus.is_some();
+        self.focus_within = chain;
+
+        changed.sort_unstable();
+        changed.dedup();
+        changed
+    }
+
+    /// Sets the links that point to visited urls, returns the elements whose state changed
+    pub fn set_visited(&mut self, visited: HashSet<NodeId>) -> Vec<NodeId> {
+        let changed = self.visited.symmetric_difference(&visited).copied().collect();
+        self.visited = visited;
+        changed
+    }
+}
+
+fn symmetric_difference(old: &[NodeId], new: &[NodeId]) -> Vec<NodeId> {
+    old.iter()
+        .filter(|id| !new.contains(id))
+        .chain(new.iter().filter(|id| !old.contains(id)))
+        .copied()
+        .collect()
+}
+
+/// Whether clicking the element or tabbing to it moves the focus there
+pub fn is_focusable(render_tree: &RenderTree, id: NodeId) -> bool {
+    let Some(RenderNodeData::Element(element)) = render_tree.get_node(id).map(|n| &n.data) else {
+        return false;
+    };
+
+    let attribute = |name: &str| element.attributes.get(name);
+
+    if attribute("disabled").is_some() {
+        return false;
+    }
+
+    if let Some(tabindex) = attribute("tabindex") {
+        return tabindex.trim().parse::<i32>().is_ok();
+    }
+
+    match element.name.as_str() {
+        "a" | "area" => attribute("href").is_some(),
+        "input" => attribute("type").map_or(true, |t| !t.eq_ignore_ascii_case("hidden")),
+        "button" | "select" | "textarea" | "summary" => true,
+        _ => false,
+    }
+}
+
+/// Whether the element is part of the sequential focus order, elements with a negative tabindex
+/// can only be focused by clicking them
+pub fn is_tabbable(render_tree: &RenderTree, id: NodeId) -> bool {
+    if !is_focusable(render_tree, id) {
+        return false;
+    }
+
+    let Some(RenderNodeData::Element(element)) = render_tree.get_node(id).map(|n| &n.data) else {
+        return false;
+    };
+
+    element
+        .attributes
+        .get("tabindex")
+        .and_then(|t| t.trim().parse::<i32>().ok())
+        .map_or(true, |t| t >= 0)
+}
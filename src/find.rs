@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use gosub_html5::node::NodeId;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+use vello::kurbo::Rect;
+
+use crate::layout::LayoutDriver;
+use crate::selection::{highlight_rects, TextLookup};
+use crate::text::TextRenderer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FindOptions {
+    /// Only find text with the same upper and lower case letters as the query
+    pub match_case: bool,
+    /// Only find text with the same accents as the query, otherwise `é` is found by `e`
+    pub match_diacritics: bool,
+}
+
+/// A match of the query. It can span several text nodes of the same block, like the text of a
+/// link and the text that follows it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FindMatch {
+    /// The byte range of each text node that is part of the match, in document order
+    pub ranges: Vec<(NodeId, Range<usize>)>,
+}
+
+/// The state of searching the page, the matches of the last query and which one is the current one
+#[derive(Debug, Clone, Default)]
+pub struct Find {
+    query: String,
+    options: FindOptions,
+    matches: Vec<FindMatch>,
+    current: Option<usize>,
+}
+
+impl Find {
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn options(&self) -> FindOptions {
+        self.options
+    }
+
+    pub fn matches(&self) -> &[FindMatch] {
+        &self.matches
+    }
+
+    /// The index of the current match
+    pub fn current_index(&self) -> Option<usize> {
+        self.current
+    }
+
+    pub fn current(&self) -> Option<&FindMatch> {
+        self.matches.get(self.current?)
+    }
+
+    /// Searches the text of the document, the first match becomes the current one. Returns the
+    /// number of matches.
+    pub fn search(&mut self, text: &TextLookup, query: &str, options: FindOptions) -> usize {
+        self.query = query.to_string();
+        self.options = options;
+        self.matches = find_matches(text, query, options);
+        self.current = (!self.matches.is_empty()).then_some(0);
+
+        self.matches.len()
+    }
+
+    /// Moves to the next match, or the previous one when going `backwards`. Wraps around at the
+    /// ends of the document.
+    pub fn advance(&mut self, backwards: bool) -> Option<&FindMatch> {
+        let count = self.matches.len();
+        if count == 0 {
+            return None;
+        }
+
+        let next = match (self.current, backwards) {
+            (None, false) => 0,
+            (None, true) => count - 1,
+            (Some(i), false) => (i + 1) % count,
+            (Some(i), true) => (i + count - 1) % count,
+        };
+
+        self.current = Some(next);
+        self.matches.get(next)
+    }
+
+    pub fn clear(&mut self) -> bool {
+        let had_matches = !self.matches.is_empty();
+        *self = Self::default();
+        had_matches
+    }
+
+    /// The matched byte ranges of every text node, with whether they belong to the current match
+    pub fn highlights(&self) -> HashMap<NodeId, Vec<(Range<usize>, bool)>> {
+        let mut highlights: HashMap<NodeId, Vec<(Range<usize>, bool)>> = HashMap::new();
+
+        for (i, found) in self.matches.iter().enumerate() {
+            for (node, range) in found.ranges.iter() {
+                highlights.entry(*node).or_default().push((range.clone(), self.current == Some(i)));
+            }
+        }
+
+        highlights
+    }
+}
+
+/// The boxes of the glyphs of a match in document coordinates, one per line of each text node
+pub fn match_boxes(found: &FindMatch, text: &TextLookup, driver: &LayoutDriver) -> Vec<Rect> {
+    let mut boxes = Vec::new();
+
+    for (node, range) in found.ranges.iter() {
+        let (Some(prerendered), Some(style), Some(id)) = (text.prerendered(*node), text.styles.get(*node), driver.layout_node(*node)) else {
+            continue;
+        };
+
+        let renderer = TextRenderer::new(style.font.families.clone(), style.font.size as f32);
+        let origin = driver.document_position(id).to_vec2();
+
+        boxes.extend(highlight_rects(prerendered, &renderer, range).into_iter().map(|rect| rect + origin));
+    }
+
+    boxes
+}
+
+/// The smallest rectangle around all boxes of a match
+pub fn bounding_box(boxes: &[Rect]) -> Option<Rect> {
+    boxes.iter().copied().reduce(|a, b| a.union(b))
+}
+
+/// Searches the text of the document. Text of different blocks is searched separately, so a match
+/// never spans a line break between blocks.
+pub fn find_matches(text: &TextLookup, query: &str, options: FindOptions) -> Vec<FindMatch> {
+    let (query, _) = fold(query, options);
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let mut matches = Vec::new();
+    let nodes = text.order.nodes();
+
+    let mut start = 0;
+    while start < nodes.len() {
+        let block = text.block(nodes[start]);
+        let end = nodes[start..]
+            .iter()
+            .position(|node| text.block(*node) != block)
+            .map_or(nodes.len(), |i| start + i);
+
+        matches.extend(find_in_block(text, &nodes[start..end], &query, options));
+        start = end;
+    }
+
+    matches
+}
+
+fn find_in_block(text: &TextLookup, nodes: &[NodeId], query: &str, options: FindOptions) -> Vec<FindMatch> {
+    // the text of the block, with where the text of each node starts in it
+    let mut content = String::new();
+    let mut segments = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        segments.push((*node, content.len()));
+        content.push_str(text.text(*node).unwrap_or_default());
+    }
+
+    let (folded, offsets) = fold(&content, options);
+    let original = |folded_offset: usize| offsets.get(folded_offset).copied().unwrap_or(content.len());
+
+    folded
+        .match_indices(query)
+        .map(|(start, found)| {
+            let (start, end) = (original(start), original(start + found.len()));
+
+            let ranges = segments
+                .iter()
+                .enumerate()
+                .filter_map(|(i, (node, segment_start))| {
+                    let segment_end = segments.get(i + 1).map_or(content.len(), |(_, s)| *s);
+                    let (from, to) = (start.max(*segment_start), end.min(segment_end));
+
+                    (from < to).then(|| (*node, from - segment_start..to - segment_start))
+                })
+                .collect();
+
+            FindMatch { ranges }
+        })
+        .collect()
+}
+
+/// Folds text for comparing it, lower cased and without accents depending on the options. Returns
+/// the folded text and for each of its bytes the offset of the character in `text` it came from.
+fn fold(text: &str, options: FindOptions) -> (String, Vec<usize>) {
+    let mut folded = String::with_capacity(text.len());
+    let mut offsets = Vec::with_capacity(text.len());
+
+    let mut push = |c: char, offset: usize| {
+        folded.push(c);
+        offsets.extend(std::iter::repeat(offset).take(c.len_utf8()));
+    };
+
+    for (offset, c) in text.char_indices() {
+        let decomposed: Vec<char> = match options.match_diacritics {
+            true => vec![c],
+            // the base letter of a precomposed character comes first when decomposing it
+            false => std::iter::once(c).nfd().filter(|c| !is_combining_mark(*c)).collect(),
+        };
+
+        for c in decomposed {
+            match options.match_case {
+                true => push(c, offset),
+                false => c.to_lowercase().for_each(|c| push(c, offset)),
+            }
+        }
+    }
+
+    (folded, offsets)
+}
+
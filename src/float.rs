@@ -0,0 +1,357 @@
+use std::collections::{HashMap, HashSet};
+
+use gosub_html5::node::NodeId;
+use taffy::{Dimension, Layout, LengthPercentageAuto, NodeId as TaffyID, PrintTree, TaffyTree, TraversePartialTree};
+use vello::kurbo::{Rect, Vec2};
+
+use crate::flow::{establishes_formatting_context, is_float};
+use crate::inline::InlineContent;
+use crate::style::{Clear, ComputedStyles, Display, Float};
+
+/// The floats of a block formatting context, which line boxes are shortened around and blocks with
+/// `clear` are moved below
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Exclusions {
+    /// The margin boxes of the floats in the order they were placed
+    floats: Vec<(Float, Rect)>,
+}
+
+impl Exclusions {
+    /// The same floats in a coordinate system whose origin is at `origin`
+    pub fn relative_to(&self, origin: Vec2) -> Self {
+        Self {
+            floats: self.floats.iter().map(|(side, rect)| (*side, *rect - origin)).collect(),
+        }
+    }
+
+    /// The part of the range from `left` to `right` that is not taken by floats anywhere between
+    /// `top` and `bottom`
+    pub fn available(&self, top: f64, bottom: f64, left: f64, right: f64) -> (f64, f64) {
+        let (mut left, mut right) = (left, right);
+
+        for (side, rect) in self.floats.iter().filter(|(_, rect)| overlaps(rect, top, bottom)) {
+            match side {
+                Float::Left => left = left.max(rect.x1),
+                Float::Right => right = right.min(rect.x0),
+                Float::None => {}
+            }
+        }
+
+        (left, right)
+    }
+
+    /// The bottom of the lowest float on the cleared sides
+    pub fn clearance(&self, clear: Clear) -> Option<f64> {
+        self.floats
+            .iter()
+            .filter(|(side, _)| match clear {
+                Clear::None => false,
+                Clear::Left => *side == Float::Left,
+                Clear::Right => *side == Float::Right,
+                Clear::Both => true,
+            })
+            .map(|(_, rect)| rect.y1)
+            .reduce(f64::max)
+    }
+
+    /// The bottom of the lowest float
+    pub fn bottom(&self) -> Option<f64> {
+        self.clearance(Clear::Both)
+    }
+
+    /// Where a box of the given height goes between `left` and `right` without overlapping floats,
+    /// as high as possible but not above `top`. A box with a `width` only fits where the floats
+    /// leave that much room, others get narrower. Returns its top and the range it can take.
+    pub fn avoid(&self, top: f64, height: f64, width: Option<f64>, left: f64, right: f64) -> (f64, f64, f64) {
+        let mut y = top;
+
+        loop {
+            let (from, to) = self.available(y, y + height, left, right);
+            let fits = width.map_or(to > from, |width| to - from >= width);
+
+            match self.next_bottom(y, y + height) {
+                Some(next) if !fits => y = next,
+                _ => return (y, from, to),
+            }
+        }
+    }
+
+    /// The bottom of the float that ends first among the floats between `top` and `bottom`
+    fn next_bottom(&self, top: f64, bottom: f64) -> Option<f64> {
+        self.floats
+            .iter()
+            .filter(|(_, rect)| overlaps(rect, top, bottom) && rect.y1 > top)
+            .map(|(_, rect)| rect.y1)
+            .reduce(f64::min)
+    }
+
+    /// Places a float with the margin box `size` as high as possible but not above `top`, and as
+    /// far to its side as the floats before it allow, see https://www.w3.org/TR/CSS2/visuren.html#float-position
+    fn place(&mut self, side: Float, size: (f64, f64), top: f64, left: f64, right: f64) -> Rect {
+        // a float is never higher than the floats before it
+        let mut y = self.floats.iter().map(|(_, rect)| rect.y0).fold(top, f64::max);
+
+        loop {
+            let (from, to) = self.available(y, y + size.1, left, right);
+
+            // the lowest bottom of the floats in the way, a float that is too wide for the
+            // containing block still has to go somewhere
+            let next = self.next_bottom(y, y + size.1);
+
+            if to - from >= size.0 || next.is_none() {
+                let x = if side == Float::Right { to - size.0 } else { from };
+                let rect = Rect::from_origin_size((x, y), size);
+
+                self.floats.push((side, rect));
+                return rect;
+            }
+
+            y = next.unwrap_or(y);
+        }
+    }
+}
+
+fn overlaps(rect: &Rect, top: f64, bottom: f64) -> bool {
+    // an empty range still lies next to the floats it is in
+    rect.y0 < bottom.max(top + f64::EPSILON) && top < rect.y1
+}
+
+/// Where the floats of the document go and what flows around them, computed from the positions of
+/// the previous layout pass
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FloatLayout {
+    /// The top left of the margin box of each float relative to the padding box of its parent
+    pub positions: HashMap<TaffyID, (f64, f64)>,
+    /// The floats next to the lines of each run of inline content, relative to its anonymous leaf
+    pub lines: HashMap<TaffyID, Exclusions>,
+    /// The space added above blocks that clear floats or move below them
+    pub clearance: HashMap<TaffyID, f64>,
+    /// The minimum left and right margins of formatting context roots next to floats, which keep
+    /// their border box from overlapping them
+    pub insets: HashMap<TaffyID, (f64, f64)>,
+    /// The height of the border box formatting context roots need to contain their floats
+    pub heights: HashMap<TaffyID, f64>,
+}
+
+/// Places the floats of every block formatting context of the laid out `tree`. `previous` is the
+/// float layout the tree was laid out with.
+pub fn layout_floats(
+    tree: &TaffyTree<NodeId>,
+    root: TaffyID,
+    styles: &ComputedStyles,
+    inline: &[InlineContent],
+    previous: &FloatLayout,
+) -> FloatLayout {
+    let mut builder = FloatBuilder {
+        tree,
+        root,
+        styles,
+        runs: inline.iter().map(|c| (c.leaf, c)).collect(),
+        in_runs: inline.iter().flat_map(|c| c.floats.iter().copied()).collect(),
+        previous,
+        result: FloatLayout::default(),
+    };
+
+    builder.layout_context(root);
+    builder.result
+}
+
+struct FloatBuilder<'a> {
+    tree: &'a TaffyTree<NodeId>,
+    /// The root of the document, which establishes the outermost formatting context
+    root: TaffyID,
+    styles: &'a ComputedStyles,
+    runs: HashMap<TaffyID, &'a InlineContent>,
+    /// Floats among inline content, they are placed at the top of their run
+    in_runs: HashSet<TaffyID>,
+    previous: &'a FloatLayout,
+    result: FloatLayout,
+}
+
+impl FloatBuilder<'_> {
+    /// Places the floats of the formatting context `root` establishes, relative to its border box
+    fn layout_context(&mut self, root: TaffyID) {
+        let mut exclusions = Exclusions::default();
+        self.walk(root, Vec2::ZERO, &mut exclusions);
+
+        // formatting context roots grow to contain their floats, the document grows anyway
+        let Some(bottom) = exclusions.bottom().filter(|_| root != self.root) else {
+            return;
+        };
+
+        let layout = self.tree.get_final_layout(root);
+        let height = bottom + (layout.padding.bottom + layout.border.bottom) as f64;
+        self.result.heights.insert(root, height);
+    }
+
+    /// Walks the boxes of a formatting context in tree order. `origin` is the top left of the
+    /// border box of `parent` in the coordinates of the context.
+    fn walk(&mut self, parent: TaffyID, origin: Vec2, exclusions: &mut Exclusions) {
+        let children = self.tree.child_ids(parent).collect::<Vec<_>>();
+
+        // the items of flex and grid containers aren't in flow, each of them is a context of its own
+        let style = self.tree.get_node_context(parent).and_then(|node| self.styles.get(*node));
+        if style.is_some_and(|style| style.display.is_flex_or_grid()) {
+            for child in children {
+                self.layout_context(child);
+            }
+            return;
+        }
+
+        let layout = *self.tree.get_final_layout(parent);
+        let content = content_box(&layout, origin);
+
+        // where a float would be if it wasn't floated
+        let mut cursor = content.y0;
+
+        for child in children {
+            let child_layout = *self.tree.get_final_layout(child);
+            let child_origin = origin + Vec2::new(child_layout.location.x as f64, child_layout.location.y as f64);
+
+            if let Some(run) = self.runs.get(&child).copied() {
+                for float in run.floats.iter() {
+                    self.place_float(*float, &layout, origin, content, child_origin.y, exclusions);
+                }
+
+                self.result.lines.insert(child, exclusions.relative_to(child_origin));
+                cursor = child_origin.y + child_layout.size.height as f64;
+                continue;
+            }
+
+            let Some(style) = self.tree.get_node_context(child).and_then(|node| self.styles.get(*node)) else {
+                continue;
+            };
+
+            if style.display == Display::None {
+                continue;
+            }
+
+            if is_float(style) {
+                if !self.in_runs.contains(&child) {
+                    self.place_float(child, &layout, origin, content, cursor, exclusions);
+                }
+                continue;
+            }
+
+            // inline content belongs to a run, and out of flow boxes don't see the floats around them
+            if style.position.is_out_of_flow() || style.display.is_inline_level() {
+                if establishes_formatting_context(style) {
+                    self.layout_context(child);
+                }
+                continue;
+            }
+
+            // the top of the block without the clearance of the previous pass
+            let applied = self.previous.clearance.get(&child).copied().unwrap_or_default();
+            let top = child_origin.y - applied;
+            let mut y = exclusions.clearance(style.clear).map_or(top, |bottom| top.max(bottom));
+
+            // the border box of a formatting context root doesn't overlap floats, it gets narrower
+            // next to them or moves below them when it doesn't fit
+            if establishes_formatting_context(style) {
+                let fixed_width = self.tree.style(child).is_ok_and(|s| s.size.width != Dimension::Auto);
+                let width = fixed_width.then_some(child_layout.size.width as f64);
+
+                let (below, from, to) = exclusions.avoid(y, child_layout.size.height as f64, width, content.x0, content.x1);
+                if from > content.x0 || to < content.x1 {
+                    self.result.insets.insert(child, (from - content.x0, content.x1 - to));
+                }
+                y = below;
+            }
+
+            if y > top {
+                self.result.clearance.insert(child, y - top);
+            }
+
+            if establishes_formatting_context(style) {
+                self.layout_context(child);
+            } else {
+                self.walk(child, child_origin, exclusions);
+            }
+
+            let margin = self.tree.style(child).map_or(0.0, |s| resolve(s.margin.bottom));
+            cursor = child_origin.y + child_layout.size.height as f64 + margin;
+        }
+    }
+
+    fn place_float(&mut self, float: TaffyID, parent: &Layout, origin: Vec2, content: Rect, top: f64, exclusions: &mut Exclusions) {
+        let Some(style) = self.tree.get_node_context(float).and_then(|node| self.styles.get(*node)) else {
+            return;
+        };
+
+        let size = self.tree.get_final_layout(float).size;
+        let margin = self.tree.style(float).map(|s| s.margin).ok();
+        let (horizontal, vertical) = margin.map_or((0.0, 0.0), |m| {
+            (resolve(m.left) + resolve(m.right), resolve(m.top) + resolve(m.bottom))
+        });
+
+        let top = exclusions.clearance(style.clear).map_or(top, |bottom| top.max(bottom));
+        let margin_box = (size.width as f64 + horizontal, size.height as f64 + vertical);
+        let rect = exclusions.place(style.float, margin_box, top, content.x0, content.x1);
+
+        // insets are relative to the padding box of the parent
+        let position = rect.origin() - origin - Vec2::new(parent.border.left as f64, parent.border.top as f64);
+        self.result.positions.insert(float, (position.x, position.y));
+
+        self.layout_context(float);
+    }
+}
+
+fn content_box(layout: &Layout, origin: Vec2) -> Rect {
+    let (border, padding) = (layout.border, layout.padding);
+
+    Rect::new(
+        origin.x + (border.left + padding.left) as f64,
+        origin.y + (border.top + padding.top) as f64,
+        origin.x + (layout.size.width - border.right - padding.right) as f64,
+        origin.y + (layout.size.height - border.bottom - padding.bottom) as f64,
+    )
+}
+
+fn resolve(length: LengthPercentageAuto) -> f64 {
+    match length {
+        LengthPercentageAuto::Length(px) => px as f64,
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn places_floats_side_by_side() {
+        let mut exclusions = Exclusions::default();
+
+        assert_eq!(
+            exclusions.place(Float::Left, (100.0, 50.0), 0.0, 0.0, 500.0),
+            Rect::new(0.0, 0.0, 100.0, 50.0)
+        );
+        assert_eq!(
+            exclusions.place(Float::Right, (100.0, 100.0), 0.0, 0.0, 500.0),
+            Rect::new(400.0, 0.0, 500.0, 100.0)
+        );
+        assert_eq!(
+            exclusions.place(Float::Left, (350.0, 10.0), 0.0, 0.0, 500.0),
+            Rect::new(0.0, 50.0, 350.0, 60.0)
+        );
+    }
+
+    #[test]
+    fn formatting_context_roots_avoid_floats() {
+        let mut exclusions = Exclusions::default();
+        exclusions.place(Float::Left, (100.0, 50.0), 0.0, 0.0, 500.0);
+        exclusions.place(Float::Right, (100.0, 100.0), 0.0, 0.0, 500.0);
+
+        // an auto width gets narrower next to the floats
+        assert_eq!(exclusions.avoid(0.0, 20.0, None, 0.0, 500.0), (0.0, 100.0, 400.0));
+        assert_eq!(exclusions.avoid(60.0, 20.0, None, 0.0, 500.0), (60.0, 0.0, 400.0));
+
+        // a fixed width moves down until it fits
+        assert_eq!(exclusions.avoid(0.0, 20.0, Some(350.0), 0.0, 500.0), (50.0, 0.0, 400.0));
+        assert_eq!(exclusions.avoid(0.0, 20.0, Some(450.0), 0.0, 500.0), (100.0, 0.0, 500.0));
+
+        // nothing to avoid below the floats
+        assert_eq!(exclusions.avoid(120.0, 20.0, Some(450.0), 0.0, 500.0), (120.0, 0.0, 500.0));
+    }
+}
@@ -0,0 +1,72 @@
+use gosub_html5::node::NodeId;
+use taffy::{NodeId as TaffyID, Overflow, Position, PrintTree, TaffyTree, TraversePartialTree};
+
+use crate::style::{ComputedStyle, ComputedStyles, Display, Float};
+
+/// Lays block-level boxes out in normal flow. Taffy's block layout stacks them vertically and
+/// collapses the margins of adjacent siblings, of a parent with its first and last child and
+/// through empty blocks, unless a box establishes a new block formatting context.
+pub fn apply_flow_styles(tree: &mut TaffyTree<NodeId>, root: TaffyID, styles: &ComputedStyles) {
+    let mut stack = vec![root];
+
+    while let Some(id) = stack.pop() {
+        stack.extend(tree.child_ids(id));
+
+        let Some(style) = tree.get_node_context(id).and_then(|gosub_id| styles.get(*gosub_id)) else {
+            continue;
+        };
+
+        let Ok(taffy_style) = tree.style(id) else {
+            continue;
+        };
+
+        let mut taffy_style = taffy_style.clone();
+        taffy_style.display = match style.display {
+            Display::None => taffy::Display::None,
+            Display::Flex | Display::InlineFlex => taffy::Display::Flex,
+            Display::Grid | Display::InlineGrid => taffy::Display::Grid,
+            // taffy has no boxes that only exist for their children, so they stay as generated
+            Display::Contents => taffy_style.display,
+            // inline boxes outside of inline content, like the items of a flex container, are
+            // blockified. Tables are laid out like blocks until there is a table layout.
+            _ => taffy::Display::Block,
+        };
+
+        // floats are taken out of flow and placed by the float layout, except in flex and grid
+        // containers which ignore them
+        let parent_display = tree.parent(id).and_then(|parent| tree.style(parent).ok()).map(|parent| parent.display);
+        let in_flow_layout = !matches!(parent_display, Some(taffy::Display::Flex | taffy::Display::Grid));
+        if is_float(style) && in_flow_layout {
+            taffy_style.position = Position::Absolute;
+        }
+
+        // taffy doesn't collapse margins through blocks that clip their content, which is the only
+        // way to tell it about a new formatting context. Taffy only collapses the margins of in flow
+        // children of blocks, which are also the only boxes whose automatic minimum size doesn't
+        // drop to zero for being a scroll container.
+        let collapses = matches!(taffy_style.overflow.y, Overflow::Visible | Overflow::Clip);
+        let in_block_flow = parent_display == Some(taffy::Display::Block) && taffy_style.position != Position::Absolute;
+        if taffy_style.display == taffy::Display::Block && in_block_flow && collapses && establishes_formatting_context(style) {
+            taffy_style.overflow.y = Overflow::Hidden;
+        }
+
+        if let Err(e) = tree.set_style(id, taffy_style) {
+            eprintln!("Failed to set flow styles: {e:?}");
+        }
+    }
+}
+
+/// Whether the margins of the box's children stay inside of it, see
+/// https://www.w3.org/TR/CSS2/visuren.html#block-formatting
+pub fn establishes_formatting_context(style: &ComputedStyle) -> bool {
+    style.display == Display::FlowRoot
+        || style.display.is_atomic_inline()
+        || style.display.is_flex_or_grid()
+        || style.position.is_out_of_flow()
+        || style.overflow.is_scroll_container()
+        || is_float(style)
+}
+
+pub fn is_float(style: &ComputedStyle) -> bool {
+    style.float != Float::None && !style.position.is_out_of_flow()
+}
@@ -0,0 +1,539 @@
+use std::collections::HashMap;
+
+use gosub_html5::node::NodeId;
+use taffy::style_helpers::{line, span};
+use taffy::{
+    GridAutoFlow, GridPlacement, GridTrackRepetition, LengthPercentage, Line, MaxTrackSizingFunction, MinTrackSizingFunction,
+    NodeId as TaffyID, NonRepeatedTrackSizingFunction, PrintTree, TaffyTree, TrackSizingFunction, TraversePartialTree,
+};
+
+use crate::css::{split_top_level, tokens};
+use crate::style::{ComputedStyles, ComputedValues, Display};
+use crate::units::{LayoutLength, LengthContext};
+
+/// The names of the lines of one axis of a grid, a name can be given to several lines
+#[derive(Debug, Clone, Default)]
+struct LineNames {
+    lines: HashMap<String, Vec<i16>>,
+}
+
+impl LineNames {
+    fn add(&mut self, name: &str, line: i16) {
+        self.lines.entry(name.to_string()).or_default().push(line);
+    }
+
+    /// The `nth` line with the name, counted from the end for negative `nth`
+    fn get(&self, name: &str, nth: i16) -> Option<i16> {
+        let lines = self.lines.get(name)?;
+
+        match nth {
+            n if n > 0 => lines.get(n as usize - 1).copied(),
+            n if n < 0 => lines.iter().rev().nth(n.unsigned_abs() as usize - 1).copied(),
+            _ => None,
+        }
+    }
+}
+
+/// The line names of both axes of a grid container, from its templates and areas
+#[derive(Debug, Clone, Default)]
+struct GridNames {
+    rows: LineNames,
+    columns: LineNames,
+}
+
+/// Translates the grid properties into the taffy styles, which lay grid containers out with
+/// taffy's grid algorithm. Taffy only knows line numbers, so named lines and areas are resolved
+/// into numbers here. `gap` also applies to flex containers.
+pub fn apply_grid_styles(tree: &mut TaffyTree<NodeId>, root: TaffyID, styles: &ComputedStyles) {
+    let mut names: HashMap<TaffyID, GridNames> = HashMap::new();
+    let mut stack = vec![root];
+
+    while let Some(id) = stack.pop() {
+        stack.extend(tree.child_ids(id));
+
+        let Some(gosub_id) = tree.get_node_context(id).copied() else {
+            continue;
+        };
+        let (Some(style), Some(values)) = (styles.get(gosub_id), styles.values(gosub_id)) else {
+            continue;
+        };
+        let Ok(taffy_style) = tree.style(id) else {
+            continue;
+        };
+
+        let ctx = styles.length_context(gosub_id);
+        let mut taffy_style = taffy_style.clone();
+
+        let (row_gap, column_gap) = gaps(values, &ctx);
+        if let Some(gap) = row_gap {
+            taffy_style.gap.height = gap;
+        }
+        if let Some(gap) = column_gap {
+            taffy_style.gap.width = gap;
+        }
+
+        if matches!(style.display, Display::Grid | Display::InlineGrid) {
+            let mut grid_names = GridNames::default();
+
+            if let Some(areas) = values.get("grid-template-areas") {
+                add_area_names(areas, &mut grid_names);
+            }
+            if let Some(rows) = values.get("grid-template-rows") {
+                taffy_style.grid_template_rows = parse_track_list(rows, &ctx, &mut grid_names.rows);
+            }
+            if let Some(columns) = values.get("grid-template-columns") {
+                taffy_style.grid_template_columns = parse_track_list(columns, &ctx, &mut grid_names.columns);
+            }
+            if let Some(rows) = values.get("grid-auto-rows") {
+                taffy_style.grid_auto_rows = tokens(rows).into_iter().filter_map(|t| parse_track_size(t, &ctx)).collect();
+            }
+            if let Some(columns) = values.get("grid-auto-columns") {
+                taffy_style.grid_auto_columns = tokens(columns).into_iter().filter_map(|t| parse_track_size(t, &ctx)).collect();
+            }
+            if let Some(flow) = values.get("grid-auto-flow").and_then(parse_auto_flow) {
+                taffy_style.grid_auto_flow = flow;
+            }
+
+            names.insert(id, grid_names);
+        }
+
+        // the placement of a grid item refers to the lines of its container
+        let container = tree.parent(id).and_then(|parent| names.get(&parent));
+        if let Some(container) = container {
+            let (row, column) = placement(values, container);
+            taffy_style.grid_row = row;
+            taffy_style.grid_column = column;
+        }
+
+        if let Err(e) = tree.set_style(id, taffy_style) {
+            eprintln!("Failed to set grid styles: {e:?}");
+        }
+    }
+}
+
+/// The row and column gaps from `gap` and its longhands, `grid-gap` is the legacy name
+fn gaps(values: &ComputedValues, ctx: &LengthContext) -> (Option<LengthPercentage>, Option<LengthPercentage>) {
+    let parse = |value: &str| LayoutLength::parse(value, ctx).and_then(LayoutLength::to_length_percentage);
+
+    let (mut row, mut column) = (None, None);
+    if let Some(gap) = values.get("gap").or_else(|| values.get("grid-gap")) {
+        let gap = tokens(gap);
+        row = gap.first().and_then(|g| parse(g));
+        column = gap.get(1).map_or(row, |g| parse(g));
+    }
+
+    if let Some(gap) = values.get("row-gap").or_else(|| values.get("grid-row-gap")).and_then(parse) {
+        row = Some(gap);
+    }
+    if let Some(gap) = values.get("column-gap").or_else(|| values.get("grid-column-gap")).and_then(parse) {
+        column = Some(gap);
+    }
+
+    (row, column)
+}
+
+fn parse_auto_flow(value: &str) -> Option<GridAutoFlow> {
+    let words = value.split_whitespace().collect::<Vec<_>>();
+    let dense = words.contains(&"dense");
+
+    Some(match (words.contains(&"column"), dense) {
+        (false, false) => GridAutoFlow::Row,
+        (false, true) => GridAutoFlow::RowDense,
+        (true, false) => GridAutoFlow::Column,
+        (true, true) => GridAutoFlow::ColumnDense,
+    })
+    .filter(|_| words.iter().all(|w| matches!(*w, "row" | "column" | "dense")))
+}
+
+/// Splits a track list into track sizes, `repeat()` and line names in brackets
+fn track_list_tokens(value: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut names: Option<String> = None;
+
+    for token in tokens(value) {
+        if let Some(names) = &mut names {
+            names.push(' ');
+            names.push_str(token);
+        } else if token.starts_with('[') {
+            names = Some(token.to_string());
+        } else {
+            result.push(token.to_string());
+        }
+
+        if names.as_deref().is_some_and(|n| n.ends_with(']')) {
+            result.extend(names.take());
+        }
+    }
+
+    result
+}
+
+/// The names in a `[name other-name]` token
+fn line_names(token: &str) -> Option<Vec<&str>> {
+    let names = token.strip_prefix('[')?.strip_suffix(']')?;
+    Some(names.split_whitespace().collect())
+}
+
+/// Parses `grid-template-rows` or `grid-template-columns`, adding the line names to `names`. Lines
+/// after a repetition that depends on the size of the grid can't be numbered, their names are lost,
+/// and so are the names of lines past the last line number an `i16` holds.
+fn parse_track_list(value: &str, ctx: &LengthContext, names: &mut LineNames) -> Vec<TrackSizingFunction> {
+    if value.trim() == "none" {
+        return Vec::new();
+    }
+
+    let mut tracks = Vec::new();
+    // the number of the next line, until an auto repetition makes it unknown
+    let mut line_number = Some(1i16);
+
+    for token in track_list_tokens(value) {
+        if let Some(line_names) = line_names(&token) {
+            if let Some(number) = line_number {
+                for name in line_names {
+                    names.add(name, number);
+                }
+            }
+            continue;
+        }
+
+        if let Some(arguments) = token.strip_prefix("repeat(").and_then(|t| t.strip_suffix(')')) {
+            let [count, track_list] = split_top_level(arguments, ',')[..] else {
+                continue;
+            };
+
+            let repetition = match count.trim() {
+                "auto-fill" => GridTrackRepetition::AutoFill,
+                "auto-fit" => GridTrackRepetition::AutoFit,
+                count => match count.parse::<u16>() {
+                    Ok(count) if count > 0 => GridTrackRepetition::Count(count),
+                    _ => continue,
+                },
+            };
+
+            let mut repeated = Vec::new();
+            let mut repeated_names = Vec::new();
+            for token in track_list_tokens(track_list) {
+                match line_names(&token) {
+                    Some(line_names) => repeated_names.push((repeated.len(), line_names.into_iter().map(str::to_string).collect::<Vec<_>>())),
+                    None => repeated.extend(parse_track_size(&token, ctx)),
+                }
+            }
+
+            if repeated.is_empty() {
+                continue;
+            }
+
+            line_number = match (repetition, line_number) {
+                (GridTrackRepetition::Count(count), Some(number)) => {
+                    // the line `offset` tracks into the `nth` repetition
+                    let line = |nth: u16, offset: usize| {
+                        let tracks = (nth as usize).checked_mul(repeated.len())?.checked_add(offset)?;
+                        number.checked_add(i16::try_from(tracks).ok()?)
+                    };
+
+                    'repetitions: for i in 0..count {
+                        for (offset, line_names) in repeated_names.iter() {
+                            let Some(line) = line(i, *offset) else {
+                                break 'repetitions;
+                            };
+
+                            for name in line_names {
+                                names.add(name, line);
+                            }
+                        }
+                    }
+                    line(count, 0)
+                }
+                _ => None,
+            };
+
+            tracks.push(TrackSizingFunction::Repeat(repetition, repeated));
+            continue;
+        }
+
+        if let Some(track) = parse_track_size(&token, ctx) {
+            tracks.push(TrackSizingFunction::Single(track));
+            line_number = line_number.and_then(|n| n.checked_add(1));
+        }
+    }
+
+    tracks
+}
+
+/// Parses a single track size like `1fr`, `100px`, `minmax(100px, 1fr)` or `fit-content(20em)`
+fn parse_track_size(value: &str, ctx: &LengthContext) -> Option<NonRepeatedTrackSizingFunction> {
+    let value = value.trim();
+
+    if let Some(arguments) = value.strip_prefix("minmax(").and_then(|v| v.strip_suffix(')')) {
+        let [min, max] = split_top_level(arguments, ',')[..] else {
+            return None;
+        };
+
+        return Some(NonRepeatedTrackSizingFunction {
+            min: parse_min_track(min, ctx)?,
+            max: parse_max_track(max, ctx)?,
+        });
+    }
+
+    if let Some(limit) = value.strip_prefix("fit-content(").and_then(|v| v.strip_suffix(')')) {
+        let limit = LayoutLength::parse(limit, ctx)?.to_length_percentage()?;
+
+        return Some(NonRepeatedTrackSizingFunction {
+            min: MinTrackSizingFunction::Auto,
+            max: MaxTrackSizingFunction::FitContent(limit),
+        });
+    }
+
+    let max = parse_max_track(value, ctx)?;
+    // flexible tracks are at least as large as their content, like `minmax(auto, 1fr)`
+    let min = parse_min_track(value, ctx).unwrap_or(MinTrackSizingFunction::Auto);
+
+    Some(NonRepeatedTrackSizingFunction { min, max })
+}
+
+fn parse_min_track(value: &str, ctx: &LengthContext) -> Option<MinTrackSizingFunction> {
+    Some(match value.trim() {
+        "auto" => MinTrackSizingFunction::Auto,
+        "min-content" => MinTrackSizingFunction::MinContent,
+        "max-content" => MinTrackSizingFunction::MaxContent,
+        value => MinTrackSizingFunction::Fixed(LayoutLength::parse(value, ctx)?.to_length_percentage()?),
+    })
+}
+
+fn parse_max_track(value: &str, ctx: &LengthContext) -> Option<MaxTrackSizingFunction> {
+    Some(match value.trim() {
+        "auto" => MaxTrackSizingFunction::Auto,
+        "min-content" => MaxTrackSizingFunction::MinContent,
+        "max-content" => MaxTrackSizingFunction::MaxContent,
+        value => match value.strip_suffix("fr").and_then(|fr| fr.parse::<f32>().ok()) {
+            Some(fr) if fr >= 0.0 => MaxTrackSizingFunction::Fraction(fr),
+            Some(_) => return None,
+            None => MaxTrackSizingFunction::Fixed(LayoutLength::parse(value, ctx)?.to_length_percentage()?),
+        },
+    })
+}
+
+/// Names the lines around each area of `grid-template-areas`, `name-start` and `name-end`
+fn add_area_names(value: &str, names: &mut GridNames) {
+    let rows = value
+        .split(['"', '\''])
+        .skip(1)
+        .step_by(2)
+        .map(|row| row.split_whitespace().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    // the rows and columns each area spans, `.` cells belong to no area
+    let mut areas: HashMap<&str, (usize, usize, usize, usize)> = HashMap::new();
+    for (r, row) in rows.iter().enumerate() {
+        for (c, cell) in row.iter().enumerate().filter(|(_, cell)| !cell.chars().all(|ch| ch == '.')) {
+            let area = areas.entry(*cell).or_insert((r, r, c, c));
+            *area = (area.0.min(r), area.1.max(r), area.2.min(c), area.3.max(c));
+        }
+    }
+
+    for (name, (first_row, last_row, first_column, last_column)) in areas {
+        // areas that reach past the last line number an `i16` holds get no names
+        let lines = [first_row + 1, last_row + 2, first_column + 1, last_column + 2].map(|line| i16::try_from(line).ok());
+        let [Some(row_start), Some(row_end), Some(column_start), Some(column_end)] = lines else {
+            continue;
+        };
+
+        names.rows.add(&format!("{name}-start"), row_start);
+        names.rows.add(&format!("{name}-end"), row_end);
+        names.columns.add(&format!("{name}-start"), column_start);
+        names.columns.add(&format!("{name}-end"), column_end);
+    }
+}
+
+/// The row and column placement of a grid item from `grid-area`, `grid-row`, `grid-column` and
+/// their longhands
+fn placement(values: &ComputedValues, names: &GridNames) -> (Line<GridPlacement>, Line<GridPlacement>) {
+    // a missing end line repeats the start line if it is a name, `grid-area` is
+    // row-start / column-start / row-end / column-end
+    let ident = |side: &Option<String>| side.clone().filter(|s| is_ident(s));
+    let mut sides: [Option<String>; 4] = Default::default();
+
+    if let Some(area) = values.get("grid-area") {
+        let parts = split_top_level(area, '/').into_iter().map(str::to_string).collect::<Vec<_>>();
+
+        let row_start = parts.first().cloned();
+        let column_start = parts.get(1).cloned().or_else(|| ident(&row_start));
+        let row_end = parts.get(2).cloned().or_else(|| ident(&row_start));
+        let column_end = parts.get(3).cloned().or_else(|| ident(&column_start));
+
+        sides = [row_start, column_start, row_end, column_end];
+    }
+
+    for (shorthand, (start, end)) in [("grid-row", (0, 2)), ("grid-column", (1, 3))] {
+        if let Some(value) = values.get(shorthand) {
+            let parts = split_top_level(value, '/').into_iter().map(str::to_string).collect::<Vec<_>>();
+            sides[start] = parts.first().cloned();
+            sides[end] = parts.get(1).cloned().or_else(|| ident(&sides[start]));
+        }
+    }
+
+    for (longhand, i) in [("grid-row-start", 0), ("grid-column-start", 1), ("grid-row-end", 2), ("grid-column-end", 3)] {
+        if let Some(value) = values.get(longhand) {
+            sides[i] = Some(value.to_string());
+        }
+    }
+
+    let resolve = |i: usize| {
+        let (lines, suffix) = match i {
+            0 => (&names.rows, "start"),
+            1 => (&names.columns, "start"),
+            2 => (&names.rows, "end"),
+            _ => (&names.columns, "end"),
+        };
+        sides[i].as_deref().map_or(GridPlacement::Auto, |value| parse_placement(value, lines, suffix))
+    };
+
+    (
+        Line {
+            start: resolve(0),
+            end: resolve(2),
+        },
+        Line {
+            start: resolve(1),
+            end: resolve(3),
+        },
+    )
+}
+
+fn is_ident(value: &str) -> bool {
+    let value = value.trim();
+    value != "auto" && value.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_' || c == '-') && value.parse::<i16>().is_err()
+}
+
+/// Parses a grid line like `2`, `-1`, `span 2`, `header` or `col 2`. A name refers to the
+/// `name-start` or `name-end` line of an area first.
+fn parse_placement(value: &str, names: &LineNames, suffix: &str) -> GridPlacement {
+    let words = value.split_whitespace().collect::<Vec<_>>();
+
+    let number = words.iter().find_map(|w| w.parse::<i16>().ok());
+    let name = words.iter().find(|w| is_ident(w) && **w != "span");
+
+    if words.contains(&"span") {
+        // spans to named lines would need the position of the item, they span one track
+        return match (number, name) {
+            (Some(count), None) if count > 0 => span(count as u16),
+            _ => span(1),
+        };
+    }
+
+    match (number, name) {
+        (Some(number), None) if number != 0 => line(number),
+        (nth, Some(name)) => {
+            let nth = nth.unwrap_or(1);
+            let implicit = (nth == 1).then(|| names.get(&format!("{name}-{suffix}"), 1)).flatten();
+
+            implicit.or_else(|| names.get(name, nth)).map_or(GridPlacement::Auto, line)
+        }
+        _ => GridPlacement::Auto,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(lines: &LineNames, name: &str) -> Vec<i16> {
+        lines.lines.get(name).cloned().unwrap_or_default()
+    }
+
+    #[test]
+    fn numbers_named_lines() {
+        let mut lines = LineNames::default();
+        let tracks = parse_track_list("[a] 100px [b c] 1fr [d]", &LengthContext::default(), &mut lines);
+
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(names(&lines, "a"), [1]);
+        assert_eq!(names(&lines, "b"), [2]);
+        assert_eq!(names(&lines, "c"), [2]);
+        assert_eq!(names(&lines, "d"), [3]);
+    }
+
+    #[test]
+    fn numbers_lines_in_repetitions() {
+        let mut lines = LineNames::default();
+        let tracks = parse_track_list("[start] repeat(3, [col] 10px) [end]", &LengthContext::default(), &mut lines);
+
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(names(&lines, "start"), [1]);
+        assert_eq!(names(&lines, "col"), [1, 2, 3]);
+        assert_eq!(names(&lines, "end"), [4]);
+        assert_eq!(lines.get("col", 2), Some(2));
+        assert_eq!(lines.get("col", -1), Some(3));
+        assert_eq!(lines.get("col", 4), None);
+    }
+
+    #[test]
+    fn loses_names_after_auto_repetitions() {
+        let mut lines = LineNames::default();
+        let tracks = parse_track_list("[first] repeat(auto-fill, 100px) [last]", &LengthContext::default(), &mut lines);
+
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(names(&lines, "first"), [1]);
+        assert_eq!(names(&lines, "last"), Vec::<i16>::new());
+    }
+
+    #[test]
+    fn drops_names_of_lines_out_of_range() {
+        let mut lines = LineNames::default();
+        let tracks = parse_track_list("repeat(20000, [a] 10px 10px) [b] 10px [c]", &LengthContext::default(), &mut lines);
+
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(names(&lines, "a").len(), 16384);
+        assert_eq!(names(&lines, "a").last(), Some(&32767));
+        assert_eq!(names(&lines, "b"), Vec::<i16>::new());
+        assert_eq!(names(&lines, "c"), Vec::<i16>::new());
+        assert_eq!(lines.get("a", i16::MIN), None);
+
+        let mut lines = LineNames::default();
+        parse_track_list("repeat(32766, 10px) [last] 10px [after]", &LengthContext::default(), &mut lines);
+        assert_eq!(names(&lines, "last"), [32767]);
+        assert_eq!(names(&lines, "after"), Vec::<i16>::new());
+    }
+
+    #[test]
+    fn names_the_lines_around_areas() {
+        let mut grid = GridNames::default();
+        add_area_names("\"head head side\" \"main main side\" \". foot foot\"", &mut grid);
+
+        assert_eq!(names(&grid.rows, "head-start"), [1]);
+        assert_eq!(names(&grid.rows, "head-end"), [2]);
+        assert_eq!(names(&grid.columns, "head-start"), [1]);
+        assert_eq!(names(&grid.columns, "head-end"), [3]);
+        assert_eq!(names(&grid.rows, "side-start"), [1]);
+        assert_eq!(names(&grid.rows, "side-end"), [3]);
+        assert_eq!(names(&grid.columns, "foot-start"), [2]);
+        assert_eq!(names(&grid.columns, "foot-end"), [4]);
+        assert!(grid.rows.lines.keys().all(|name| !name.starts_with('.')));
+    }
+
+    #[test]
+    fn places_items_on_lines() {
+        let mut lines = LineNames::default();
+        parse_track_list("[a] 1fr [b] 1fr [a] 1fr", &LengthContext::default(), &mut lines);
+
+        assert_eq!(parse_placement("2", &lines, "start"), line(2));
+        assert_eq!(parse_placement("-1", &lines, "start"), line(-1));
+        assert_eq!(parse_placement("span 2", &lines, "start"), span(2));
+        assert_eq!(parse_placement("b", &lines, "start"), line(2));
+        assert_eq!(parse_placement("a 2", &lines, "start"), line(3));
+        assert_eq!(parse_placement("2 a", &lines, "start"), line(3));
+        assert_eq!(parse_placement("missing", &lines, "start"), GridPlacement::Auto);
+        assert_eq!(parse_placement("0", &lines, "start"), GridPlacement::Auto);
+    }
+
+    #[test]
+    fn places_items_in_areas() {
+        let mut grid = GridNames::default();
+        add_area_names("\"x x y\" \"x x y\"", &mut grid);
+
+        assert_eq!(parse_placement("x", &grid.columns, "start"), line(1));
+        assert_eq!(parse_placement("x", &grid.columns, "end"), line(3));
+        assert_eq!(parse_placement("y", &grid.rows, "end"), line(3));
+        assert_eq!(parse_placement("x-end", &grid.columns, "start"), line(3));
+    }
+}
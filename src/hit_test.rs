@@ -0,0 +1,124 @@
+use gosub_html5::node::NodeId;
+use taffy::{NodeId as TaffyID, PrintTree, TaffyTree};
+use vello::kurbo::{Affine, Point, RoundedRect, Shape, Vec2};
+
+use crate::overflow::scrollbar_tracks;
+use crate::stacking::{PaintItem, PaintNode, StackingContext};
+use crate::style::{ComputedStyles, PointerEvents, Visibility};
+
+/// The topmost node under a point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub node: NodeId,
+    pub layout_id: TaffyID,
+    /// The point relative to the top left of the node's border box, before its transforms
+    pub local: Vec2,
+    /// Whether the point is on the scrollbars of the node rather than on its content
+    pub scrollbar: bool,
+}
+
+/// Finds the topmost node at `point`, by walking the stacking context tree in reverse paint order.
+/// `point` has to be in the same coordinate space the context was built for, so for hit testing
+/// in css pixels it should be built without the scale factor of the window.
+pub fn hit_test(
+    context: &StackingContext,
+    layout: &TaffyTree<NodeId>,
+    styles: &ComputedStyles,
+    point: Point,
+) -> Option<Hit> {
+    let tester = HitTester { layout, styles, point };
+    tester.context(context)
+}
+
+struct HitTester<'a> {
+    layout: &'a TaffyTree<NodeId>,
+    styles: &'a ComputedStyles,
+    point: Point,
+}
+
+impl HitTester<'_> {
+    fn context(&self, context: &StackingContext) -> Option<Hit> {
+        for item in context.items().rev() {
+            let hit = match item {
+                PaintItem::Node(node) => self.node(node),
+                PaintItem::Scrollbars(node, _) => self.scrollbars(node),
+                PaintItem::Context(context) => self.context(context),
+            };
+
+            if hit.is_some() {
+                return hit;
+            }
+        }
+
+        self.node(&context.root)
+    }
+
+    fn node(&self, node: &PaintNode) -> Option<Hit> {
+        let style = self.styles.get(node.gosub_id)?;
+
+        if style.pointer_events == PointerEvents::None || style.visibility != Visibility::Visible {
+            return None;
+        }
+
+        let local = self.local_point(node)?;
+        let border_box = node.border_box(self.layout);
+
+        let radius = style.border_radius(border_box.width());
+        if !RoundedRect::from_rect(border_box, radius).contains(local) {
+            return None;
+        }
+
+        Some(Hit {
+            node: node.gosub_id,
+            layout_id: node.id,
+            local: local - border_box.origin(),
+            scrollbar: false,
+        })
+    }
+
+    fn scrollbars(&self, node: &PaintNode) -> Option<Hit> {
+        let style = self.styles.get(node.gosub_id)?;
+
+        if style.pointer_events == PointerEvents::None || style.visibility != Visibility::Visible {
+            return None;
+        }
+
+        let local = self.local_point(node)?;
+
+        let node_layout = self.layout.get_final_layout(node.id);
+        let (vertical, horizontal) = scrollbar_tracks(node.padding_box(self.layout), node_layout, &style.overflow);
+
+        let on_track = [vertical, horizontal].into_iter().flatten().any(|track| track.contains(local));
+        if !on_track {
+            return None;
+        }
+
+        Some(Hit {
+            node: node.gosub_id,
+            layout_id: node.id,
+            local: local - node.border_box(self.layout).origin(),
+            scrollbar: true,
+        })
+    }
+
+    /// Maps the point into the untransformed space of the node, or `None` if one of the clips of its
+    /// ancestors hides the point
+    fn local_point(&self, node: &PaintNode) -> Option<Point> {
+        for clip in node.clips.iter() {
+            if !clip.shape.contains(invert(clip.transform)? * self.point) {
+                return None;
+            }
+        }
+
+        Some(invert(node.transform)? * self.point)
+    }
+}
+
+/// Transforms that collapse an axis, like `scale(0)`, can't be hit
+fn invert(transform: Affine) -> Option<Affine> {
+    if transform.determinant().abs() < f64::EPSILON {
+        return None;
+    }
+
+    Some(transform.inverse())
+}
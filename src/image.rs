@@ -1,9 +1,16 @@
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use image;
+use once_cell::sync::Lazy;
+use url::Url;
 use vello::peniko::{Blob, Format, Image};
 
+use crate::loader::fetch_bytes;
+
+/// The images shared by everything that paints, decoded once per source
+pub static IMAGE_CACHE: Lazy<Mutex<ImageCache>> = Lazy::new(Default::default);
+
 #[derive(Default)]
 pub struct ImageCache {
     images: HashMap<String, Image>,
@@ -20,6 +27,18 @@ impl ImageCache {
             Ok(image)
         }
     }
+
+    /// Loads an image from a http(s) or file url
+    pub fn load(&mut self, url: &Url) -> anyhow::Result<Image> {
+        if let Some(image) = self.images.get(url.as_str()) {
+            return Ok(image.clone());
+        }
+
+        let data = fetch_bytes(url)?;
+        let image = decode_image(&data)?;
+        self.images.insert(url.to_string(), image.clone());
+        Ok(image)
+    }
 }
 
 fn decode_image(data: &[u8]) -> anyhow::Result<Image> {
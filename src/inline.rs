@@ -0,0 +1,912 @@
+use std::collections::HashMap;
+
+use gosub_html5::node::NodeId;
+use gosub_styling::render_tree::{RenderNodeData, RenderTree};
+use taffy::{
+    AvailableSpace, Dimension, Display as TaffyDisplay, LengthPercentage, LengthPercentageAuto, NodeId as TaffyID,
+    Position as TaffyPosition, PrintTree, Rect as TaffyRect, Size, Style, TaffyTree, TraversePartialTree,
+};
+use vello::glyph::Glyph;
+use vello::kurbo::{Point, Rect, Vec2};
+
+use crate::float::Exclusions;
+use crate::style::{ComputedStyle, ComputedStyles, Display, Float, TextAlign, WhiteSpace};
+use crate::text::TextRenderer;
+
+/// The sizes of the margin boxes of atomic inlines, as taffy laid them out
+pub type AtomSizes = HashMap<TaffyID, (f64, f64)>;
+
+/// A box of an inline element or text node on one line, relative to its border box. Inline
+/// elements that are split over several lines only have their start edge on the first fragment
+/// and their end edge on the last.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fragment {
+    pub rect: Rect,
+    pub first: bool,
+    pub last: bool,
+}
+
+/// A run of inline-level children of a block container, laid out in line boxes. Taffy only sees
+/// an anonymous leaf in place of the run, which is measured by breaking the run into lines, while
+/// the boxes of the run are taken out of flow and placed where the lines put them.
+pub struct InlineContent {
+    /// The block container
+    pub parent: TaffyID,
+    /// The anonymous leaf that takes the place of the run in the layout of the parent
+    pub leaf: TaffyID,
+    /// The floats among the content, they are placed at the top of the run
+    pub floats: Vec<TaffyID>,
+    items: Vec<InlineItem>,
+    text_align: TextAlign,
+    /// The ascent and descent every line has at least, from the font of the block container
+    strut: (f64, f64),
+    /// The floats next to the lines, relative to the leaf
+    exclusions: Exclusions,
+}
+
+enum InlineItem {
+    Text(TextItem),
+    /// The start of an inline element
+    Open(OpenItem),
+    /// The end of an inline element, with the width of its right border and padding and its margin
+    Close { end: f64, margin: f64 },
+    /// An inline-block or other atomic inline, placed like a single glyph
+    Atom { id: TaffyID },
+    /// A `<br>`
+    Break { id: TaffyID },
+}
+
+struct TextItem {
+    id: TaffyID,
+    node: NodeId,
+    chars: Vec<TextChar>,
+    /// The number of glyphs of the prerendered text
+    glyphs: usize,
+    metrics: Metrics,
+    white_space: WhiteSpace,
+}
+
+#[derive(Clone, Copy)]
+struct TextChar {
+    /// The index of the glyph of the character in the prerendered text, line breaks have none
+    glyph: Option<usize>,
+    advance: f64,
+    kind: CharKind,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharKind {
+    Space,
+    Newline,
+    Other,
+}
+
+struct OpenItem {
+    id: TaffyID,
+    node: NodeId,
+    margin: f64,
+    /// The width of the left border and padding
+    start: f64,
+    border: Vec2,
+    /// The height of the top and bottom border and padding, which extend the fragments beyond the font
+    top: f64,
+    bottom: f64,
+    metrics: Metrics,
+}
+
+/// The vertical metrics of a font in a line box
+#[derive(Clone, Copy)]
+struct Metrics {
+    ascent: f64,
+    descent: f64,
+    /// Half of the leading the line height adds to the font, above and below it
+    half_leading: f64,
+}
+
+impl Metrics {
+    fn new(renderer: &TextRenderer, style: &ComputedStyle) -> Self {
+        let (ascent, descent) = (renderer.ascent as f64, renderer.descent as f64);
+
+        Self {
+            ascent,
+            descent,
+            half_leading: (style.font.line_height - (ascent + descent)) / 2.0,
+        }
+    }
+
+    /// The ascent and descent it contributes to the line box
+    fn line(&self) -> (f64, f64) {
+        (self.ascent + self.half_leading, self.descent + self.half_leading)
+    }
+}
+
+/// A piece of a line, the smallest unit line breaking works with
+#[derive(Clone, Copy)]
+enum Piece {
+    Glyph {
+        item: usize,
+        char: usize,
+        width: f64,
+        space: bool,
+        /// Collapsible spaces hang at the end of a line and vanish at its start
+        collapsible: bool,
+    },
+    Open { item: usize, width: f64 },
+    Close { end: f64, margin: f64 },
+    Atom { item: usize, width: f64 },
+}
+
+impl Piece {
+    fn width(&self) -> f64 {
+        match *self {
+            Piece::Glyph { width, .. } | Piece::Open { width, .. } | Piece::Atom { width, .. } => width,
+            Piece::Close { end, margin } => end + margin,
+        }
+    }
+
+    fn is_collapsible_space(&self) -> bool {
+        matches!(self, Piece::Glyph { collapsible: true, .. })
+    }
+
+    fn is_space(&self) -> bool {
+        matches!(self, Piece::Glyph { space: true, .. })
+    }
+}
+
+struct Line {
+    pieces: Vec<Piece>,
+    width: f64,
+    /// The width the line has next to the floats
+    limit: f64,
+    /// Ended by a `<br>` or a preserved newline, such a line is never justified
+    forced: bool,
+}
+
+impl Line {
+    fn new(limit: f64) -> Self {
+        Self {
+            pieces: Vec::new(),
+            width: 0.0,
+            limit,
+            forced: false,
+        }
+    }
+
+    /// Lines without anything but collapsed spaces take no room
+    fn has_content(&self) -> bool {
+        self.pieces.iter().any(|p| !p.is_collapsible_space() && (p.width() > 0.0 || matches!(p, Piece::Glyph { .. } | Piece::Atom { .. })))
+    }
+}
+
+/// The lines of an inline run laid out at a width, in coordinates of the anonymous leaf
+#[derive(Debug, Default)]
+struct InlineLayout {
+    width: f64,
+    height: f64,
+    /// The position of every glyph of the text nodes, with the baseline as y
+    glyphs: HashMap<TaffyID, Vec<Point>>,
+    /// The border boxes of the text nodes and inline elements, one per line they are on
+    fragments: HashMap<TaffyID, Vec<Fragment>>,
+    /// The top left of the margin boxes of atomic inlines
+    atoms: HashMap<TaffyID, Point>,
+}
+
+/// Finds the runs of inline content of every block container in the tree and replaces them with
+/// anonymous leaves. The leaves of the `previous` runs are removed first.
+pub fn build_inline_content(
+    tree: &mut TaffyTree<NodeId>,
+    root: TaffyID,
+    render_tree: &RenderTree,
+    styles: &ComputedStyles,
+    previous: Vec<InlineContent>,
+) -> Vec<InlineContent> {
+    for content in previous {
+        if let Err(e) = tree.remove(content.leaf) {
+            eprintln!("Failed to remove inline leaf: {e:?}");
+        }
+    }
+
+    let mut containers = Vec::new();
+    let mut stack = vec![root];
+    while let Some(id) = stack.pop() {
+        stack.extend(tree.child_ids(id));
+        if is_block_container(tree, styles, id) {
+            containers.push(id);
+        }
+    }
+
+    let mut builder = ItemBuilder {
+        render_tree,
+        styles,
+        renderers: HashMap::new(),
+    };
+
+    let mut contents = Vec::new();
+    for parent in containers {
+        for run in inline_runs(tree, render_tree, styles, parent) {
+            if let Some(content) = builder.build(tree, parent, run) {
+                contents.push(content);
+            }
+        }
+    }
+
+    contents
+}
+
+fn is_block_container(tree: &TaffyTree<NodeId>, styles: &ComputedStyles, id: TaffyID) -> bool {
+    let style = tree.get_node_context(id).and_then(|node| styles.get(*node));
+
+    // the root of the tree has no style
+    style.map_or(true, |style| {
+        matches!(style.display, Display::Block | Display::InlineBlock | Display::FlowRoot | Display::ListItem)
+    })
+}
+
+enum Flow {
+    Inline,
+    /// Floats are placed at the top of the run they are in
+    Float,
+    /// Out of flow boxes don't end a run of inline content
+    OutOfFlow,
+    Block,
+}
+
+fn flow(tree: &TaffyTree<NodeId>, render_tree: &RenderTree, styles: &ComputedStyles, id: TaffyID) -> Flow {
+    let Some(node) = tree.get_node_context(id) else {
+        return Flow::Block;
+    };
+
+    if matches!(render_tree.get_node(*node).map(|n| &n.data), Some(RenderNodeData::Text(_))) {
+        return Flow::Inline;
+    }
+
+    match styles.get(*node) {
+        Some(style) if style.display == Display::None || style.position.is_out_of_flow() => Flow::OutOfFlow,
+        Some(style) if style.float != Float::None => Flow::Float,
+        Some(style) if style.display.is_inline_level() => Flow::Inline,
+        _ => Flow::Block,
+    }
+}
+
+#[derive(Default)]
+struct Run {
+    nodes: Vec<TaffyID>,
+    floats: Vec<TaffyID>,
+}
+
+/// The consecutive inline-level children of a block container
+fn inline_runs(tree: &TaffyTree<NodeId>, render_tree: &RenderTree, styles: &ComputedStyles, parent: TaffyID) -> Vec<Run> {
+    let mut runs = vec![Run::default()];
+
+    for child in tree.child_ids(parent) {
+        let run = runs.last_mut().expect("there is always a run");
+
+        match flow(tree, render_tree, styles, child) {
+            Flow::Inline => run.nodes.push(child),
+            // floats before any content are placed by the block flow
+            Flow::Float if !run.nodes.is_empty() => run.floats.push(child),
+            Flow::Float | Flow::OutOfFlow => {}
+            Flow::Block => runs.push(Run::default()),
+        }
+    }
+
+    runs.retain(|run| !run.nodes.is_empty());
+    runs
+}
+
+struct ItemBuilder<'a> {
+    render_tree: &'a RenderTree,
+    styles: &'a ComputedStyles,
+    renderers: HashMap<(Vec<String>, u64), TextRenderer>,
+}
+
+impl ItemBuilder<'_> {
+    fn renderer(&mut self, style: &ComputedStyle) -> &TextRenderer {
+        self.renderers
+            .entry((style.font.families.clone(), style.font.size.to_bits()))
+            .or_insert_with(|| TextRenderer::new(style.font.families.clone(), style.font.size as f32))
+    }
+
+    fn style(&self, tree: &TaffyTree<NodeId>, id: TaffyID) -> Option<&ComputedStyle> {
+        tree.get_node_context(id).and_then(|node| self.styles.get(*node))
+    }
+
+    fn build(&mut self, tree: &mut TaffyTree<NodeId>, parent: TaffyID, run: Run) -> Option<InlineContent> {
+        let mut items = Vec::new();
+        for id in run.nodes.iter() {
+            self.collect(tree, *id, &mut items);
+        }
+
+        // white space between blocks is not rendered at all
+        let collapsed = items.iter().all(|item| match item {
+            InlineItem::Text(text) => text.white_space.collapses_spaces() && text.chars.iter().all(|c| c.kind != CharKind::Other),
+            _ => false,
+        });
+        if collapsed {
+            for id in run.nodes {
+                set_display(tree, id, TaffyDisplay::None);
+            }
+            return None;
+        }
+
+        let parent_style = self.style(tree, parent).cloned().unwrap_or_default();
+        let strut = Metrics::new(self.renderer(&parent_style), &parent_style).line();
+
+        let index = tree.child_ids(parent).position(|child| child == run.nodes[0]).unwrap_or(0);
+        let leaf = tree.new_leaf(Style::default()).ok()?;
+        if let Err(e) = tree.insert_child_at_index(parent, index, leaf) {
+            eprintln!("Failed to insert inline leaf: {e:?}");
+            return None;
+        }
+
+        for id in run.nodes {
+            take_out_of_flow(tree, id);
+        }
+        set_display(tree, parent, TaffyDisplay::Block);
+
+        Some(InlineContent {
+            parent,
+            leaf,
+            floats: run.floats,
+            items,
+            text_align: parent_style.text_align,
+            strut,
+            exclusions: Exclusions::default(),
+        })
+    }
+
+    /// Flattens an inline-level box into items
+    fn collect(&mut self, tree: &TaffyTree<NodeId>, id: TaffyID, items: &mut Vec<InlineItem>) {
+        let Some(node) = tree.get_node_context(id).copied() else {
+            return;
+        };
+        let Some(style) = self.styles.get(node).cloned() else {
+            return;
+        };
+        let render_tree = self.render_tree;
+        let Some(render_node) = render_tree.get_node(node) else {
+            return;
+        };
+
+        match &render_node.data {
+            RenderNodeData::Text(text) => {
+                let item = self.text_item(id, node, &text.text, &style);
+                items.push(InlineItem::Text(item));
+            }
+            _ if style.display == Display::None => {}
+            RenderNodeData::Element(element) if element.name == "br" => items.push(InlineItem::Break { id }),
+            _ if style.display.is_atomic_inline() => items.push(InlineItem::Atom { id }),
+            _ => {
+                let Ok(taffy_style) = tree.style(id) else {
+                    return;
+                };
+                let (margin, padding, border) = (taffy_style.margin, taffy_style.padding, taffy_style.border);
+
+                let metrics = Metrics::new(self.renderer(&style), &style);
+                items.push(InlineItem::Open(OpenItem {
+                    id,
+                    node,
+                    margin: resolve_auto(margin.left),
+                    start: resolve(border.left) + resolve(padding.left),
+                    border: Vec2::new(resolve(border.left), resolve(border.top)),
+                    top: resolve(border.top) + resolve(padding.top),
+                    bottom: resolve(border.bottom) + resolve(padding.bottom),
+                    metrics,
+                }));
+
+                for child in tree.child_ids(id) {
+                    if matches!(flow(tree, render_tree, self.styles, child), Flow::Inline) {
+                        self.collect(tree, child, items);
+                    }
+                }
+
+                items.push(InlineItem::Close {
+                    end: resolve(border.right) + resolve(padding.right),
+                    margin: resolve_auto(margin.right),
+                });
+            }
+        }
+    }
+
+    fn text_item(&mut self, id: TaffyID, node: NodeId, text: &str, style: &ComputedStyle) -> TextItem {
+        let renderer = self.renderer(style);
+        let space = renderer.char_advance(' ') as f64;
+
+        let mut glyphs = 0;
+        let chars = text
+            .chars()
+            .map(|c| {
+                let kind = match c {
+                    '\n' => CharKind::Newline,
+                    ' ' | '\t' | '\r' => CharKind::Space,
+                    _ => CharKind::Other,
+                };
+
+                // shaping gives each char but newlines a glyph, `place_glyphs` falls back when not
+                let glyph = (c != '\n').then(|| {
+                    glyphs += 1;
+                    glyphs - 1
+                });
+
+                // a line break that collapses into a space is as wide as one
+                let advance = match kind {
+                    CharKind::Newline => space + style.word_spacing,
+                    CharKind::Space => space + style.word_spacing,
+                    CharKind::Other => renderer.char_advance(c) as f64,
+                };
+
+                TextChar {
+                    glyph,
+                    advance: advance + style.letter_spacing,
+                    kind,
+                }
+            })
+            .collect();
+
+        TextItem {
+            id,
+            node,
+            chars,
+            glyphs,
+            metrics: Metrics::new(renderer, style),
+            white_space: style.white_space,
+        }
+    }
+}
+
+/// Moves the shaped glyphs of a text to the positions the lines put its chars at, relative to the
+/// box of the text at `origin`. That takes one glyph per char, when shaping merged or split chars
+/// the glyphs can't be told apart and stay as shaped, moved to the first position.
+fn place_glyphs(shaped: &mut [Glyph], positions: &[Point], origin: Point) {
+    if shaped.len() == positions.len() {
+        for (glyph, position) in shaped.iter_mut().zip(positions) {
+            glyph.x = (position.x - origin.x) as f32;
+            glyph.y = (position.y - origin.y) as f32;
+        }
+        return;
+    }
+
+    let (Some(first), Some(position)) = (shaped.first(), positions.first()) else {
+        return;
+    };
+    let dx = (position.x - origin.x) as f32 - first.x;
+    let dy = (position.y - origin.y) as f32 - first.y;
+
+    for glyph in shaped.iter_mut() {
+        glyph.x += dx;
+        glyph.y += dy;
+    }
+}
+
+fn resolve(length: LengthPercentage) -> f64 {
+    match length {
+        LengthPercentage::Length(px) => px as f64,
+        LengthPercentage::Percent(_) => 0.0,
+    }
+}
+
+fn resolve_auto(length: LengthPercentageAuto) -> f64 {
+    match length {
+        LengthPercentageAuto::Length(px) => px as f64,
+        _ => 0.0,
+    }
+}
+
+fn set_display(tree: &mut TaffyTree<NodeId>, id: TaffyID, display: TaffyDisplay) {
+    update_style(tree, id, |style| style.display = display);
+}
+
+fn take_out_of_flow(tree: &mut TaffyTree<NodeId>, id: TaffyID) {
+    update_style(tree, id, |style| style.position = TaffyPosition::Absolute);
+}
+
+fn update_style(tree: &mut TaffyTree<NodeId>, id: TaffyID, update: impl FnOnce(&mut Style)) {
+    let Ok(style) = tree.style(id) else {
+        return;
+    };
+
+    let mut style = style.clone();
+    update(&mut style);
+
+    if let Err(e) = tree.set_style(id, style) {
+        eprintln!("Failed to set style: {e:?}");
+    }
+}
+
+/// Places an out of flow box at `position` relative to the padding box of its parent
+fn place_box(tree: &mut TaffyTree<NodeId>, id: TaffyID, position: Point, size: Option<(f64, f64)>) {
+    update_style(tree, id, |style| {
+        style.position = TaffyPosition::Absolute;
+        style.inset = TaffyRect {
+            left: LengthPercentageAuto::Length(position.x as f32),
+            top: LengthPercentageAuto::Length(position.y as f32),
+            right: LengthPercentageAuto::Auto,
+            bottom: LengthPercentageAuto::Auto,
+        };
+
+        if let Some((width, height)) = size {
+            style.size = Size {
+                width: Dimension::Length(width as f32),
+                height: Dimension::Length(height as f32),
+            };
+            style.min_size = Size::auto();
+            style.max_size = Size::auto();
+        }
+    });
+}
+
+/// The margin box sizes of the atomic inlines of all runs after a layout pass
+pub fn atom_sizes(tree: &TaffyTree<NodeId>, contents: &[InlineContent]) -> AtomSizes {
+    let mut sizes = AtomSizes::new();
+
+    for item in contents.iter().flat_map(|c| c.items.iter()) {
+        let InlineItem::Atom { id } = item else {
+            continue;
+        };
+
+        let size = tree.get_final_layout(*id).size;
+        let margin = tree.style(*id).map(|s| s.margin).ok();
+        let (horizontal, vertical) = margin.map_or((0.0, 0.0), |m| {
+            (resolve_auto(m.left) + resolve_auto(m.right), resolve_auto(m.top) + resolve_auto(m.bottom))
+        });
+
+        sizes.insert(*id, (size.width as f64 + horizontal, size.height as f64 + vertical));
+    }
+
+    sizes
+}
+
+impl InlineContent {
+    /// Sets the floats the lines flow around in the next layout pass
+    pub fn set_exclusions(&mut self, exclusions: Exclusions) {
+        self.exclusions = exclusions;
+    }
+
+    /// The size of the lines for the measure function of the anonymous leaf
+    pub fn measure(&self, known: Size<Option<f32>>, available: Size<AvailableSpace>, atoms: &AtomSizes) -> Size<f32> {
+        let width = known.width.map(f64::from).unwrap_or(match available.width {
+            AvailableSpace::Definite(width) => width as f64,
+            AvailableSpace::MinContent => 0.0,
+            AvailableSpace::MaxContent => f64::INFINITY,
+        });
+
+        let layout = self.layout(width, atoms);
+
+        Size {
+            width: known.width.unwrap_or(layout.width as f32),
+            height: known.height.unwrap_or(layout.height as f32),
+        }
+    }
+
+    /// Breaks the run into lines at the final width of the leaf and moves its boxes and glyphs
+    /// there. The fragments of the inline elements are added to `fragments`.
+    pub fn place(
+        &self,
+        tree: &mut TaffyTree<NodeId>,
+        render_tree: &mut RenderTree,
+        atoms: &AtomSizes,
+        fragments: &mut HashMap<NodeId, Vec<Fragment>>,
+    ) {
+        let leaf = *tree.get_final_layout(self.leaf);
+        let parent_border = tree.get_final_layout(self.parent).border;
+        let layout = self.layout(leaf.size.width as f64, atoms);
+
+        let bounds = |id: TaffyID| {
+            layout
+                .fragments
+                .get(&id)
+                .and_then(|f| f.iter().map(|f| f.rect).reduce(|a, b| a.union(b)))
+                .unwrap_or_default()
+        };
+
+        // maps leaf coordinates to the padding box of the parent of the current item
+        let block_offset = Vec2::new(
+            (leaf.location.x - parent_border.left) as f64,
+            (leaf.location.y - parent_border.top) as f64,
+        );
+        let mut offsets = vec![block_offset];
+
+        for item in self.items.iter() {
+            let offset = *offsets.last().expect("the block is never closed");
+
+            match item {
+                InlineItem::Text(text) => {
+                    let rect = bounds(text.id);
+                    place_box(tree, text.id, rect.origin() + offset, Some((rect.width(), rect.height())));
+
+                    if let (Some(glyphs), Some(node)) = (layout.glyphs.get(&text.id), render_tree.nodes.get_mut(&text.node)) {
+                        if let RenderNodeData::Text(prerendered) = &mut node.data {
+                            place_glyphs(&mut prerendered.glyphs, glyphs, rect.origin());
+                        }
+                    }
+                }
+                InlineItem::Open(open) => {
+                    let rect = bounds(open.id);
+
+                    // taffy adds the top and left margins to the inset
+                    let margin = tree.style(open.id).map(|s| Vec2::new(resolve_auto(s.margin.left), resolve_auto(s.margin.top))).unwrap_or_default();
+                    place_box(tree, open.id, rect.origin() + offset - margin, Some((rect.width(), rect.height())));
+
+                    if let Some(boxes) = layout.fragments.get(&open.id) {
+                        let boxes = boxes.iter().map(|f| Fragment { rect: f.rect - rect.origin().to_vec2(), ..*f });
+                        fragments.insert(open.node, boxes.collect());
+                    }
+
+                    offsets.push(-(rect.origin().to_vec2() + open.border));
+                }
+                InlineItem::Close { .. } => {
+                    offsets.pop();
+                }
+                InlineItem::Atom { id } => {
+                    let position = layout.atoms.get(id).copied().unwrap_or_default();
+                    place_box(tree, *id, position + offset, None);
+                }
+                InlineItem::Break { id } => set_display(tree, *id, TaffyDisplay::None),
+            }
+        }
+    }
+
+    fn layout(&self, width: f64, atoms: &AtomSizes) -> InlineLayout {
+        let lines = self.break_lines(width, atoms);
+        let mut layout = InlineLayout::default();
+
+        // inline elements that are still open at the start of a line, with the x of their fragment
+        let mut open: Vec<(usize, f64, bool)> = Vec::new();
+        let mut y = 0.0;
+
+        for (i, line) in lines.iter().enumerate() {
+            let has_content = line.has_content();
+
+            // the line box fits the strut and everything on the line, aligned at the baseline
+            let (mut ascent, mut descent) = self.strut;
+            let mut fit = |(a, d): (f64, f64)| {
+                ascent = f64::max(ascent, a);
+                descent = f64::max(descent, d);
+            };
+            for (item, _, _) in open.iter() {
+                fit(self.metrics(*item).line());
+            }
+            for piece in line.pieces.iter() {
+                match *piece {
+                    Piece::Glyph { item, .. } | Piece::Open { item, .. } => fit(self.metrics(item).line()),
+                    Piece::Atom { item, .. } => {
+                        let InlineItem::Atom { id } = &self.items[item] else { continue };
+                        fit((atoms.get(id).map_or(0.0, |s| s.1), 0.0));
+                    }
+                    Piece::Close { .. } => {}
+                }
+            }
+            if !has_content && !line.forced {
+                (ascent, descent) = (0.0, 0.0);
+            }
+            let baseline = y + ascent;
+
+            // collapsible spaces at the end of the line hang and are not aligned
+            let trailing: f64 = line.pieces.iter().rev().take_while(|p| p.is_collapsible_space()).map(Piece::width).sum();
+            let content_width = line.width - trailing;
+
+            // the line box is shortened by the floats next to it
+            let (left, right) = self.exclusions.available(y, y + ascent + descent, 0.0, width);
+            layout.width = layout.width.max(left + content_width);
+
+            let free = if right.is_finite() { (right - left - content_width).max(0.0) } else { 0.0 };
+            let last = i + 1 == lines.len();
+            let (offset, justify) = match self.text_align {
+                TextAlign::Start | TextAlign::Left => (0.0, 0.0),
+                TextAlign::End | TextAlign::Right => (free, 0.0),
+                TextAlign::Center => (free / 2.0, 0.0),
+                TextAlign::Justify if last || line.forced => (0.0, 0.0),
+                TextAlign::Justify => {
+                    let hanging = line.pieces.iter().rev().take_while(|p| p.is_collapsible_space()).count();
+                    let spaces = line.pieces[..line.pieces.len() - hanging].iter().filter(|p| p.is_space() && p.width() > 0.0).count();
+                    (0.0, if spaces > 0 { free / spaces as f64 } else { 0.0 })
+                }
+            };
+            let mut x = left + offset;
+
+            for (_, start, _) in open.iter_mut() {
+                *start = x;
+            }
+
+            for piece in line.pieces.iter() {
+                match *piece {
+                    Piece::Glyph { item, char, width, space, .. } => {
+                        let InlineItem::Text(text) = &self.items[item] else { continue };
+                        let glyphs = layout.glyphs.entry(text.id).or_insert_with(|| vec![Point::ZERO; text.glyphs]);
+                        if let Some(glyph) = text.chars[char].glyph {
+                            glyphs[glyph] = Point::new(x, baseline);
+                        }
+
+                        let extra = if space && width > 0.0 { justify } else { 0.0 };
+                        let rect = Rect::new(x, baseline - text.metrics.ascent, x + width + extra, baseline + text.metrics.descent);
+                        let fragments = layout.fragments.entry(text.id).or_default();
+                        match fragments.last_mut() {
+                            Some(fragment) if fragment.rect.y0 == rect.y0 => fragment.rect = fragment.rect.union(rect),
+                            _ => fragments.push(Fragment { rect, first: true, last: true }),
+                        }
+
+                        x += width + extra;
+                    }
+                    Piece::Open { item, width } => {
+                        let InlineItem::Open(element) = &self.items[item] else { continue };
+                        x += element.margin;
+                        open.push((item, x, true));
+                        x += width - element.margin;
+                    }
+                    Piece::Close { end, margin } => {
+                        x += end;
+                        if let Some((item, start, first)) = open.pop() {
+                            self.push_fragment(&mut layout, item, start, x, baseline, first, true);
+                        }
+                        x += margin;
+                    }
+                    Piece::Atom { item, width } => {
+                        let InlineItem::Atom { id } = &self.items[item] else { continue };
+                        let height = atoms.get(id).map_or(0.0, |s| s.1);
+                        layout.atoms.insert(*id, Point::new(x, baseline - height));
+                        x += width;
+                    }
+                }
+            }
+
+            // elements that continue on the next line
+            for (item, start, first) in open.iter_mut() {
+                self.push_fragment(&mut layout, *item, *start, x, baseline, *first, false);
+                *first = false;
+            }
+
+            y += ascent + descent;
+        }
+
+        layout.height = y;
+        layout
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn push_fragment(&self, layout: &mut InlineLayout, item: usize, start: f64, end: f64, baseline: f64, first: bool, last: bool) {
+        let InlineItem::Open(element) = &self.items[item] else {
+            return;
+        };
+
+        // the border box is the content area of the font with the padding and borders around it
+        let rect = Rect::new(
+            start,
+            baseline - element.metrics.ascent - element.top,
+            end,
+            baseline + element.metrics.descent + element.bottom,
+        );
+
+        layout.fragments.entry(element.id).or_default().push(Fragment { rect, first, last });
+    }
+
+    fn metrics(&self, item: usize) -> Metrics {
+        match &self.items[item] {
+            InlineItem::Text(text) => text.metrics,
+            InlineItem::Open(open) => open.metrics,
+            _ => Metrics {
+                ascent: self.strut.0,
+                descent: self.strut.1,
+                half_leading: 0.0,
+            },
+        }
+    }
+
+    /// Greedy line breaking, lines break after spaces and around atomic inlines
+    fn break_lines(&self, width: f64, atoms: &AtomSizes) -> Vec<Line> {
+        // lines are as high as the strut until they are laid out, which is close enough to find
+        // the floats next to them
+        let line_height = self.strut.0 + self.strut.1;
+        let limit = |line: usize| {
+            let top = line as f64 * line_height;
+            let (left, right) = self.exclusions.available(top, top + line_height, 0.0, width);
+            right - left
+        };
+
+        let mut lines = vec![Line::new(limit(0))];
+        let mut word: Vec<Piece> = Vec::new();
+        // a collapsible space here would follow another space or start the line
+        let mut collapse = true;
+
+        for (i, item) in self.items.iter().enumerate() {
+            match item {
+                InlineItem::Open(open) => word.push(Piece::Open {
+                    item: i,
+                    width: open.margin + open.start,
+                }),
+                InlineItem::Close { end, margin } => word.push(Piece::Close { end: *end, margin: *margin }),
+                InlineItem::Atom { id } => {
+                    flush(&mut lines, &mut word, &limit);
+                    let width_of_atom = atoms.get(id).map_or(0.0, |s| s.0);
+                    word.push(Piece::Atom { item: i, width: width_of_atom });
+                    flush(&mut lines, &mut word, &limit);
+                    collapse = false;
+                }
+                InlineItem::Break { .. } => {
+                    flush(&mut lines, &mut word, &limit);
+                    force_break(&mut lines, &limit);
+                    collapse = true;
+                }
+                InlineItem::Text(text) => {
+                    let collapses = text.white_space.collapses_spaces();
+
+                    for (c, ch) in text.chars.iter().enumerate() {
+                        let glyph = |width: f64, space: bool, collapsible: bool| Piece::Glyph {
+                            item: i,
+                            char: c,
+                            width,
+                            space,
+                            collapsible,
+                        };
+
+                        match ch.kind {
+                            CharKind::Newline if text.white_space.preserves_newlines() => {
+                                word.push(glyph(0.0, true, true));
+                                flush(&mut lines, &mut word, &limit);
+                                force_break(&mut lines, &limit);
+                                collapse = true;
+                            }
+                            CharKind::Space | CharKind::Newline => {
+                                let width_of_space = if collapses && collapse { 0.0 } else { ch.advance };
+                                word.push(glyph(width_of_space, true, collapses));
+                                collapse = collapses;
+
+                                if text.white_space.wraps() {
+                                    flush(&mut lines, &mut word, &limit);
+                                }
+                            }
+                            CharKind::Other => {
+                                word.push(glyph(ch.advance, false, false));
+                                collapse = false;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        flush(&mut lines, &mut word, &limit);
+
+        // a break at the very end doesn't start another line
+        if lines.len() > 1 && lines.last().is_some_and(|l| l.pieces.is_empty()) {
+            lines.pop();
+        }
+
+        lines
+    }
+}
+
+/// Adds a word to the current line, or to a new line if it doesn't fit
+fn flush(lines: &mut Vec<Line>, word: &mut Vec<Piece>, limit: &dyn Fn(usize) -> f64) {
+    if word.is_empty() {
+        return;
+    }
+
+    let total: f64 = word.iter().map(Piece::width).sum();
+    let trailing: f64 = word.iter().rev().take_while(|p| p.is_collapsible_space()).map(Piece::width).sum();
+
+    let line = lines.last().expect("there is always a line");
+    if line.has_content() && line.width + total - trailing > line.limit + 0.01 {
+        lines.push(Line::new(limit(lines.len())));
+    }
+
+    let line = lines.last_mut().expect("there is always a line");
+    for mut piece in word.drain(..) {
+        // collapsible spaces at the start of a line are removed
+        let at_start = !line.has_content();
+        if let Piece::Glyph { width, collapsible: true, .. } = &mut piece {
+            if at_start {
+                *width = 0.0;
+            }
+        }
+
+        line.width += piece.width();
+        line.pieces.push(piece);
+    }
+}
+
+fn force_break(lines: &mut Vec<Line>, limit: &dyn Fn(usize) -> f64) {
+    if let Some(line) = lines.last_mut() {
+        line.forced = true;
+    }
+    lines.push(Line::new(limit(lines.len())));
+}
@@ -0,0 +1,261 @@
+use vello::kurbo::{Affine, Point, Vec2};
+use winit::event::{ElementState, Ime, MouseScrollDelta, TouchPhase as WinitTouchPhase, WindowEvent};
+use winit::keyboard::ModifiersState;
+
+pub use winit::keyboard::{Key, KeyCode, NamedKey, PhysicalKey};
+
+/// Height of a line for wheels that scroll by lines, in css pixels
+pub const LINE_HEIGHT: f64 = 40.0;
+
+/// Input from the window, all positions are in css pixels relative to the top left of the viewport
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputEvent {
+    CursorMoved {
+        position: Point,
+    },
+    CursorEntered,
+    CursorLeft,
+    MouseButton {
+        button: MouseButton,
+        state: ButtonState,
+        position: Point,
+        modifiers: Modifiers,
+    },
+    Wheel {
+        /// The distance to scroll the content by, positive values scroll down and to the right
+        delta: Vec2,
+        position: Point,
+        modifiers: Modifiers,
+    },
+    /// A pinch gesture on a touchpad, `scale` is the factor to zoom by
+    Pinch {
+        scale: f64,
+        position: Point,
+    },
+    Key(KeyEvent),
+    ModifiersChanged(Modifiers),
+    Ime(ImeEvent),
+    Touch {
+        id: u64,
+        phase: TouchPhase,
+        position: Point,
+    },
+    /// The window gained or lost keyboard focus
+    Focus(bool),
+}
+
+impl InputEvent {
+    /// The event with its positions and scroll distances mapped through `transform`
+    pub fn transformed(&self, transform: Affine) -> InputEvent {
+        let mut event = self.clone();
+
+        match &mut event {
+            InputEvent::CursorMoved { position }
+            | InputEvent::MouseButton { position, .. }
+            | InputEvent::Pinch { position, .. }
+            | InputEvent::Touch { position, .. } => *position = transform * *position,
+            InputEvent::Wheel { delta, position, .. } => {
+                *position = transform * *position;
+                *delta = transform * delta.to_point() - transform * Point::ZERO;
+            }
+            _ => {}
+        }
+
+        event
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Back,
+    Forward,
+    Other(u16),
+}
+
+impl From<winit::event::MouseButton> for MouseButton {
+    fn from(button: winit::event::MouseButton) -> Self {
+        match button {
+            winit::event::MouseButton::Left => MouseButton::Left,
+            winit::event::MouseButton::Right => MouseButton::Right,
+            winit::event::MouseButton::Middle => MouseButton::Middle,
+            winit::event::MouseButton::Back => MouseButton::Back,
+            winit::event::MouseButton::Forward => MouseButton::Forward,
+            winit::event::MouseButton::Other(b) => MouseButton::Other(b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonState {
+    Pressed,
+    Released,
+}
+
+impl From<ElementState> for ButtonState {
+    fn from(state: ElementState) -> Self {
+        match state {
+            ElementState::Pressed => ButtonState::Pressed,
+            ElementState::Released => ButtonState::Released,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    /// The command key on macOS, the windows key elsewhere
+    pub meta: bool,
+}
+
+impl Modifiers {
+    /// The modifier for shortcuts, command on macOS and control everywhere else
+    pub fn shortcut(&self) -> bool {
+        if cfg!(target_os = "macos") {
+            self.meta
+        } else {
+            self.ctrl
+        }
+    }
+}
+
+impl From<ModifiersState> for Modifiers {
+    fn from(state: ModifiersState) -> Self {
+        Self {
+            shift: state.shift_key(),
+            ctrl: state.control_key(),
+            alt: state.alt_key(),
+            meta: state.super_key(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyEvent {
+    /// The key with the keyboard layout applied
+    pub key: Key,
+    /// The physical location of the key, independent of the layout
+    pub code: PhysicalKey,
+    pub state: ButtonState,
+    /// The text the key produces, if any
+    pub text: Option<String>,
+    pub repeat: bool,
+    pub modifiers: Modifiers,
+}
+
+/// Text composition of an input method editor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImeEvent {
+    Enabled,
+    /// The text being composed, with the cursor range as byte offsets into it
+    Preedit(String, Option<(usize, usize)>),
+    Commit(String),
+    Disabled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchPhase {
+    Started,
+    Moved,
+    Ended,
+    Cancelled,
+}
+
+impl From<WinitTouchPhase> for TouchPhase {
+    fn from(phase: WinitTouchPhase) -> Self {
+        match phase {
+            WinitTouchPhase::Started => TouchPhase::Started,
+            WinitTouchPhase::Moved => TouchPhase::Moved,
+            WinitTouchPhase::Ended => TouchPhase::Ended,
+            WinitTouchPhase::Cancelled => TouchPhase::Cancelled,
+        }
+    }
+}
+
+/// Receives the input events of a window. Returns whether the event changed anything that has to
+/// be painted, the window is redrawn if it did.
+pub trait InputHandler {
+    fn handle_input(&mut self, event: &InputEvent) -> bool;
+}
+
+impl<F: FnMut(&InputEvent) -> bool> InputHandler for F {
+    fn handle_input(&mut self, event: &InputEvent) -> bool {
+        self(event)
+    }
+}
+
+/// Turns winit's window events into input events. Winit doesn't include the cursor position and
+/// the modifiers in every event, so they are tracked here.
+#[derive(Debug, Clone, Default)]
+pub struct InputState {
+    pub cursor: Point,
+    pub modifiers: Modifiers,
+}
+
+impl InputState {
+    /// Returns `None` for window events that are not input
+    pub fn translate(&mut self, event: &WindowEvent, scale_factor: f64) -> Option<InputEvent> {
+        let to_css = |x: f64, y: f64| Point::new(x / scale_factor, y / scale_factor);
+
+        Some(match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = to_css(position.x, position.y);
+                InputEvent::CursorMoved { position: self.cursor }
+            }
+            WindowEvent::CursorEntered { .. } => InputEvent::CursorEntered,
+            WindowEvent::CursorLeft { .. } => InputEvent::CursorLeft,
+            WindowEvent::MouseInput { state, button, .. } => InputEvent::MouseButton {
+                button: (*button).into(),
+                state: (*state).into(),
+                position: self.cursor,
+                modifiers: self.modifiers,
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                let delta = match delta {
+                    // a positive line delta means the wheel was turned up, which scrolls up
+                    MouseScrollDelta::LineDelta(x, y) => Vec2::new(-*x as f64, -*y as f64) * LINE_HEIGHT,
+                    MouseScrollDelta::PixelDelta(p) => Vec2::new(-p.x, -p.y) / scale_factor,
+                };
+
+                InputEvent::Wheel {
+                    delta,
+                    position: self.cursor,
+                    modifiers: self.modifiers,
+                }
+            }
+            WindowEvent::TouchpadMagnify { delta, .. } => InputEvent::Pinch {
+                scale: 1.0 + delta,
+                position: self.cursor,
+            },
+            WindowEvent::KeyboardInput { event, .. } => InputEvent::Key(KeyEvent {
+                key: event.logical_key.clone(),
+                code: event.physical_key,
+                state: event.state.into(),
+                text: event.text.as_ref().map(|t| t.to_string()),
+                repeat: event.repeat,
+                modifiers: self.modifiers,
+            }),
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state().into();
+                InputEvent::ModifiersChanged(self.modifiers)
+            }
+            WindowEvent::Ime(ime) => InputEvent::Ime(match ime {
+                Ime::Enabled => ImeEvent::Enabled,
+                Ime::Preedit(text, cursor) => ImeEvent::Preedit(text.clone(), *cursor),
+                Ime::Commit(text) => ImeEvent::Commit(text.clone()),
+                Ime::Disabled => ImeEvent::Disabled,
+            }),
+            WindowEvent::Touch(touch) => InputEvent::Touch {
+                id: touch.id,
+                phase: touch.phase.into(),
+                position: to_css(touch.location.x, touch.location.y),
+            },
+            WindowEvent::Focused(focused) => InputEvent::Focus(*focused),
+            _ => return None,
+        })
+    }
+}
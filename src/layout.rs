@@ -0,0 +1,600 @@
+use std::collections::{HashMap, HashSet};
+
+use gosub_html5::node::NodeId;
+use gosub_rendering::layout::generate_taffy_tree;
+use gosub_styling::render_tree::RenderTree;
+use taffy::{
+    AvailableSpace, Dimension, Layout, LengthPercentageAuto, NodeId as TaffyID, PrintTree, Rect, Size, Style, TaffyTree,
+    TraversePartialTree,
+};
+use vello::kurbo::{Affine, Point, Vec2};
+
+use crate::element_state::{ElementStates, PseudoClass};
+use crate::float::{layout_floats, FloatLayout};
+use crate::flow::apply_flow_styles;
+use crate::grid::apply_grid_styles;
+use crate::hit_test::{hit_test, Hit};
+use crate::inline::{atom_sizes, build_inline_content, AtomSizes, Fragment, InlineContent};
+use crate::overflow::{apply_overflow_styles, ScrollOffsets};
+use crate::selector::{StateCascade, StateRules};
+use crate::stacking::StackingContext;
+use crate::style::{ComputedStyle, ComputedStyles, StyleChange};
+use crate::units::apply_length_styles;
+
+/// How often the document is laid out at most until the sizes of atomic inlines and the positions
+/// of floats settle
+const MAX_LAYOUT_PASSES: usize = 4;
+
+/// The smallest and largest page zoom
+pub const MIN_ZOOM: f64 = 0.25;
+pub const MAX_ZOOM: f64 = 5.0;
+
+/// The area the document is laid out in. Layout works in css pixels, the surface we paint to is
+/// `scale_factor` times larger.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    /// Width in css pixels
+    pub width: f64,
+    /// Height in css pixels
+    pub height: f64,
+    /// Device pixels per css pixel
+    pub scale_factor: f64,
+}
+
+impl Viewport {
+    pub fn from_logical(size: (f64, f64), scale_factor: f64) -> Self {
+        Self {
+            width: size.0,
+            height: size.1,
+            scale_factor,
+        }
+    }
+
+    /// The viewport with css pixels `zoom` times larger, so less of the document fits in it
+    pub fn zoomed(&self, zoom: f64) -> Self {
+        Self {
+            width: self.width / zoom,
+            height: self.height / zoom,
+            scale_factor: self.scale_factor * zoom,
+        }
+    }
+
+    pub fn from_physical(size: (usize, usize), scale_factor: f64) -> Self {
+        Self {
+            width: size.0 as f64 / scale_factor,
+            height: size.1 as f64 / scale_factor,
+            scale_factor,
+        }
+    }
+
+    /// The size in css pixels
+    pub fn logical(&self) -> (f64, f64) {
+        (self.width, self.height)
+    }
+
+    /// The size in device pixels
+    pub fn physical(&self) -> (usize, usize) {
+        (
+            (self.width * self.scale_factor).round() as usize,
+            (self.height * self.scale_factor).round() as usize,
+        )
+    }
+}
+
+/// Owns the taffy tree and the computed styles of a document, and only lays it out again when the
+/// viewport or the styles actually changed.
+pub struct LayoutDriver {
+    tree: TaffyTree<NodeId>,
+    root: TaffyID,
+    /// The taffy styles as generated from the render tree, before our computed styles are applied
+    base_styles: HashMap<TaffyID, Style>,
+    styles: ComputedStyles,
+    rules: StateRules,
+    states: ElementStates,
+    /// The viewport of the window, before the zoom is applied
+    window: Option<Viewport>,
+    /// The page zoom, which scales css pixels and lays the document out again
+    zoom: f64,
+    viewport: Option<Viewport>,
+    styles_dirty: bool,
+    layout_dirty: bool,
+    /// Nodes whose state changed since the last update, they are restyled with their descendants
+    restyle: Vec<NodeId>,
+    /// The runs of inline content, laid out in line boxes instead of by taffy
+    inline: Vec<InlineContent>,
+    atom_sizes: AtomSizes,
+    /// The fragments of inline elements, one per line they are on
+    fragments: HashMap<NodeId, Vec<Fragment>>,
+    /// The float layout the taffy styles currently include
+    floats: FloatLayout,
+    /// The min heights formatting context roots had before they grew to contain their floats
+    min_heights: HashMap<TaffyID, Dimension>,
+    /// The margins blocks had before the clearance above them and the space next to floats was added
+    margins: HashMap<TaffyID, Rect<LengthPercentageAuto>>,
+}
+
+impl LayoutDriver {
+    /// `rules` are the state dependent rules of the document, see [`StateRules`]
+    pub fn new(render_tree: &mut RenderTree, rules: StateRules) -> anyhow::Result<Self> {
+        let (tree, root) = generate_taffy_tree(render_tree)?;
+
+        let mut base_styles = HashMap::new();
+        let mut stack = vec![root];
+        while let Some(id) = stack.pop() {
+            stack.extend(tree.child_ids(id));
+            if let Ok(style) = tree.style(id) {
+                base_styles.insert(id, style.clone());
+            }
+        }
+
+        Ok(Self {
+            tree,
+            root,
+            base_styles,
+            styles: ComputedStyles::default(),
+            rules,
+            states: ElementStates::default(),
+            window: None,
+            zoom: 1.0,
+            viewport: None,
+            styles_dirty: true,
+            layout_dirty: true,
+            restyle: Vec::new(),
+            inline: Vec::new(),
+            atom_sizes: AtomSizes::new(),
+            fragments: HashMap::new(),
+            floats: FloatLayout::default(),
+            min_heights: HashMap::new(),
+            margins: HashMap::new(),
+        })
+    }
+
+    /// Sets the viewport of the window for the next [`LayoutDriver::update`]. Returns whether the
+    /// document has to be laid out again, which is not the case when only the scale factor changed.
+    pub fn set_viewport(&mut self, window: Viewport) -> bool {
+        self.window = Some(window);
+        self.apply_viewport(window.zoomed(self.zoom))
+    }
+
+    pub fn zoom(&self) -> f64 {
+        self.zoom
+    }
+
+    /// Sets the page zoom, clamped to [`MIN_ZOOM`] and [`MAX_ZOOM`]. The css pixel gets `zoom`
+    /// device pixels times the scale factor of the window and the document is laid out again.
+    /// Returns whether the zoom changed.
+    pub fn set_zoom(&mut self, zoom: f32) -> bool {
+        let zoom = (zoom as f64).clamp(MIN_ZOOM, MAX_ZOOM);
+        if zoom == self.zoom {
+            return false;
+        }
+
+        self.zoom = zoom;
+        if let Some(window) = self.window {
+            self.apply_viewport(window.zoomed(zoom));
+        }
+
+        true
+    }
+
+    /// The viewport of the window, without the zoom applied
+    pub fn window_viewport(&self) -> Option<Viewport> {
+        self.window
+    }
+
+    fn apply_viewport(&mut self, viewport: Viewport) -> bool {
+        let old = self.viewport.replace(viewport);
+
+        let resized = old.map_or(true, |old| old.logical() != viewport.logical());
+        if resized {
+            // viewport units depend on the size
+            self.styles_dirty = true;
+            self.layout_dirty = true;
+        } else if old.is_some_and(|old| old.scale_factor != viewport.scale_factor) {
+            // lengths stay the same in css pixels, only their device pixel values change
+            self.styles_dirty = true;
+        }
+
+        resized
+    }
+
+    /// Marks the styles as changed, they are recomputed on the next update
+    pub fn invalidate_styles(&mut self) {
+        self.styles_dirty = true;
+        self.layout_dirty = true;
+    }
+
+    /// Marks the layout as changed without touching the styles, e.g. after a node's content changed
+    pub fn invalidate_layout(&mut self) {
+        self.layout_dirty = true;
+    }
+
+    /// Whether [`LayoutDriver::update`] has anything to do
+    pub fn needs_update(&self) -> bool {
+        self.viewport.is_some() && (self.styles_dirty || self.layout_dirty || !self.restyle.is_empty())
+    }
+
+    pub fn states(&self) -> &ElementStates {
+        &self.states
+    }
+
+    /// Sets the element under the pointer. Returns whether the styles have to be updated.
+    pub fn set_hover(&mut self, node: Option<NodeId>) -> bool {
+        let changed = self.states.set_hover(self.chain(node));
+        self.states_changed(changed, &[PseudoClass::Hover])
+    }
+
+    /// Sets the element the pointer is pressed on. Returns whether the styles have to be updated.
+    pub fn set_active(&mut self, node: Option<NodeId>) -> bool {
+        let changed = self.states.set_active(self.chain(node));
+        self.states_changed(changed, &[PseudoClass::Active])
+    }
+
+    /// Moves the focus, `visible` is set when it was moved with the keyboard. Returns whether the
+    /// styles have to be updated.
+    pub fn set_focus(&mut self, node: Option<NodeId>, visible: bool) -> bool {
+        let changed = self.states.set_focus(self.chain(node), visible);
+        self.states_changed(changed, &[PseudoClass::Focus, PseudoClass::FocusVisible, PseudoClass::FocusWithin])
+    }
+
+    /// Sets the links that point to visited urls. Returns whether the styles have to be updated.
+    pub fn set_visited(&mut self, links: HashSet<NodeId>) -> bool {
+        let changed = self.states.set_visited(links);
+        self.states_changed(changed, &[PseudoClass::Link, PseudoClass::Visited])
+    }
+
+    /// The node and its ancestors
+    fn chain(&self, node: Option<NodeId>) -> Vec<NodeId> {
+        node.map(|node| self.styles.ancestors(node).collect()).unwrap_or_default()
+    }
+
+    fn states_changed(&mut self, changed: Vec<NodeId>, pseudo_classes: &[PseudoClass]) -> bool {
+        // without rules for these states nothing looks different
+        if changed.is_empty() || !pseudo_classes.iter().any(|p| self.rules.uses(*p)) {
+            return false;
+        }
+
+        self.restyle.extend(changed);
+        true
+    }
+
+    /// Recomputes what is out of date. Returns whether the layout changed.
+    pub fn update(&mut self, render_tree: &mut RenderTree) -> bool {
+        let Some(viewport) = self.viewport else {
+            return false;
+        };
+
+        if self.styles_dirty {
+            let state = StateCascade {
+                rules: &self.rules,
+                states: &self.states,
+            };
+            self.styles = ComputedStyles::compute(render_tree, viewport.logical(), viewport.scale_factor, &state);
+            self.apply_styles(self.root);
+
+            self.styles_dirty = false;
+            self.restyle.clear();
+        } else if !self.restyle.is_empty() {
+            self.restyle_changed(render_tree);
+        }
+
+        if !self.layout_dirty {
+            return false;
+        }
+
+        let size = Size {
+            width: AvailableSpace::Definite(viewport.width as f32),
+            height: AvailableSpace::Definite(viewport.height as f32),
+        };
+
+        self.compute_layout(render_tree, size);
+        self.layout_dirty = false;
+
+        true
+    }
+
+    /// Lays the document out. Runs of inline content are measured by breaking them into lines,
+    /// and once the size of everything is known their boxes are placed where the lines put them.
+    fn compute_layout(&mut self, render_tree: &mut RenderTree, size: Size<AvailableSpace>) {
+        let previous = std::mem::take(&mut self.inline);
+        self.inline = build_inline_content(&mut self.tree, self.root, render_tree, &self.styles, previous);
+
+        // the lines depend on the size of atomic inlines and the floats next to them, and taffy
+        // lays those out from the lines around them, so this takes another pass when they changed
+        let mut floats = std::mem::take(&mut self.floats);
+        for _ in 0..MAX_LAYOUT_PASSES {
+            if !self.compute_taffy_layout(size) {
+                self.floats = floats;
+                return;
+            }
+
+            let sizes = atom_sizes(&self.tree, &self.inline);
+            let next = layout_floats(&self.tree, self.root, &self.styles, &self.inline, &floats);
+            if sizes == self.atom_sizes && next == floats {
+                break;
+            }
+
+            self.atom_sizes = sizes;
+            self.apply_floats(&floats, &next);
+            floats = next;
+        }
+        self.floats = floats;
+
+        self.fragments.clear();
+        for content in self.inline.iter() {
+            content.place(&mut self.tree, render_tree, &self.atom_sizes, &mut self.fragments);
+        }
+
+        // the boxes of the inline content are out of flow, so this only moves them into place
+        self.compute_taffy_layout(size);
+    }
+
+    /// Moves the floats into place and makes room for them for the next layout pass
+    fn apply_floats(&mut self, previous: &FloatLayout, floats: &FloatLayout) {
+        let update = |tree: &mut TaffyTree<NodeId>, id: TaffyID, f: &dyn Fn(&mut Style)| {
+            let Ok(style) = tree.style(id) else {
+                return;
+            };
+
+            let mut style = style.clone();
+            f(&mut style);
+
+            if let Err(e) = tree.set_style(id, style) {
+                eprintln!("Failed to set float styles: {e:?}");
+            }
+        };
+
+        for (id, (x, y)) in floats.positions.iter() {
+            update(&mut self.tree, *id, &|style| {
+                style.inset.left = LengthPercentageAuto::Length(*x as f32);
+                style.inset.top = LengthPercentageAuto::Length(*y as f32);
+            });
+        }
+
+        // clearance is added to the styled top margin and blocks next to floats get wider side
+        // margins, the styled margins come back once neither applies
+        let moved = [previous, floats]
+            .into_iter()
+            .flat_map(|f| f.clearance.keys().chain(f.insets.keys()))
+            .copied()
+            .collect::<HashSet<_>>();
+        for id in moved {
+            let Ok(style) = self.tree.style(id) else {
+                continue;
+            };
+            let styled = *self.margins.entry(id).or_insert(style.margin);
+
+            let (clearance, insets) = (floats.clearance.get(&id), floats.insets.get(&id));
+            let margin = if clearance.is_none() && insets.is_none() {
+                self.margins.remove(&id).unwrap_or(styled)
+            } else {
+                let widen = |margin: LengthPercentageAuto, inset: f64| {
+                    if inset > 0.0 {
+                        LengthPercentageAuto::Length(self.resolve_margin(id, margin).max(inset) as f32)
+                    } else {
+                        margin
+                    }
+                };
+
+                Rect {
+                    top: match clearance {
+                        Some(clearance) => LengthPercentageAuto::Length((self.resolve_margin(id, styled.top) + clearance) as f32),
+                        None => styled.top,
+                    },
+                    left: widen(styled.left, insets.map_or(0.0, |i| i.0)),
+                    right: widen(styled.right, insets.map_or(0.0, |i| i.1)),
+                    bottom: styled.bottom,
+                }
+            };
+
+            update(&mut self.tree, id, &|style| style.margin = margin);
+        }
+
+        // roots grow from their styled min height, which comes back once they have no floats
+        let grown = previous.heights.keys().chain(floats.heights.keys()).copied().collect::<HashSet<_>>();
+        for id in grown {
+            let Ok(style) = self.tree.style(id) else {
+                continue;
+            };
+            let styled = *self.min_heights.entry(id).or_insert(style.min_size.height);
+
+            let min_height = match floats.heights.get(&id) {
+                Some(height) => {
+                    let min = match styled {
+                        Dimension::Length(px) => px as f64,
+                        _ => 0.0,
+                    };
+                    Dimension::Length(min.max(*height) as f32)
+                }
+                None => self.min_heights.remove(&id).unwrap_or(styled),
+            };
+
+            update(&mut self.tree, id, &|style| style.min_size.height = min_height);
+        }
+
+        for content in self.inline.iter_mut() {
+            content.set_exclusions(floats.lines.get(&content.leaf).cloned().unwrap_or_default());
+        }
+    }
+
+    /// The used value of a margin of a block from the last layout, percentages are relative to the
+    /// width of the containing block and auto margins are zero
+    fn resolve_margin(&self, id: TaffyID, margin: LengthPercentageAuto) -> f64 {
+        match margin {
+            LengthPercentageAuto::Length(px) => px as f64,
+            LengthPercentageAuto::Percent(fraction) => {
+                let Some(parent) = self.tree.parent(id) else {
+                    return 0.0;
+                };
+
+                let layout = self.tree.get_final_layout(parent);
+                let width = layout.size.width - layout.padding.left - layout.padding.right - layout.border.left - layout.border.right;
+                (fraction * width) as f64
+            }
+            LengthPercentageAuto::Auto => 0.0,
+        }
+    }
+
+    fn compute_taffy_layout(&mut self, size: Size<AvailableSpace>) -> bool {
+        let leaves: HashMap<TaffyID, &InlineContent> = self.inline.iter().map(|c| (c.leaf, c)).collect();
+        let atoms = &self.atom_sizes;
+
+        let result = self.tree.compute_layout_with_measure(self.root, size, |known, available, id, _| match leaves.get(&id) {
+            Some(content) => content.measure(known, available, atoms),
+            None => Size::ZERO,
+        });
+
+        if let Err(e) = result {
+            eprintln!("Failed to compute layout: {e:?}");
+            return false;
+        }
+
+        true
+    }
+
+    /// Restyles the subtrees of the nodes whose state changed, and only schedules a relayout if a
+    /// property that affects layout changed
+    fn restyle_changed(&mut self, render_tree: &RenderTree) {
+        let changed = std::mem::take(&mut self.restyle);
+
+        // restyling a node restyles its descendants too
+        let roots = changed
+            .iter()
+            .filter(|id| !self.styles.ancestors(**id).skip(1).any(|a| changed.contains(&a)))
+            .copied()
+            .collect::<HashSet<_>>();
+
+        for id in roots {
+            let state = StateCascade {
+                rules: &self.rules,
+                states: &self.states,
+            };
+
+            if self.styles.restyle(render_tree, id, &state) != StyleChange::Relayout {
+                continue;
+            }
+
+            if let Some(layout_id) = self.layout_node(id) {
+                self.apply_styles(layout_id);
+            }
+            self.layout_dirty = true;
+        }
+    }
+
+    /// Applies the computed styles to the taffy styles of a subtree, starting over from the
+    /// generated styles so properties that are no longer set don't stick around
+    fn apply_styles(&mut self, root: TaffyID) {
+        let mut stack = vec![root];
+        while let Some(id) = stack.pop() {
+            stack.extend(self.tree.child_ids(id));
+
+            if let Some(style) = self.base_styles.get(&id) {
+                if let Err(e) = self.tree.set_style(id, style.clone()) {
+                    eprintln!("Failed to reset style: {e:?}");
+                }
+            }
+
+            // the reset styles no longer include the floats
+            self.floats.positions.remove(&id);
+            self.floats.clearance.remove(&id);
+            self.floats.insets.remove(&id);
+            self.floats.heights.remove(&id);
+            self.min_heights.remove(&id);
+            self.margins.remove(&id);
+        }
+
+        apply_overflow_styles(&mut self.tree, root, &self.styles);
+        apply_length_styles(&mut self.tree, root, &self.styles);
+        apply_flow_styles(&mut self.tree, root, &self.styles);
+        apply_grid_styles(&mut self.tree, root, &self.styles);
+    }
+
+    pub fn tree(&self) -> &TaffyTree<NodeId> {
+        &self.tree
+    }
+
+    pub fn root(&self) -> TaffyID {
+        self.root
+    }
+
+    pub fn styles(&self) -> &ComputedStyles {
+        &self.styles
+    }
+
+    /// The viewport the document is laid out in, with the zoom applied
+    pub fn viewport(&self) -> Option<Viewport> {
+        self.viewport
+    }
+
+    /// The boxes of an inline element on each line it is on, relative to its border box. Only
+    /// inline elements that take part in an inline formatting context have them.
+    pub fn inline_fragments(&self, gosub_id: NodeId) -> Option<&[Fragment]> {
+        self.fragments.get(&gosub_id).map(Vec::as_slice)
+    }
+
+    pub fn layout(&self, id: TaffyID) -> &Layout {
+        self.tree.get_final_layout(id)
+    }
+
+    /// The layout node that was generated for a node of the render tree
+    pub fn layout_node(&self, gosub_id: NodeId) -> Option<TaffyID> {
+        let mut stack = vec![self.root];
+
+        while let Some(id) = stack.pop() {
+            if self.tree.get_node_context(id) == Some(&gosub_id) {
+                return Some(id);
+            }
+
+            stack.extend(self.tree.child_ids(id));
+        }
+
+        None
+    }
+
+    /// The top left of a node's border box relative to the document, ignoring transforms and the
+    /// scroll offsets of scroll containers
+    pub fn document_position(&self, id: TaffyID) -> Point {
+        let mut position = Point::ZERO;
+        let mut current = Some(id);
+
+        while let Some(id) = current {
+            let location = self.tree.get_final_layout(id).location;
+            position += Vec2::new(location.x as f64, location.y as f64);
+            current = self.tree.parent(id);
+        }
+
+        position
+    }
+
+    /// The style of the root element, which applies some properties like `scroll-behavior` to the
+    /// viewport
+    pub fn root_element_style(&self) -> Option<&ComputedStyle> {
+        std::iter::once(self.root)
+            .chain(self.tree.child_ids(self.root))
+            .find_map(|id| self.tree.get_node_context(id).and_then(|node| self.styles.get(*node)))
+    }
+
+    /// The size of the document including everything that overflows the root, in css pixels
+    pub fn document_size(&self) -> (f64, f64) {
+        let layout = self.tree.get_final_layout(self.root);
+
+        let width = layout.size.width.max(layout.content_size.width) + layout.location.x;
+        let height = layout.size.height.max(layout.content_size.height) + layout.location.y;
+
+        (width as f64, height as f64)
+    }
+
+    /// The topmost node at `point`, which is in css pixels relative to the viewport. The document
+    /// is scrolled by `document_scroll`.
+    pub fn hit_test(
+        &self,
+        render_tree: &RenderTree,
+        scroll: &ScrollOffsets,
+        document_scroll: Vec2,
+        point: Point,
+    ) -> Option<Hit> {
+        let transform = Affine::translate(-document_scroll);
+        let context = StackingContext::build(&self.tree, render_tree, &self.styles, self.root, scroll, transform)?;
+        hit_test(&context, &self.tree, &self.styles, point)
+    }
+}
@@ -1,18 +1,48 @@
 pub mod text;
 pub mod image;
 pub mod tree;
+pub mod css;
+pub mod shadow;
+pub mod compositing;
+pub mod overflow;
+pub mod stacking;
+pub mod transform;
+pub mod units;
+pub mod style;
+pub mod color;
+pub mod layout;
+pub mod input;
+pub mod hit_test;
+pub mod scroll;
+pub mod loader;
+pub mod navigation;
+pub mod page;
+pub mod selector;
+pub mod media;
+pub mod element_state;
+pub mod cursor;
+pub mod selection;
+pub mod find;
+pub mod zoom;
+pub mod inline;
+pub mod flow;
+pub mod float;
+pub mod grid;
 
 use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use vello::{AaConfig, AaSupport, Renderer, RendererOptions, RenderParams, Scene};
 use vello::peniko::Color;
 use vello::util::{RenderContext, RenderSurface};
 use winit::dpi::LogicalSize;
 use winit::event::{Event, WindowEvent};
-use winit::event_loop::EventLoop;
-use winit::window::{Window, WindowBuilder};
+use winit::event_loop::{ControlFlow, EventLoop, EventLoopBuilder, EventLoopProxy, EventLoopWindowTarget};
+use winit::window::{CursorIcon, Window, WindowBuilder};
 
 use rust_fontconfig::{FcFontCache};
+
+use crate::input::{InputHandler, InputState};
 use once_cell::sync::Lazy;
 
 static FONT_CACHE: Lazy<FcFontCache> = Lazy::new(FcFontCache::build);
@@ -25,19 +55,70 @@ pub enum RenderState<'a> {
     Suspended(Arc<Window>),
 }
 
-pub struct WindowState<'a, FN: FnMut(&mut Scene, (usize, usize))> {
-    event_loop: EventLoop<()>,
+/// Events the embedder can send to the event loop from any thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserEvent {
+    Redraw,
+    /// Shows the system cursor, or hides it for `None`
+    Cursor(Option<CursorIcon>),
+}
+
+/// Schedules redraws of the window. Nothing is painted unless the window was resized or a redraw
+/// was requested, so embedders have to call [`RedrawHandle::request_redraw`] whenever the document
+/// changed. Animations request the next frame from within the render callback.
+#[derive(Debug, Clone)]
+pub struct RedrawHandle {
+    proxy: EventLoopProxy<UserEvent>,
+    /// Set while a redraw is pending, so requests in between two frames are coalesced into one
+    pending: Arc<AtomicBool>,
+}
+
+impl RedrawHandle {
+    pub fn request_redraw(&self) {
+        if self.pending.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        if self.proxy.send_event(UserEvent::Redraw).is_err() {
+            // the event loop is gone, there is nothing left to draw to
+            self.pending.store(false, Ordering::Release);
+        }
+    }
+}
+
+/// Changes the system cursor of the window
+#[derive(Debug, Clone)]
+pub struct CursorHandle {
+    proxy: EventLoopProxy<UserEvent>,
+}
+
+impl CursorHandle {
+    /// Shows `icon`, or hides the cursor for `None` so a custom one can be painted instead
+    pub fn set_cursor(&self, icon: Option<CursorIcon>) {
+        // if the event loop is gone there is no window to change the cursor of
+        let _ = self.proxy.send_event(UserEvent::Cursor(icon));
+    }
+}
+
+/// The render callback gets the surface size in physical pixels and the scale factor, which is the
+/// number of physical pixels per css pixel.
+pub struct WindowState<'a, FN: FnMut(&mut Scene, (usize, usize), f64)> {
+    event_loop: EventLoop<UserEvent>,
     render_state: RenderState<'a>,
     render_scene: &'a mut FN,
+    scale_factor: f64,
+    redraw_pending: Arc<AtomicBool>,
+    input: InputState,
+    input_handler: Option<Box<dyn InputHandler + 'a>>,
     cx: RenderContext,
     renderers: Vec<Option<Renderer>>,
     scene: Scene,
 }
 
 
-impl<'a, FN: FnMut(&mut Scene, (usize, usize))> WindowState<'a, FN> {
+impl<'a, FN: FnMut(&mut Scene, (usize, usize), f64)> WindowState<'a, FN> {
     pub fn new(render_scene: &'a mut FN) -> anyhow::Result<Self> {
-        let event_loop = EventLoop::new()?;
+        let event_loop = EventLoopBuilder::<UserEvent>::with_user_event().build()?;
         let render_state = RenderState::Suspended(create_window(&event_loop)?);
         let cx = RenderContext::new().map_err(|e| anyhow::anyhow!(e.to_string()))?;
         
@@ -46,6 +127,10 @@ impl<'a, FN: FnMut(&mut Scene, (usize, usize))> WindowState<'a, FN> {
             event_loop,
             render_state,
             render_scene,
+            scale_factor: 1.0,
+            redraw_pending: Arc::new(AtomicBool::new(false)),
+            input: InputState::default(),
+            input_handler: None,
             cx,
             scene: Scene::new(),
             renderers: Vec::new(),
@@ -53,6 +138,26 @@ impl<'a, FN: FnMut(&mut Scene, (usize, usize))> WindowState<'a, FN> {
     }
 
 
+    /// Sets the handler that receives all input events of the window
+    pub fn set_input_handler(&mut self, handler: impl InputHandler + 'a) {
+        self.input_handler = Some(Box::new(handler));
+    }
+
+    /// A handle to request redraws, it can be sent to other threads
+    pub fn redraw_handle(&self) -> RedrawHandle {
+        RedrawHandle {
+            proxy: self.event_loop.create_proxy(),
+            pending: self.redraw_pending.clone(),
+        }
+    }
+
+    /// A handle to change the cursor, e.g. from the input handler
+    pub fn cursor_handle(&self) -> CursorHandle {
+        CursorHandle {
+            proxy: self.event_loop.create_proxy(),
+        }
+    }
+
     pub fn start(mut self) -> anyhow::Result<()> {
         self.event_loop.run(move |event, event_loop| {
             match event {
@@ -62,6 +167,7 @@ impl<'a, FN: FnMut(&mut Scene, (usize, usize))> WindowState<'a, FN> {
                     };
                     
                     let size = window.inner_size();
+                    self.scale_factor = window.scale_factor();
                     
                     let surface_future = self.cx.create_surface(
                         window.clone(),
@@ -90,12 +196,16 @@ impl<'a, FN: FnMut(&mut Scene, (usize, usize))> WindowState<'a, FN> {
                     });
 
 
+                    window.set_ime_allowed(true);
+                    window.request_redraw();
+
                     self.render_state = RenderState::Active {
                         surface,
                         window: window.clone(),
                     };
                     
-                    event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
+                    // only redraw when something changed
+                    event_loop.set_control_flow(ControlFlow::Wait);
                     
                 }
                 
@@ -103,15 +213,29 @@ impl<'a, FN: FnMut(&mut Scene, (usize, usize))> WindowState<'a, FN> {
                     if let RenderState::Active {window, .. } = &self.render_state {
                         self.render_state = RenderState::Suspended(window.clone());
                     }
-                    event_loop.set_control_flow(winit::event_loop::ControlFlow::Wait);
+                    event_loop.set_control_flow(ControlFlow::Wait);
                 }
                 
-                Event::AboutToWait => {
+                Event::UserEvent(UserEvent::Redraw) => {
+                    self.redraw_pending.store(false, Ordering::Release);
+
                     if let RenderState::Active {window, .. } = &self.render_state {
                         window.request_redraw();
                     }
                 }
                 
+                Event::UserEvent(UserEvent::Cursor(icon)) => {
+                    if let RenderState::Active {window, .. } = &self.render_state {
+                        match icon {
+                            Some(icon) => {
+                                window.set_cursor_icon(icon);
+                                window.set_cursor_visible(true);
+                            }
+                            None => window.set_cursor_visible(false),
+                        }
+                    }
+                }
+                
                 Event::WindowEvent { ref event, window_id } => {
                     let  RenderState::Active { window, surface} = &mut self.render_state else {
                         return;
@@ -129,12 +253,17 @@ impl<'a, FN: FnMut(&mut Scene, (usize, usize))> WindowState<'a, FN> {
                             self.cx.resize_surface(surface, size.width, size.height);
                             window.request_redraw();
                         }
+                        WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                            // winit follows up with a resize to the new physical size
+                            self.scale_factor = *scale_factor;
+                            window.request_redraw();
+                        }
                         
                         WindowEvent::RedrawRequested => {
                             self.scene.reset();
                             let size = window.inner_size();
 
-                            (self.render_scene)(&mut self.scene, (size.width as usize, size.height as usize));
+                            (self.render_scene)(&mut self.scene, (size.width as usize, size.height as usize), self.scale_factor);
                             
                             let width = surface.config.width;
                             let height = surface.config.height;
@@ -169,7 +298,17 @@ impl<'a, FN: FnMut(&mut Scene, (usize, usize))> WindowState<'a, FN> {
                         }
                         
 
-                        _ => {}
+                        event => {
+                            let Some(input) = self.input.translate(event, self.scale_factor) else {
+                                return;
+                            };
+
+                            if let Some(handler) = &mut self.input_handler {
+                                if handler.handle_input(&input) {
+                                    window.request_redraw();
+                                }
+                            }
+                        }
                     }
                 }
                 _ => {}
@@ -184,7 +323,7 @@ impl<'a, FN: FnMut(&mut Scene, (usize, usize))> WindowState<'a, FN> {
 
 
 fn create_window(
-    event_loop: &winit::event_loop::EventLoopWindowTarget<()>,
+    event_loop: &EventLoopWindowTarget<UserEvent>,
 ) -> anyhow::Result<Arc<Window>> {
     Ok(Arc::new(
         WindowBuilder::new()
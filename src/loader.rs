@@ -0,0 +1,102 @@
+use std::fs;
+use std::io::Read;
+
+use anyhow::bail;
+use gosub_html5::parser::document::{Document, DocumentBuilder};
+use gosub_html5::parser::Html5Parser;
+use gosub_shared::bytes::{CharIterator, Confidence, Encoding};
+use gosub_styling::render_tree::{generate_render_tree, RenderTree};
+use url::Url;
+
+/// Fetches the html of a http(s) or file url
+pub fn fetch_html(url: &Url) -> anyhow::Result<String> {
+    Ok(String::from_utf8_lossy(&fetch_bytes(url)?).into_owned())
+}
+
+/// Fetches the contents of a http(s) or file url
+pub fn fetch_bytes(url: &Url) -> anyhow::Result<Vec<u8>> {
+    if url.scheme() == "http" || url.scheme() == "https" {
+        let response = ureq::get(url.as_ref()).call()?;
+        if response.status() != 200 {
+            bail!(format!(
+                "Could not get url. Status code {}",
+                response.status()
+            ));
+        }
+
+        let mut data = Vec::new();
+        response.into_reader().read_to_end(&mut data)?;
+        Ok(data)
+    } else if url.scheme() == "file" {
+        let mut url = url.clone();
+        url.set_fragment(None);
+        url.set_query(None);
+
+        // file://assets/page.html is a path relative to the working directory
+        Ok(fs::read(url.as_str().trim_start_matches("file://"))?)
+    } else {
+        bail!("Unsupported url scheme: {}", url.scheme());
+    }
+}
+
+/// A parsed document
+pub struct LoadedDocument {
+    pub render_tree: RenderTree,
+    /// The text of the document's `<style>` elements, in document order
+    pub style_sheets: Vec<String>,
+}
+
+pub fn load_document(url: &Url) -> anyhow::Result<LoadedDocument> {
+    let html = fetch_html(url)?;
+
+    Ok(LoadedDocument {
+        style_sheets: extract_style_sheets(&html),
+        render_tree: parse_html(url, &html)?,
+    })
+}
+
+pub fn load_html_rendertree(url: &Url) -> anyhow::Result<RenderTree> {
+    let html = fetch_html(url)?;
+    parse_html(url, &html)
+}
+
+fn parse_html(url: &Url, html: &str) -> anyhow::Result<RenderTree> {
+    let mut chars = CharIterator::new();
+    chars.read_from_str(html, Some(Encoding::UTF8));
+    chars.set_confidence(Confidence::Certain);
+
+    let doc_handle = DocumentBuilder::new_document(Some(url.clone()));
+    let _parse_errors =
+        Html5Parser::parse_document(&mut chars, Document::clone(&doc_handle), None)?;
+
+    generate_render_tree(Document::clone(&doc_handle))
+}
+
+/// The contents of the `<style>` elements. The render tree only has the styles the styling crate
+/// cascaded, so rules it can't match, like the ones for `:hover`, are taken from the source.
+fn extract_style_sheets(html: &str) -> Vec<String> {
+    let lower = html.to_ascii_lowercase();
+    let mut sheets = Vec::new();
+    let mut pos = 0;
+
+    while let Some(start) = lower[pos..].find("<style").map(|i| pos + i) {
+        // `<styles>` or similar is not a style element
+        let after_name = lower.as_bytes().get(start + 6).copied();
+        if !matches!(after_name, Some(b'>' | b' ' | b'\t' | b'\n' | b'\r' | b'/')) {
+            pos = start + 6;
+            continue;
+        }
+
+        let Some(open_end) = lower[start..].find('>').map(|i| start + i + 1) else {
+            break;
+        };
+        let Some(close) = lower[open_end..].find("</style").map(|i| open_end + i) else {
+            break;
+        };
+
+        sheets.push(html[open_end..close].to_string());
+        pos = close;
+    }
+
+    sheets
+}
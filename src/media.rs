@@ -0,0 +1,230 @@
+use crate::css::{split_top_level, tokens};
+use crate::units::LengthContext;
+
+/// A media query list like `screen and (min-width: 40em), print`, which matches if any of its
+/// queries matches. Queries with unknown media types or features never match, see
+/// https://drafts.csswg.org/mediaqueries/
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQueryList {
+    queries: Vec<Option<MediaQuery>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct MediaQuery {
+    negated: bool,
+    /// Whether the media type matches a screen, `all` and `screen` do
+    screen: bool,
+    features: Vec<MediaFeature>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct MediaFeature {
+    name: String,
+    comparison: Comparison,
+    value: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl MediaQueryList {
+    pub fn parse(value: &str) -> Self {
+        let queries = split_top_level(&value.to_ascii_lowercase(), ',')
+            .into_iter()
+            .map(MediaQuery::parse)
+            .collect();
+
+        Self { queries }
+    }
+
+    /// Whether the list matches a screen with the given viewport in css pixels, an empty list
+    /// matches everything
+    pub fn matches(&self, viewport: (f64, f64)) -> bool {
+        self.queries.is_empty() || self.queries.iter().flatten().any(|q| q.matches(viewport))
+    }
+}
+
+impl MediaQuery {
+    fn parse(query: &str) -> Option<Self> {
+        let words = tokens(query);
+        let mut words = words.as_slice();
+
+        let negated = words.first() == Some(&"not");
+        if matches!(words.first(), Some(&"not" | &"only")) {
+            words = &words[1..];
+        }
+
+        let mut screen = true;
+        if let Some(media_type) = words.first().filter(|w| !w.starts_with('(')) {
+            screen = match *media_type {
+                "all" | "screen" => true,
+                "print" | "speech" => false,
+                _ => return None,
+            };
+            words = &words[1..];
+
+            // features follow the type after an `and`
+            if !words.is_empty() {
+                if words.len() == 1 || words[0] != "and" {
+                    return None;
+                }
+                words = &words[1..];
+            }
+        }
+
+        let mut features = Vec::new();
+        for (i, word) in words.iter().enumerate() {
+            // features are joined by `and`
+            if i % 2 == 1 {
+                if *word != "and" {
+                    return None;
+                }
+                continue;
+            }
+
+            let feature = word.strip_prefix('(')?.strip_suffix(')')?;
+            features.push(MediaFeature::parse(feature)?);
+        }
+
+        if words.len() % 2 == 0 && !words.is_empty() {
+            // a trailing `and`
+            return None;
+        }
+
+        Some(Self { negated, screen, features })
+    }
+
+    fn matches(&self, viewport: (f64, f64)) -> bool {
+        let matches = self.screen && self.features.iter().all(|f| f.matches(viewport));
+        matches != self.negated
+    }
+}
+
+impl MediaFeature {
+    /// Parses `name`, `name: value` and the range forms `name >= value` and `value < name`
+    fn parse(feature: &str) -> Option<Self> {
+        if let Some((name, value)) = feature.split_once(':') {
+            let name = name.trim();
+            let (name, comparison) = match (name.strip_prefix("min-"), name.strip_prefix("max-")) {
+                (Some(name), _) => (name, Comparison::GreaterOrEqual),
+                (_, Some(name)) => (name, Comparison::LessOrEqual),
+                _ => (name, Comparison::Equal),
+            };
+
+            return Some(Self {
+                name: name.to_string(),
+                comparison,
+                value: Some(value.trim().to_string()),
+            });
+        }
+
+        for (operator, comparison, flipped) in [
+            ("<=", Comparison::LessOrEqual, Comparison::GreaterOrEqual),
+            (">=", Comparison::GreaterOrEqual, Comparison::LessOrEqual),
+            ("<", Comparison::Less, Comparison::Greater),
+            (">", Comparison::Greater, Comparison::Less),
+            ("=", Comparison::Equal, Comparison::Equal),
+        ] {
+            let Some((left, right)) = feature.split_once(operator) else {
+                continue;
+            };
+            let (left, right) = (left.trim(), right.trim());
+
+            // the name can be on either side, `600px < width` is `width > 600px`
+            let left_is_name = left.starts_with(|c: char| c.is_ascii_alphabetic());
+            let (name, value, comparison) = if left_is_name {
+                (left, right, comparison)
+            } else {
+                (right, left, flipped)
+            };
+
+            return Some(Self {
+                name: name.to_string(),
+                comparison,
+                value: Some(value.to_string()),
+            });
+        }
+
+        Some(Self {
+            name: feature.trim().to_string(),
+            comparison: Comparison::Equal,
+            value: None,
+        })
+    }
+
+    fn matches(&self, (width, height): (f64, f64)) -> bool {
+        // lengths in media queries are relative to the initial font size
+        let ctx = LengthContext::new((width, height));
+        let compare = |actual: f64| {
+            let Some(expected) = self.value.as_deref().and_then(|v| ctx.resolve(v, 0.0)) else {
+                return self.value.is_none() && actual != 0.0;
+            };
+
+            match self.comparison {
+                Comparison::Equal => actual == expected,
+                Comparison::Less => actual < expected,
+                Comparison::LessOrEqual => actual <= expected,
+                Comparison::Greater => actual > expected,
+                Comparison::GreaterOrEqual => actual >= expected,
+            }
+        };
+        let keyword = |expected: &str| self.value.as_deref().map_or(true, |v| v == expected);
+
+        match self.name.as_str() {
+            "width" | "device-width" => compare(width),
+            "height" | "device-height" => compare(height),
+            "orientation" => keyword(if height >= width { "portrait" } else { "landscape" }),
+            "color" => self.value.is_none(),
+            "prefers-color-scheme" => keyword("light"),
+            "prefers-reduced-motion" | "prefers-contrast" => self.value.as_deref() == Some("no-preference"),
+            "hover" | "any-hover" => keyword("hover"),
+            "pointer" | "any-pointer" => keyword("fine"),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(query: &str, viewport: (f64, f64)) -> bool {
+        MediaQueryList::parse(query).matches(viewport)
+    }
+
+    #[test]
+    fn matches_media_types() {
+        assert!(matches("", (800.0, 600.0)));
+        assert!(matches("all", (800.0, 600.0)));
+        assert!(matches("screen", (800.0, 600.0)));
+        assert!(!matches("print", (800.0, 600.0)));
+        assert!(matches("not print", (800.0, 600.0)));
+        assert!(matches("print, screen", (800.0, 600.0)));
+        assert!(!matches("tv", (800.0, 600.0)));
+    }
+
+    #[test]
+    fn matches_sizes() {
+        assert!(matches("screen and (min-width: 600px)", (800.0, 600.0)));
+        assert!(!matches("screen and (max-width: 600px)", (800.0, 600.0)));
+        assert!(matches("(min-width: 40em) and (max-width: 60em)", (800.0, 600.0)));
+        assert!(matches("(width >= 800px)", (800.0, 600.0)));
+        assert!(!matches("(width > 800px)", (800.0, 600.0)));
+        assert!(matches("(700px < width)", (800.0, 600.0)));
+        assert!(matches("(orientation: landscape)", (800.0, 600.0)));
+        assert!(!matches("not all and (orientation: landscape)", (800.0, 600.0)));
+    }
+
+    #[test]
+    fn never_matches_what_it_does_not_understand() {
+        assert!(!matches("(min-resolution: 2dppx)", (800.0, 600.0)));
+        assert!(!matches("screen and", (800.0, 600.0)));
+        assert!(!matches("screen (min-width: 1px)", (800.0, 600.0)));
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::HashSet;
+
+use gosub_html5::node::NodeId;
+use gosub_styling::render_tree::{RenderNodeData, RenderTree};
+use taffy::{NodeId as TaffyID, TaffyTree, TraversePartialTree};
+use url::Url;
+use vello::kurbo::Vec2;
+
+/// A visited document and where it was scrolled to when we left it
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub url: Url,
+    pub scroll: Vec2,
+}
+
+/// The back/forward history of a page
+#[derive(Debug, Clone)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    current: usize,
+}
+
+impl History {
+    pub fn new(url: Url) -> Self {
+        Self {
+            entries: vec![HistoryEntry { url, scroll: Vec2::ZERO }],
+            current: 0,
+        }
+    }
+
+    pub fn current(&self) -> &HistoryEntry {
+        &self.entries[self.current]
+    }
+
+    /// Adds `url` after the current entry and drops everything we could go forward to.
+    /// `scroll` is the scroll offset of the document we leave.
+    pub fn push(&mut self, url: Url, scroll: Vec2) {
+        self.entries[self.current].scroll = scroll;
+        self.entries.truncate(self.current + 1);
+        self.entries.push(HistoryEntry { url, scroll: Vec2::ZERO });
+        self.current += 1;
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        self.current > 0
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        self.current + 1 < self.entries.len()
+    }
+
+    /// The entry going back leads to
+    pub fn previous_entry(&self) -> Option<&HistoryEntry> {
+        self.current.checked_sub(1).map(|i| &self.entries[i])
+    }
+
+    /// The entry going forward leads to
+    pub fn next_entry(&self) -> Option<&HistoryEntry> {
+        self.entries.get(self.current + 1)
+    }
+
+    /// Moves to the previous entry once it is shown. `scroll` is the scroll offset of the document
+    /// we leave.
+    pub fn back(&mut self, scroll: Vec2) {
+        if self.can_go_back() {
+            self.entries[self.current].scroll = scroll;
+            self.current -= 1;
+        }
+    }
+
+    /// Moves to the next entry once it is shown. `scroll` is the scroll offset of the document we
+    /// leave.
+    pub fn forward(&mut self, scroll: Vec2) {
+        if self.can_go_forward() {
+            self.entries[self.current].scroll = scroll;
+            self.current += 1;
+        }
+    }
+}
+
+/// The `href` of a node if it is a link
+pub fn link_href(render_tree: &RenderTree, id: NodeId) -> Option<&str> {
+    match render_tree.get_node(id).map(|n| &n.data) {
+        Some(RenderNodeData::Element(element)) if matches!(element.name.as_str(), "a" | "area") => {
+            element.attributes.get("href").map(String::as_str)
+        }
+        _ => None,
+    }
+}
+
+/// The `href` of the anchor that contains the layout node `id`, if any
+pub fn find_link<'a>(layout: &TaffyTree<NodeId>, render_tree: &'a RenderTree, id: TaffyID) -> Option<&'a str> {
+    let mut current = Some(id);
+
+    while let Some(id) = current {
+        if let Some(href) = layout.get_node_context(id).and_then(|gosub_id| link_href(render_tree, *gosub_id)) {
+            return Some(href);
+        }
+
+        current = layout.parent(id);
+    }
+
+    None
+}
+
+/// The links of the document that point to one of the `visited` urls
+pub fn visited_links(render_tree: &RenderTree, base: &Url, visited: &HashSet<Url>) -> HashSet<NodeId> {
+    render_tree
+        .nodes
+        .keys()
+        .filter(|id| {
+            link_href(render_tree, **id)
+                .and_then(|href| resolve_link(base, href))
+                .is_some_and(|url| visited.contains(&url))
+        })
+        .copied()
+        .collect()
+}
+
+/// Resolves an `href` against the url of the document. Links with a scheme we can't load, like
+/// `mailto:` or `javascript:`, resolve to `None`.
+pub fn resolve_link(base: &Url, href: &str) -> Option<Url> {
+    let url = base.join(href.trim()).ok()?;
+
+    match url.scheme() {
+        "http" | "https" | "file" => Some(url),
+        _ => None,
+    }
+}
+
+/// Whether going from `from` to `to` only moves to another fragment of the same document
+pub fn is_same_document(from: &Url, to: &Url) -> bool {
+    to.fragment().is_some() && from[..url::Position::AfterQuery] == to[..url::Position::AfterQuery]
+}
+
+/// The element a fragment points to, an element with that id or otherwise an anchor with that name.
+/// The fragment is percent decoded first.
+pub fn find_fragment_target(render_tree: &RenderTree, fragment: &str) -> Option<NodeId> {
+    let fragment = percent_decode(fragment);
+
+    let find = |attribute: &str, only_anchors: bool| {
+        render_tree
+            .nodes
+            .iter()
+            .filter_map(|(id, node)| match &node.data {
+                RenderNodeData::Element(e) if !only_anchors || e.name == "a" => Some((id, e)),
+                _ => None,
+            })
+            .filter(|(_, e)| e.attributes.get(attribute).is_some_and(|value| *value == fragment))
+            // the first matching element in tree order, node ids grow in document order
+            .min_by_key(|(id, _)| **id)
+            .map(|(id, _)| *id)
+    };
+
+    find("id", false).or_else(|| find("name", true))
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+
+use gosub_html5::node::NodeId;
+use taffy::{Layout, NodeId as TaffyID, Point, TaffyTree, TraversePartialTree};
+use vello::kurbo::{Affine, Rect, RoundedRect, Vec2};
+use vello::peniko::{Color, Fill};
+use vello::Scene;
+
+use crate::compositing::UNBOUNDED;
+use crate::style::{ComputedStyles, ComputedValues};
+
+/// Thickness of the overlay scrollbars that are painted for scroll containers
+pub const SCROLLBAR_WIDTH: f64 = 8.0;
+const SCROLLBAR_MIN_THUMB: f64 = 24.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    #[default]
+    Visible,
+    Hidden,
+    Clip,
+    Scroll,
+    Auto,
+}
+
+impl Overflow {
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value.trim() {
+            "visible" => Overflow::Visible,
+            "hidden" => Overflow::Hidden,
+            "clip" => Overflow::Clip,
+            "scroll" => Overflow::Scroll,
+            "auto" | "overlay" => Overflow::Auto,
+            _ => return None,
+        })
+    }
+
+    pub fn clips(self) -> bool {
+        self != Overflow::Visible
+    }
+
+    pub fn is_scrollable(self) -> bool {
+        matches!(self, Overflow::Hidden | Overflow::Scroll | Overflow::Auto)
+    }
+
+    fn to_taffy(self) -> taffy::Overflow {
+        match self {
+            Overflow::Visible => taffy::Overflow::Visible,
+            Overflow::Clip => taffy::Overflow::Clip,
+            Overflow::Hidden => taffy::Overflow::Hidden,
+            Overflow::Scroll | Overflow::Auto => taffy::Overflow::Scroll,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OverflowStyle {
+    pub x: Overflow,
+    pub y: Overflow,
+}
+
+impl OverflowStyle {
+    pub fn from_values(values: &ComputedValues) -> Self {
+        let mut style = OverflowStyle::default();
+
+        if let Some(overflow) = values.get("overflow") {
+            let mut values = overflow.split_whitespace().filter_map(Overflow::parse);
+            if let Some(x) = values.next() {
+                style.x = x;
+                style.y = values.next().unwrap_or(x);
+            }
+        }
+
+        if let Some(x) = values.get("overflow-x").and_then(Overflow::parse) {
+            style.x = x;
+        }
+
+        if let Some(y) = values.get("overflow-y").and_then(Overflow::parse) {
+            style.y = y;
+        }
+
+        // visible and clip can't be combined with a scrolling axis, see
+        // https://drafts.csswg.org/css-overflow-3/#overflow-properties
+        if style.x.is_scrollable() || style.y.is_scrollable() {
+            style.x = fixup_axis(style.x);
+            style.y = fixup_axis(style.y);
+        }
+
+        style
+    }
+
+    pub fn clips(&self) -> bool {
+        self.x.clips() || self.y.clips()
+    }
+
+    /// Hidden, scroll and auto create a scroll container, clip doesn't
+    pub fn is_scroll_container(&self) -> bool {
+        self.x.is_scrollable() || self.y.is_scrollable()
+    }
+
+    /// Hidden scroll containers can only be scrolled programmatically, not by the user
+    pub fn is_user_scrollable(&self) -> bool {
+        [self.x, self.y].iter().any(|o| matches!(o, Overflow::Scroll | Overflow::Auto))
+    }
+
+    /// The clip region for the descendants. An axis that doesn't clip extends to infinity.
+    pub fn clip_shape(&self, padding_box: Rect, radius: f64) -> RoundedRect {
+        let mut rect = padding_box;
+
+        if !self.x.clips() {
+            rect.x0 = UNBOUNDED.x0;
+            rect.x1 = UNBOUNDED.x1;
+        }
+
+        if !self.y.clips() {
+            rect.y0 = UNBOUNDED.y0;
+            rect.y1 = UNBOUNDED.y1;
+        }
+
+        // the radius only makes sense when both axes are clipped
+        let radius = if self.x.clips() && self.y.clips() { radius } else { 0.0 };
+
+        RoundedRect::from_rect(rect, radius)
+    }
+}
+
+fn fixup_axis(overflow: Overflow) -> Overflow {
+    match overflow {
+        Overflow::Visible => Overflow::Auto,
+        Overflow::Clip => Overflow::Hidden,
+        o => o,
+    }
+}
+
+/// Copies the overflow properties into the taffy styles, so scroll containers don't get stretched
+/// by their content
+pub fn apply_overflow_styles(tree: &mut TaffyTree<NodeId>, root: TaffyID, styles: &ComputedStyles) {
+    let mut stack = vec![root];
+
+    while let Some(id) = stack.pop() {
+        stack.extend(tree.child_ids(id));
+
+        let Some(gosub_id) = tree.get_node_context(id).copied() else {
+            continue;
+        };
+
+        let Some(overflow) = styles.get(gosub_id).map(|s| s.overflow) else {
+            continue;
+        };
+
+        if overflow == OverflowStyle::default() {
+            continue;
+        }
+
+        let Ok(style) = tree.style(id) else {
+            continue;
+        };
+
+        let mut style = style.clone();
+        style.overflow = Point {
+            x: overflow.x.to_taffy(),
+            y: overflow.y.to_taffy(),
+        };
+        // our scrollbars are overlays, they don't take space from the content
+        style.scrollbar_width = 0.0;
+
+        if let Err(e) = tree.set_style(id, style) {
+            eprintln!("Failed to set overflow style: {e:?}");
+        }
+    }
+}
+
+/// Scroll positions of all scroll containers, keyed by their node
+#[derive(Debug, Default, Clone)]
+pub struct ScrollOffsets {
+    offsets: HashMap<NodeId, Vec2>,
+}
+
+impl ScrollOffsets {
+    pub fn get(&self, id: NodeId) -> Vec2 {
+        self.offsets.get(&id).copied().unwrap_or_default()
+    }
+
+    /// Scrolls a container by the given delta, clamped to its scrollable overflow.
+    /// Returns whether the offset changed.
+    pub fn scroll_by(&mut self, id: NodeId, delta: Vec2, layout: &Layout) -> bool {
+        let current = self.get(id);
+        self.scroll_to(id, current + delta, layout)
+    }
+
+    pub fn scroll_to(&mut self, id: NodeId, offset: Vec2, layout: &Layout) -> bool {
+        let max = max_scroll(layout);
+        let clamped = Vec2::new(offset.x.clamp(0.0, max.x), offset.y.clamp(0.0, max.y));
+
+        let previous = self.offsets.insert(id, clamped).unwrap_or_default();
+        previous != clamped
+    }
+
+    pub fn clear(&mut self) {
+        self.offsets.clear();
+    }
+}
+
+/// How far the content of a scroll container can be scrolled
+pub fn max_scroll(layout: &Layout) -> Vec2 {
+    let (width, height) = padding_box_size(layout);
+
+    Vec2::new(
+        (layout.content_size.width as f64 - width).max(0.0),
+        (layout.content_size.height as f64 - height).max(0.0),
+    )
+}
+
+fn padding_box_size(layout: &Layout) -> (f64, f64) {
+    let border = layout.border;
+    (
+        (layout.size.width - border.left - border.right) as f64,
+        (layout.size.height - border.top - border.bottom) as f64,
+    )
+}
+
+/// The vertical and horizontal scrollbar tracks of a scroll container, if they are shown
+pub fn scrollbar_tracks(padding_box: Rect, layout: &Layout, overflow: &OverflowStyle) -> (Option<Rect>, Option<Rect>) {
+    let max = max_scroll(layout);
+
+    let show_y = overflow.y == Overflow::Scroll || (overflow.y == Overflow::Auto && max.y > 0.0);
+    let show_x = overflow.x == Overflow::Scroll || (overflow.x == Overflow::Auto && max.x > 0.0);
+
+    let vertical = show_y.then(|| {
+        Rect::new(
+            padding_box.x1 - SCROLLBAR_WIDTH,
+            padding_box.y0,
+            padding_box.x1,
+            padding_box.y1 - if show_x { SCROLLBAR_WIDTH } else { 0.0 },
+        )
+    });
+
+    let horizontal = show_x.then(|| {
+        Rect::new(
+            padding_box.x0,
+            padding_box.y1 - SCROLLBAR_WIDTH,
+            padding_box.x1 - if show_y { SCROLLBAR_WIDTH } else { 0.0 },
+            padding_box.y1,
+        )
+    });
+
+    (vertical, horizontal)
+}
+
+/// Paints overlay scrollbars on the edges of the padding box of a scroll container
+pub fn draw_scrollbars(
+    scene: &mut Scene,
+    transform: Affine,
+    padding_box: Rect,
+    layout: &Layout,
+    overflow: &OverflowStyle,
+    offset: Vec2,
+) {
+    let max = max_scroll(layout);
+    let (vertical, horizontal) = scrollbar_tracks(padding_box, layout, overflow);
+
+    if let Some(track) = vertical {
+        draw_scrollbar(scene, transform, track, true, padding_box.height(), max.y, offset.y);
+    }
+
+    if let Some(track) = horizontal {
+        draw_scrollbar(scene, transform, track, false, padding_box.width(), max.x, offset.x);
+    }
+}
+
+/// Paints a single scrollbar, `visible` is the visible extent of the content along the scrollbar
+pub fn draw_scrollbar(
+    scene: &mut Scene,
+    transform: Affine,
+    track: Rect,
+    vertical: bool,
+    visible: f64,
+    max_scroll: f64,
+    offset: f64,
+) {
+    let track_color = Color::rgba8(0x80, 0x80, 0x80, 0x40);
+    let thumb_color = Color::rgba8(0xc0, 0xc0, 0xc0, 0xb0);
+
+    scene.fill(Fill::NonZero, transform, track_color, None, &track);
+
+    let length = if vertical { track.height() } else { track.width() };
+    let Some((start, len)) = thumb_extent(length, visible, max_scroll, offset) else {
+        return;
+    };
+
+    let thumb = if vertical {
+        Rect::new(track.x0, track.y0 + start, track.x1, track.y0 + start + len)
+    } else {
+        Rect::new(track.x0 + start, track.y0, track.x0 + start + len, track.y1)
+    };
+
+    let shape = RoundedRect::from_rect(thumb.inflate(-1.0, -1.0), SCROLLBAR_WIDTH / 2.0);
+    scene.fill(Fill::NonZero, transform, thumb_color, None, &shape);
+}
+
+/// Start and length of a scrollbar thumb along its track
+pub fn thumb_extent(track: f64, visible: f64, max_scroll: f64, offset: f64) -> Option<(f64, f64)> {
+    if max_scroll <= 0.0 || track <= 0.0 {
+        return None;
+    }
+
+    let len = (track * visible / (visible + max_scroll)).clamp(SCROLLBAR_MIN_THUMB.min(track), track);
+    let start = (track - len) * (offset / max_scroll).clamp(0.0, 1.0);
+
+    Some((start, len))
+}
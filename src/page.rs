@@ -0,0 +1,533 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use gosub_html5::node::NodeId;
+use gosub_styling::render_tree::RenderTree;
+use url::Url;
+use taffy::NodeId as TaffyID;
+use vello::kurbo::{Affine, Point, Rect, Vec2};
+
+use crate::cursor::{is_selectable, resolve_cursor, Cursor};
+use crate::element_state::{is_focusable, is_tabbable};
+use crate::find::{bounding_box, match_boxes, Find, FindMatch, FindOptions};
+use crate::hit_test::Hit;
+use crate::input::KeyEvent;
+use crate::layout::{LayoutDriver, Viewport};
+use crate::loader::load_document;
+use crate::navigation::{find_fragment_target, find_link, is_same_document, link_href, resolve_link, visited_links, History};
+use crate::overflow::ScrollOffsets;
+use crate::scroll::{scroll_at, DocumentScroll};
+use crate::selection::{TextLookup, TextOrder, TextPosition, TextSelection};
+use crate::selector::StateRules;
+use crate::style::ScrollBehavior;
+use crate::zoom::{step_zoom, VisualZoom};
+
+/// A loaded document with its layout, scroll state and history
+pub struct Page {
+    url: Url,
+    render_tree: RenderTree,
+    driver: LayoutDriver,
+    pub scroll: ScrollOffsets,
+    pub document_scroll: DocumentScroll,
+    history: History,
+    /// Every url loaded in this page, for `:visited`
+    visited: HashSet<Url>,
+    /// Where to scroll to once the document is laid out
+    pending_scroll: Option<PendingScroll>,
+    /// The last pointer position in css pixels, `None` when the pointer is outside the window
+    pointer: Option<Point>,
+    cursor: Cursor,
+    selection: TextSelection,
+    /// The text nodes in document order, which is the order selections run in
+    text_order: TextOrder,
+    find: Find,
+    visual_zoom: VisualZoom,
+}
+
+enum PendingScroll {
+    Fragment(String),
+    Offset(Vec2),
+    Anchor(ScrollAnchor),
+}
+
+/// The node at the top of the viewport when the zoom changed, which stays there after the relayout
+struct ScrollAnchor {
+    node: TaffyID,
+    /// How far down the node the top of the viewport was, from 0 to 1
+    fraction: f64,
+    /// The horizontal scroll offset in window pixels
+    x: f64,
+}
+
+impl Page {
+    pub fn load(url: Url) -> anyhow::Result<Self> {
+        let (render_tree, driver) = load(&url)?;
+
+        let mut page = Self {
+            text_order: TextOrder::new(&render_tree),
+            selection: TextSelection::default(),
+            find: Find::default(),
+            visual_zoom: VisualZoom::default(),
+            history: History::new(url.clone()),
+            visited: HashSet::from([url.clone()]),
+            pending_scroll: url.fragment().map(|f| PendingScroll::Fragment(f.to_string())),
+            url,
+            render_tree,
+            driver,
+            scroll: ScrollOffsets::default(),
+            document_scroll: DocumentScroll::default(),
+            pointer: None,
+            cursor: Cursor::Default,
+        };
+        page.update_visited();
+
+        Ok(page)
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    pub fn render_tree(&self) -> &RenderTree {
+        &self.render_tree
+    }
+
+    pub fn driver(&self) -> &LayoutDriver {
+        &self.driver
+    }
+
+    /// Lays the document out for the `viewport` of the window if needed. Returns whether the layout
+    /// changed.
+    pub fn update(&mut self, viewport: Viewport) -> bool {
+        self.driver.set_viewport(viewport);
+        let changed = self.driver.update(&mut self.render_tree);
+
+        let zoomed = viewport.zoomed(self.zoom());
+        self.document_scroll.set_bounds(zoomed.logical(), self.driver.document_size());
+        self.visual_zoom.set_viewport(viewport.logical());
+
+        match self.pending_scroll.take() {
+            Some(PendingScroll::Fragment(fragment)) => {
+                self.scroll_to_fragment(&fragment, ScrollBehavior::Auto);
+            }
+            Some(PendingScroll::Offset(offset)) => {
+                self.document_scroll.scroll_to(offset, ScrollBehavior::Auto);
+            }
+            Some(PendingScroll::Anchor(anchor)) => {
+                let top = self.driver.document_position(anchor.node).y;
+                let height = self.driver.layout(anchor.node).size.height as f64;
+
+                let offset = Vec2::new(anchor.x / self.zoom(), top + anchor.fraction * height);
+                self.document_scroll.scroll_to(offset, ScrollBehavior::Auto);
+            }
+            None => {}
+        }
+
+        changed
+    }
+
+    pub fn zoom(&self) -> f64 {
+        self.driver.zoom()
+    }
+
+    /// Sets the page zoom, see [`LayoutDriver::set_zoom`]. The node at the top of the viewport
+    /// stays there when the document is laid out again.
+    pub fn set_zoom(&mut self, zoom: f32) -> bool {
+        let anchor = self.scroll_anchor();
+        if !self.driver.set_zoom(zoom) {
+            return false;
+        }
+
+        if self.pending_scroll.is_none() {
+            self.pending_scroll = anchor.map(PendingScroll::Anchor);
+        }
+
+        true
+    }
+
+    /// Goes to the next zoom level, or the previous one when zooming `out`
+    pub fn step_zoom(&mut self, out: bool) -> bool {
+        self.set_zoom(step_zoom(self.zoom(), out) as f32)
+    }
+
+    /// Resets the page zoom and the pinch zoom
+    pub fn reset_zoom(&mut self) -> bool {
+        self.visual_zoom.reset() | self.set_zoom(1.0)
+    }
+
+    pub fn visual_zoom(&self) -> &VisualZoom {
+        &self.visual_zoom
+    }
+
+    /// Pinch zooms by `factor` around `center`, in window pixels. The layout stays the same.
+    pub fn pinch(&mut self, center: Point, factor: f64) -> bool {
+        self.visual_zoom.pinch(center, factor)
+    }
+
+    /// Maps css pixels relative to the viewport to logical pixels of the window
+    pub fn css_to_window(&self) -> Affine {
+        self.visual_zoom.transform() * Affine::scale(self.zoom())
+    }
+
+    fn scroll_anchor(&self) -> Option<ScrollAnchor> {
+        let viewport = self.driver.viewport()?;
+        let hit = self.hit_test(Point::new(viewport.width / 2.0, 0.0))?;
+
+        let top = self.driver.document_position(hit.layout_id).y;
+        let height = self.driver.layout(hit.layout_id).size.height as f64;
+        let offset = self.document_scroll.offset();
+
+        let fraction = match height > 0.0 {
+            true => ((offset.y - top) / height).clamp(0.0, 1.0),
+            false => 0.0,
+        };
+
+        Some(ScrollAnchor {
+            node: hit.layout_id,
+            fraction,
+            x: offset.x * self.zoom(),
+        })
+    }
+
+    /// The `scroll-behavior` of the root element, which applies to the viewport
+    pub fn scroll_behavior(&self) -> ScrollBehavior {
+        self.driver.root_element_style().map_or(ScrollBehavior::Auto, |style| style.scroll_behavior)
+    }
+
+    /// The topmost node at `point`, in css pixels relative to the viewport
+    pub fn hit_test(&self, point: Point) -> Option<Hit> {
+        self.driver.hit_test(&self.render_tree, &self.scroll, self.document_scroll.offset(), point)
+    }
+
+    /// The resolved target of the link at `point`
+    pub fn link_at(&self, point: Point) -> Option<Url> {
+        let hit = self.hit_test(point)?;
+        let href = find_link(self.driver.tree(), &self.render_tree, hit.layout_id)?;
+
+        resolve_link(&self.url, href)
+    }
+
+    pub fn pointer(&self) -> Option<Point> {
+        self.pointer
+    }
+
+    /// The cursor for what is under the pointer
+    pub fn cursor(&self) -> &Cursor {
+        &self.cursor
+    }
+
+    /// The pointer moved to `point`. Returns whether the page has to be redrawn.
+    pub fn pointer_moved(&mut self, point: Point) -> bool {
+        self.pointer = Some(point);
+
+        let hit = self.hit_test(point);
+        self.cursor = resolve_cursor(hit.as_ref(), &self.render_tree, self.driver.styles(), &self.url);
+
+        // custom cursors are painted, so they have to follow the pointer
+        let paints_cursor = matches!(self.cursor, Cursor::Image { .. });
+
+        let selection_changed = match self.text_position(hit.as_ref()) {
+            Some(position) if self.selection.is_dragging() => {
+                let text = text_lookup(&self.render_tree, &self.driver, &self.text_order);
+                self.selection.drag(position, &text)
+            }
+            _ => false,
+        };
+
+        self.driver.set_hover(hit.map(|hit| hit.node)) | paints_cursor | selection_changed
+    }
+
+    /// The pointer left the window
+    pub fn pointer_left(&mut self) -> bool {
+        self.pointer = None;
+        self.cursor = Cursor::Default;
+
+        self.driver.set_hover(None) | self.driver.set_active(None)
+    }
+
+    /// The primary button was pressed at `point`, which activates and focuses what is under it and
+    /// starts selecting text
+    pub fn pointer_pressed(&mut self, point: Point) -> bool {
+        let hit = self.hit_test(point);
+        let node = hit.as_ref().map(|hit| hit.node);
+
+        let had_selection = self.selection.range(&self.text_order).is_some();
+        let position = self.text_position(hit.as_ref());
+        let text = text_lookup(&self.render_tree, &self.driver, &self.text_order);
+        self.selection.press(point, position, &text);
+        let selection_changed = had_selection || self.selection.range(&self.text_order).is_some();
+
+        let focus = node.and_then(|node| {
+            self.driver
+                .styles()
+                .ancestors(node)
+                .find(|id| is_focusable(&self.render_tree, *id))
+        });
+
+        self.driver.set_active(node) | self.driver.set_focus(focus, false) | selection_changed
+    }
+
+    /// The primary button was released
+    pub fn pointer_released(&mut self) -> bool {
+        self.selection.release();
+        self.driver.set_active(None)
+    }
+
+    /// The caret position in the text under the pointer, if it can be selected
+    fn text_position(&self, hit: Option<&Hit>) -> Option<TextPosition> {
+        let hit = hit.filter(|hit| !hit.scrollbar && is_selectable(hit.node, self.driver.styles()))?;
+
+        let text = text_lookup(&self.render_tree, &self.driver, &self.text_order);
+        text.position_at(hit.node, hit.local.to_point())
+    }
+
+    /// The selected text, with line breaks between blocks
+    pub fn selected_text(&self) -> Option<String> {
+        let text = text_lookup(&self.render_tree, &self.driver, &self.text_order);
+        self.selection.selected_text(&text)
+    }
+
+    /// Selects the text of the whole document
+    pub fn select_all(&mut self) -> bool {
+        let text = text_lookup(&self.render_tree, &self.driver, &self.text_order);
+        self.selection.select_all(&text)
+    }
+
+    /// The selected byte range of every text node that is part of the selection, for painting
+    pub fn selection_highlights(&self) -> HashMap<NodeId, Range<usize>> {
+        let text = text_lookup(&self.render_tree, &self.driver, &self.text_order);
+        self.selection.highlights(&text)
+    }
+
+    /// Moves the focus to the next element in the focus order, or the previous one when going
+    /// `backwards`. The focus wraps around at the ends.
+    pub fn focus_next(&mut self, backwards: bool) -> bool {
+        let mut order = Vec::new();
+        let mut stack = vec![NodeId::root()];
+        while let Some(id) = stack.pop() {
+            if is_tabbable(&self.render_tree, id) {
+                order.push(id);
+            }
+
+            if let Some(node) = self.render_tree.get_node(id) {
+                stack.extend(node.children.iter().rev());
+            }
+        }
+
+        if order.is_empty() {
+            return false;
+        }
+
+        let current = self.driver.states().focused().and_then(|f| order.iter().position(|id| *id == f));
+        let next = match (current, backwards) {
+            (None, false) => 0,
+            (None, true) => order.len() - 1,
+            (Some(i), false) => (i + 1) % order.len(),
+            (Some(i), true) => (i + order.len() - 1) % order.len(),
+        };
+
+        self.driver.set_focus(Some(order[next]), true)
+    }
+
+    /// The target of the focused link, for activating it with the keyboard
+    pub fn focused_link(&self) -> Option<Url> {
+        let focused = self.driver.states().focused()?;
+        resolve_link(&self.url, link_href(&self.render_tree, focused)?)
+    }
+
+    /// Scrolls whatever is under `point` by `delta`, see [`scroll_at`]
+    pub fn scroll_at(&mut self, point: Point, delta: Vec2) -> bool {
+        scroll_at(&self.driver, &self.render_tree, &mut self.scroll, &mut self.document_scroll, point, delta)
+    }
+
+    /// Handles the scrolling keys
+    pub fn handle_key(&mut self, event: &KeyEvent) -> bool {
+        let behavior = self.scroll_behavior();
+        self.document_scroll.handle_key(event, behavior)
+    }
+
+    /// Searches the text of the page and scrolls to the first match, returns the matches
+    pub fn find(&mut self, query: &str, options: FindOptions) -> &[FindMatch] {
+        let text = text_lookup(&self.render_tree, &self.driver, &self.text_order);
+        self.find.search(&text, query, options);
+        self.scroll_to_current_match();
+
+        self.find.matches()
+    }
+
+    /// Moves to the next match of the last search, or the previous one when going `backwards`
+    pub fn find_next(&mut self, backwards: bool) -> bool {
+        if self.find.advance(backwards).is_none() {
+            return false;
+        }
+
+        self.scroll_to_current_match();
+        true
+    }
+
+    /// Ends the search and removes its highlights
+    pub fn stop_finding(&mut self) -> bool {
+        self.find.clear()
+    }
+
+    pub fn find_state(&self) -> &Find {
+        &self.find
+    }
+
+    /// The boxes of the glyphs of a match, in css pixels relative to the document
+    pub fn match_boxes(&self, found: &FindMatch) -> Vec<Rect> {
+        let text = text_lookup(&self.render_tree, &self.driver, &self.text_order);
+        match_boxes(found, &text, &self.driver)
+    }
+
+    /// The matched byte ranges of every text node, with whether they belong to the current match
+    pub fn find_highlights(&self) -> HashMap<NodeId, Vec<(Range<usize>, bool)>> {
+        self.find.highlights()
+    }
+
+    fn scroll_to_current_match(&mut self) {
+        let Some(current) = self.find.current() else {
+            return;
+        };
+
+        if let Some(rect) = bounding_box(&self.match_boxes(current)) {
+            let behavior = self.scroll_behavior();
+            self.document_scroll.scroll_into_view(rect, behavior);
+        }
+    }
+
+    /// Scrolls the element a fragment points to into view, returns whether it exists
+    pub fn scroll_to_fragment(&mut self, fragment: &str, behavior: ScrollBehavior) -> bool {
+        let target = find_fragment_target(&self.render_tree, fragment);
+
+        // an empty fragment and #top go to the top of the document, unless an element has that id
+        if target.is_none() && (fragment.is_empty() || fragment.eq_ignore_ascii_case("top")) {
+            self.document_scroll.scroll_to(Vec2::ZERO, behavior);
+            return true;
+        }
+
+        let Some(target) = target else {
+            return false;
+        };
+
+        let Some(id) = self.driver.layout_node(target) else {
+            return false;
+        };
+
+        let position = self.driver.document_position(id);
+        self.document_scroll.scroll_to(position.to_vec2(), behavior);
+
+        true
+    }
+
+    /// Follows a link. If only the fragment changed the current document is scrolled, otherwise the
+    /// target document is loaded and replaces this one.
+    pub fn navigate(&mut self, url: Url) -> anyhow::Result<()> {
+        if is_same_document(&self.url, &url) {
+            let fragment = url.fragment().unwrap_or_default().to_string();
+
+            self.history.push(url.clone(), self.document_scroll.offset());
+            self.url = url;
+            self.scroll_to_fragment(&fragment, self.scroll_behavior());
+            self.update_visited();
+
+            return Ok(());
+        }
+
+        let scroll = self.document_scroll.offset();
+        self.replace_document(url.clone())?;
+        self.history.push(url, scroll);
+
+        Ok(())
+    }
+
+    /// Goes back in the history, returns whether there was anything to go back to. The history
+    /// only moves once the entry is loaded.
+    pub fn back(&mut self) -> anyhow::Result<bool> {
+        let Some(entry) = self.history.previous_entry().cloned() else {
+            return Ok(false);
+        };
+
+        let scroll = self.document_scroll.offset();
+        self.restore(entry.url, entry.scroll)?;
+        self.history.back(scroll);
+
+        Ok(true)
+    }
+
+    /// Goes forward in the history, returns whether there was anything to go forward to
+    pub fn forward(&mut self) -> anyhow::Result<bool> {
+        let Some(entry) = self.history.next_entry().cloned() else {
+            return Ok(false);
+        };
+
+        let scroll = self.document_scroll.offset();
+        self.restore(entry.url, entry.scroll)?;
+        self.history.forward(scroll);
+
+        Ok(true)
+    }
+
+    fn restore(&mut self, url: Url, scroll: Vec2) -> anyhow::Result<()> {
+        if url[..url::Position::AfterQuery] != self.url[..url::Position::AfterQuery] {
+            self.replace_document(url.clone())?;
+        }
+
+        self.url = url;
+        self.pending_scroll = Some(PendingScroll::Offset(scroll));
+        self.update_visited();
+
+        Ok(())
+    }
+
+    fn update_visited(&mut self) {
+        self.visited.insert(self.url.clone());
+
+        let links = visited_links(&self.render_tree, &self.url, &self.visited);
+        self.driver.set_visited(links);
+    }
+
+    /// Loads `url` and replaces the current document, the history is kept
+    fn replace_document(&mut self, url: Url) -> anyhow::Result<()> {
+        let (render_tree, mut driver) = load(&url)?;
+
+        // the zoom stays when following links
+        driver.set_zoom(self.zoom() as f32);
+        if let Some(window) = self.driver.window_viewport() {
+            driver.set_viewport(window);
+        }
+
+        self.text_order = TextOrder::new(&render_tree);
+        self.selection.clear();
+        self.find.clear();
+        self.render_tree = render_tree;
+        self.driver = driver;
+        self.scroll = ScrollOffsets::default();
+        self.document_scroll = DocumentScroll {
+            smooth: self.document_scroll.smooth,
+            ..Default::default()
+        };
+        self.pending_scroll = url.fragment().map(|f| PendingScroll::Fragment(f.to_string()));
+        self.url = url;
+        self.update_visited();
+
+        Ok(())
+    }
+}
+
+fn text_lookup<'a>(render_tree: &'a RenderTree, driver: &'a LayoutDriver, order: &'a TextOrder) -> TextLookup<'a> {
+    TextLookup {
+        render_tree,
+        styles: driver.styles(),
+        order,
+    }
+}
+
+fn load(url: &Url) -> anyhow::Result<(RenderTree, LayoutDriver)> {
+    let mut document = load_document(url)?;
+
+    let rules = StateRules::parse(document.style_sheets.iter().map(String::as_str));
+    let driver = LayoutDriver::new(&mut document.render_tree, rules)?;
+
+    Ok((document.render_tree, driver))
+}
@@ -0,0 +1,220 @@
+use std::time::{Duration, Instant};
+
+use gosub_styling::render_tree::RenderTree;
+use taffy::{PrintTree, TraversePartialTree};
+use vello::kurbo::{Affine, Point, Rect, Vec2};
+use vello::Scene;
+
+use crate::input::{ButtonState, Key, KeyEvent, NamedKey};
+use crate::layout::LayoutDriver;
+use crate::overflow::{draw_scrollbar, ScrollOffsets, SCROLLBAR_WIDTH};
+use crate::style::ScrollBehavior;
+
+/// Distance scrolled by the arrow keys, in css pixels
+pub const ARROW_SCROLL: f64 = 40.0;
+
+/// Part of the viewport that stays visible when scrolling by a page
+const PAGE_OVERLAP: f64 = 0.125;
+
+const SMOOTH_SCROLL_DURATION: Duration = Duration::from_millis(150);
+
+/// The scroll position of the viewport over the document
+#[derive(Debug, Clone, Default)]
+pub struct DocumentScroll {
+    offset: Vec2,
+    max: Vec2,
+    viewport: (f64, f64),
+    animation: Option<ScrollAnimation>,
+    /// Animate scrolling by lines and pages even if the document doesn't ask for smooth scrolling
+    pub smooth: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ScrollAnimation {
+    from: Vec2,
+    to: Vec2,
+    start: Instant,
+}
+
+impl DocumentScroll {
+    pub fn offset(&self) -> Vec2 {
+        self.offset
+    }
+
+    pub fn max_scroll(&self) -> Vec2 {
+        self.max
+    }
+
+    /// Where the viewport ends up once the running animation finished
+    pub fn target(&self) -> Vec2 {
+        self.animation.map_or(self.offset, |a| a.to)
+    }
+
+    pub fn is_animating(&self) -> bool {
+        self.animation.is_some()
+    }
+
+    /// Updates the scrollable area after a relayout, the offset is clamped to it
+    pub fn set_bounds(&mut self, viewport: (f64, f64), document: (f64, f64)) {
+        self.viewport = viewport;
+        self.max = Vec2::new((document.0 - viewport.0).max(0.0), (document.1 - viewport.1).max(0.0));
+        self.offset = self.clamp(self.offset);
+
+        if let Some(animation) = &mut self.animation {
+            animation.to = clamp(animation.to, self.max);
+        }
+    }
+
+    fn clamp(&self, offset: Vec2) -> Vec2 {
+        clamp(offset, self.max)
+    }
+
+    /// Returns whether the offset changes
+    pub fn scroll_to(&mut self, target: Vec2, behavior: ScrollBehavior) -> bool {
+        let target = self.clamp(target);
+        if target == self.target() {
+            return false;
+        }
+
+        if behavior == ScrollBehavior::Smooth || self.smooth {
+            self.animation = Some(ScrollAnimation {
+                from: self.offset,
+                to: target,
+                start: Instant::now(),
+            });
+        } else {
+            self.animation = None;
+            self.offset = target;
+        }
+
+        true
+    }
+
+    /// Scrolls relative to the target of a running animation, so repeated key presses add up
+    pub fn scroll_by(&mut self, delta: Vec2, behavior: ScrollBehavior) -> bool {
+        self.scroll_to(self.target() + delta, behavior)
+    }
+
+    /// Scrolls immediately and cancels a running animation, for trackpads that scroll by pixels
+    pub fn scroll_by_instant(&mut self, delta: Vec2) -> bool {
+        let target = self.clamp(self.target() + delta);
+        self.animation = None;
+
+        let changed = target != self.offset;
+        self.offset = target;
+        changed
+    }
+
+    /// Centers `rect`, in document coordinates, in the viewport unless it is already fully visible
+    pub fn scroll_into_view(&mut self, rect: Rect, behavior: ScrollBehavior) -> bool {
+        let visible = Rect::from_origin_size(self.target().to_point(), self.viewport);
+        if visible.contains(rect.origin()) && visible.contains(Point::new(rect.x1, rect.y1)) {
+            return false;
+        }
+
+        let center = rect.center() - Vec2::new(self.viewport.0, self.viewport.1) / 2.0;
+        self.scroll_to(center.to_vec2(), behavior)
+    }
+
+    /// Advances the smooth scroll animation, returns whether it is still running
+    pub fn tick(&mut self, now: Instant) -> bool {
+        let Some(animation) = self.animation else {
+            return false;
+        };
+
+        let t = now.duration_since(animation.start).as_secs_f64() / SMOOTH_SCROLL_DURATION.as_secs_f64();
+        if t >= 1.0 {
+            self.offset = animation.to;
+            self.animation = None;
+            return false;
+        }
+
+        // ease out cubic
+        let eased = 1.0 - (1.0 - t).powi(3);
+        self.offset = animation.from.lerp(animation.to, eased);
+
+        true
+    }
+
+    /// Handles the scrolling keys, returns whether the key scrolled the document
+    pub fn handle_key(&mut self, event: &KeyEvent, behavior: ScrollBehavior) -> bool {
+        if event.state != ButtonState::Pressed || event.modifiers.alt || event.modifiers.ctrl || event.modifiers.meta {
+            return false;
+        }
+
+        let page = self.viewport.1 * (1.0 - PAGE_OVERLAP);
+        let target = self.target();
+
+        let delta = match &event.key {
+            Key::Named(NamedKey::ArrowDown) => Vec2::new(0.0, ARROW_SCROLL),
+            Key::Named(NamedKey::ArrowUp) => Vec2::new(0.0, -ARROW_SCROLL),
+            Key::Named(NamedKey::ArrowRight) => Vec2::new(ARROW_SCROLL, 0.0),
+            Key::Named(NamedKey::ArrowLeft) => Vec2::new(-ARROW_SCROLL, 0.0),
+            Key::Named(NamedKey::PageDown) => Vec2::new(0.0, page),
+            Key::Named(NamedKey::PageUp) => Vec2::new(0.0, -page),
+            Key::Named(NamedKey::Space) if event.modifiers.shift => Vec2::new(0.0, -page),
+            Key::Named(NamedKey::Space) => Vec2::new(0.0, page),
+            Key::Named(NamedKey::Home) => Vec2::new(0.0, -target.y),
+            Key::Named(NamedKey::End) => Vec2::new(0.0, self.max.y - target.y),
+            _ => return false,
+        };
+
+        self.scroll_by(delta, behavior)
+    }
+}
+
+fn clamp(offset: Vec2, max: Vec2) -> Vec2 {
+    Vec2::new(offset.x.clamp(0.0, max.x), offset.y.clamp(0.0, max.y))
+}
+
+/// Scrolls the innermost scroll container under `point` that can still scroll in the direction of
+/// `delta`, or the document if none of them can. Returns whether anything scrolled.
+pub fn scroll_at(
+    driver: &LayoutDriver,
+    render_tree: &RenderTree,
+    elements: &mut ScrollOffsets,
+    document: &mut DocumentScroll,
+    point: Point,
+    delta: Vec2,
+) -> bool {
+    if let Some(hit) = driver.hit_test(render_tree, elements, document.offset(), point) {
+        let tree = driver.tree();
+        let mut current = Some(hit.layout_id);
+
+        while let Some(id) = current {
+            let scrollable = tree
+                .get_node_context(id)
+                .and_then(|gosub_id| driver.styles().get(*gosub_id).map(|s| (*gosub_id, s)))
+                .filter(|(_, style)| style.overflow.is_user_scrollable());
+
+            if let Some((gosub_id, _)) = scrollable {
+                if elements.scroll_by(gosub_id, delta, tree.get_final_layout(id)) {
+                    return true;
+                }
+            }
+
+            current = tree.parent(id);
+        }
+    }
+
+    document.scroll_by_instant(delta)
+}
+
+/// Paints the scrollbars of the viewport, above everything else
+pub fn draw_document_scrollbars(scene: &mut Scene, transform: Affine, scroll: &DocumentScroll) {
+    let (width, height) = scroll.viewport;
+    let max = scroll.max;
+
+    let show_x = max.x > 0.0;
+    let show_y = max.y > 0.0;
+
+    if show_y {
+        let track = Rect::new(width - SCROLLBAR_WIDTH, 0.0, width, height - if show_x { SCROLLBAR_WIDTH } else { 0.0 });
+        draw_scrollbar(scene, transform, track, true, height, max.y, scroll.offset.y);
+    }
+
+    if show_x {
+        let track = Rect::new(0.0, height - SCROLLBAR_WIDTH, width - if show_y { SCROLLBAR_WIDTH } else { 0.0 }, height);
+        draw_scrollbar(scene, transform, track, false, width, max.x, scroll.offset.x);
+    }
+}
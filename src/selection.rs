@@ -0,0 +1,472 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use gosub_html5::node::NodeId;
+use gosub_styling::prerender_text::PrerenderText;
+use gosub_styling::render_tree::{RenderNodeData, RenderTree};
+use vello::kurbo::{Point, Rect};
+
+use crate::style::ComputedStyles;
+use crate::text::TextRenderer;
+
+/// Clicks closer together than this count as a double or triple click
+const MULTI_CLICK_TIME: Duration = Duration::from_millis(500);
+/// How far the pointer may move between the clicks of a multi-click, in css pixels
+const MULTI_CLICK_DISTANCE: f64 = 4.0;
+
+/// A caret position, a byte offset into the text of a text node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextPosition {
+    pub node: NodeId,
+    pub offset: usize,
+}
+
+/// What a drag extends the selection by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Granularity {
+    #[default]
+    Character,
+    Word,
+    /// The text of the whole block, which is what a triple click selects
+    Line,
+}
+
+impl Granularity {
+    fn from_clicks(clicks: u32) -> Self {
+        match clicks {
+            0 | 1 => Granularity::Character,
+            2 => Granularity::Word,
+            _ => Granularity::Line,
+        }
+    }
+}
+
+/// The text nodes of a document in tree order, selections are ranges in this order
+#[derive(Debug, Clone, Default)]
+pub struct TextOrder {
+    nodes: Vec<NodeId>,
+    index: HashMap<NodeId, usize>,
+}
+
+impl TextOrder {
+    pub fn new(render_tree: &RenderTree) -> Self {
+        let mut nodes = Vec::new();
+        let mut stack = vec![NodeId::root()];
+
+        while let Some(id) = stack.pop() {
+            let Some(node) = render_tree.get_node(id) else {
+                continue;
+            };
+
+            if matches!(node.data, RenderNodeData::Text(_)) {
+                nodes.push(id);
+            }
+
+            stack.extend(node.children.iter().rev());
+        }
+
+        let index = nodes.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+
+        Self { nodes, index }
+    }
+
+    fn index(&self, position: &TextPosition) -> (usize, usize) {
+        (self.index.get(&position.node).copied().unwrap_or(usize::MAX), position.offset)
+    }
+
+    pub fn nodes(&self) -> &[NodeId] {
+        &self.nodes
+    }
+
+    fn first(&self) -> Option<NodeId> {
+        self.nodes.first().copied()
+    }
+
+    fn last(&self) -> Option<NodeId> {
+        self.nodes.last().copied()
+    }
+}
+
+/// The selected text of a document and the state of the pointer gesture that makes it
+#[derive(Debug, Clone, Default)]
+pub struct TextSelection {
+    /// The word or line where the selection started, a single position for character selections
+    anchor: Option<(TextPosition, TextPosition)>,
+    /// The word or line the selection was extended to
+    focus: Option<(TextPosition, TextPosition)>,
+    granularity: Granularity,
+    dragging: bool,
+    last_click: Option<(Instant, Point, u32)>,
+}
+
+impl TextSelection {
+    /// Removes the selection, returns whether there was one
+    pub fn clear(&mut self) -> bool {
+        let had_selection = self.anchor.is_some();
+        self.anchor = None;
+        self.focus = None;
+        self.dragging = false;
+        had_selection
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.dragging
+    }
+
+    /// Starts a selection at a pointer press. The number of clicks in quick succession decides
+    /// whether characters, words or lines are selected.
+    pub fn press(&mut self, point: Point, position: Option<TextPosition>, text: &TextLookup) {
+        let now = Instant::now();
+        let clicks = match self.last_click {
+            Some((time, last, clicks)) if now - time < MULTI_CLICK_TIME && (point - last).hypot() < MULTI_CLICK_DISTANCE => {
+                clicks + 1
+            }
+            _ => 1,
+        };
+        self.last_click = Some((now, point, clicks));
+
+        let Some(position) = position else {
+            self.clear();
+            return;
+        };
+
+        self.granularity = Granularity::from_clicks(clicks);
+        let unit = text.expand(position, self.granularity);
+
+        self.anchor = Some(unit);
+        self.focus = Some(unit);
+        self.dragging = true;
+    }
+
+    /// Extends the selection to `position` while dragging, returns whether it changed
+    pub fn drag(&mut self, position: TextPosition, text: &TextLookup) -> bool {
+        if !self.dragging || self.anchor.is_none() {
+            return false;
+        }
+
+        let unit = Some(text.expand(position, self.granularity));
+        if self.focus == unit {
+            return false;
+        }
+
+        self.focus = unit;
+        true
+    }
+
+    pub fn release(&mut self) {
+        self.dragging = false;
+    }
+
+    /// Selects all text of the document
+    pub fn select_all(&mut self, text: &TextLookup) -> bool {
+        let (Some(first), Some(last)) = (text.order.first(), text.order.last()) else {
+            return false;
+        };
+
+        let start = TextPosition { node: first, offset: 0 };
+        let end = TextPosition {
+            node: last,
+            offset: text.text(last).map_or(0, str::len),
+        };
+
+        self.granularity = Granularity::Character;
+        self.anchor = Some((start, start));
+        self.focus = Some((end, end));
+        true
+    }
+
+    /// The selected range in document order, `None` if nothing is selected
+    pub fn range(&self, order: &TextOrder) -> Option<(TextPosition, TextPosition)> {
+        let (anchor_start, anchor_end) = self.anchor?;
+        let (focus_start, focus_end) = self.focus?;
+
+        let key = |position: &TextPosition| order.index(position);
+        let start = std::cmp::min_by_key(anchor_start, focus_start, key);
+        let end = std::cmp::max_by_key(anchor_end, focus_end, key);
+
+        (start != end).then_some((start, end))
+    }
+
+    /// The selected byte range of every text node that is at least partly selected
+    pub fn highlights(&self, text: &TextLookup) -> HashMap<NodeId, Range<usize>> {
+        let mut highlights = HashMap::new();
+
+        let Some((start, end)) = self.range(text.order) else {
+            return highlights;
+        };
+
+        let (first, _) = text.order.index(&start);
+        let (last, _) = text.order.index(&end);
+
+        for (i, node) in text.order.nodes.iter().enumerate().take(last.saturating_add(1)).skip(first) {
+            let len = text.text(*node).map_or(0, str::len);
+
+            let from = if i == first { start.offset.min(len) } else { 0 };
+            let to = if i == last { end.offset.min(len) } else { len };
+
+            if from < to {
+                highlights.insert(*node, from..to);
+            }
+        }
+
+        highlights
+    }
+
+    /// The selected text, text of different blocks is separated by line breaks
+    pub fn selected_text(&self, text: &TextLookup) -> Option<String> {
+        let highlights = self.highlights(text);
+        if highlights.is_empty() {
+            return None;
+        }
+
+        let mut result = String::new();
+        let mut last_block = None;
+
+        for node in text.order.nodes.iter() {
+            let (Some(range), Some(content)) = (highlights.get(node), text.text(*node)) else {
+                continue;
+            };
+
+            let block = text.block(*node);
+            if last_block.is_some() && last_block != block {
+                result.push('\n');
+            }
+            last_block = block;
+
+            result.push_str(&content[range.clone()]);
+        }
+
+        Some(result)
+    }
+}
+
+/// Access to the text of a document for selecting it
+pub struct TextLookup<'a> {
+    pub render_tree: &'a RenderTree,
+    pub styles: &'a ComputedStyles,
+    pub order: &'a TextOrder,
+}
+
+impl TextLookup<'_> {
+    pub fn prerendered(&self, node: NodeId) -> Option<&PrerenderText> {
+        match &self.render_tree.get_node(node)?.data {
+            RenderNodeData::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn text(&self, node: NodeId) -> Option<&str> {
+        self.prerendered(node).map(|t| t.text.as_str())
+    }
+
+    /// The nearest ancestor that is not inline-level, text in different blocks is on different lines
+    pub fn block(&self, node: NodeId) -> Option<NodeId> {
+        self.styles
+            .ancestors(node)
+            .skip(1)
+            .find(|id| self.styles.get(*id).is_some_and(|s| !s.display.is_inline_level()))
+    }
+
+    /// The range of the word or block around a position
+    fn expand(&self, position: TextPosition, granularity: Granularity) -> (TextPosition, TextPosition) {
+        let Some(text) = self.text(position.node) else {
+            return (position, position);
+        };
+
+        let at = |offset| TextPosition {
+            node: position.node,
+            offset,
+        };
+
+        match granularity {
+            Granularity::Character => (position, position),
+            Granularity::Word => {
+                let (start, end) = word_range(text, position.offset);
+                (at(start), at(end))
+            }
+            Granularity::Line => {
+                let block = self.block(position.node);
+                let nodes = self.order.nodes.iter().filter(|n| self.block(**n) == block);
+
+                let first = nodes.clone().next().copied().unwrap_or(position.node);
+                let last = nodes.last().copied().unwrap_or(position.node);
+
+                (
+                    TextPosition { node: first, offset: 0 },
+                    TextPosition {
+                        node: last,
+                        offset: self.text(last).map_or(0, str::len),
+                    },
+                )
+            }
+        }
+    }
+
+    /// The caret position closest to `local`, a point relative to the origin of the text node
+    pub fn position_at(&self, node: NodeId, local: Point) -> Option<TextPosition> {
+        let text = self.prerendered(node)?;
+        let style = self.styles.get(node)?;
+
+        let renderer = TextRenderer::new(style.font.families.clone(), style.font.size as f32);
+        let carets = caret_positions(text, &renderer);
+
+        // the line closest to the point, then the closest caret on that line
+        let line = carets
+            .iter()
+            .map(|c| c.line)
+            .min_by(|a, b| distance(a.0, a.1, local.y).total_cmp(&distance(b.0, b.1, local.y)))?;
+
+        let offset = carets
+            .iter()
+            .filter(|c| c.line == line)
+            .min_by(|a, b| (a.x - local.x).abs().total_cmp(&(b.x - local.x).abs()))?
+            .offset;
+
+        Some(TextPosition { node, offset })
+    }
+}
+
+/// How far `y` is outside of the range from `top` to `bottom`
+fn distance(top: f64, bottom: f64, y: f64) -> f64 {
+    (top - y).max(y - bottom).max(0.0)
+}
+
+/// A position between two characters and where it is painted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Caret {
+    pub offset: usize,
+    pub x: f64,
+    /// The top and bottom of the line, relative to the text node
+    pub line: (f64, f64),
+}
+
+/// The caret positions of a shaped text, before every character and after the last character of
+/// every line. The glyphs are placed one per character with no glyph for line breaks, when shaping
+/// merged or split characters the carets are spaced by the advance of each character instead.
+pub fn caret_positions(text: &PrerenderText, renderer: &TextRenderer) -> Vec<Caret> {
+    let offsets = text
+        .text
+        .char_indices()
+        .filter(|(_, c)| *c != '\n')
+        .map(|(i, _)| i)
+        .chain(std::iter::once(text.text.len()))
+        .collect::<Vec<_>>();
+
+    let line = |y: f32| {
+        let top = (y - renderer.ascent) as f64;
+        (top, top + renderer.line_height as f64)
+    };
+
+    if text.glyphs.len() != offsets.len() - 1 {
+        let Some(first) = text.glyphs.first() else {
+            return Vec::new();
+        };
+
+        let mut x = first.x as f64;
+        let mut carets = Vec::with_capacity(offsets.len());
+        for (offset, c) in text.text.char_indices().filter(|(_, c)| *c != '\n') {
+            carets.push(Caret {
+                offset,
+                x,
+                line: line(first.y),
+            });
+            x += renderer.char_advance(c) as f64;
+        }
+        carets.push(Caret {
+            offset: text.text.len(),
+            x,
+            line: line(first.y),
+        });
+
+        return carets;
+    }
+
+    let mut carets = Vec::with_capacity(text.glyphs.len() + 1);
+
+    for (i, glyph) in text.glyphs.iter().enumerate().take(offsets.len() - 1) {
+        carets.push(Caret {
+            offset: offsets[i],
+            x: glyph.x as f64,
+            line: line(glyph.y),
+        });
+
+        // the end of a line is a position as well
+        let next = text.glyphs.get(i + 1).filter(|_| i + 1 < offsets.len() - 1);
+        if next.map_or(true, |next| next.y != glyph.y) {
+            carets.push(Caret {
+                offset: offsets[i + 1],
+                x: (glyph.x + renderer.glyph_advance(glyph.id)) as f64,
+                line: line(glyph.y),
+            });
+        }
+    }
+
+    carets
+}
+
+/// The areas to paint the highlight of the selected `range` of a text node in, one per line
+pub fn highlight_rects(text: &PrerenderText, renderer: &TextRenderer, range: &Range<usize>) -> Vec<Rect> {
+    let carets = caret_positions(text, renderer);
+    let mut rects: Vec<Rect> = Vec::new();
+
+    for pair in carets.windows(2) {
+        let (caret, next) = (pair[0], pair[1]);
+        if !range.contains(&caret.offset) || caret.line != next.line {
+            continue;
+        }
+
+        match rects.last_mut() {
+            Some(rect) if rect.y0 == caret.line.0 => rect.x1 = next.x,
+            _ => rects.push(Rect::new(caret.x, caret.line.0, next.x, caret.line.1)),
+        }
+    }
+
+    rects
+}
+
+/// The word around a byte offset. Runs of letters and digits are words, as are runs of whitespace,
+/// any other character is a word by itself.
+fn word_range(text: &str, offset: usize) -> (usize, usize) {
+    #[derive(PartialEq)]
+    enum Class {
+        Word,
+        Space,
+        Other,
+    }
+
+    let class = |c: char| {
+        if c.is_alphanumeric() || c == '_' || c == '\'' {
+            Class::Word
+        } else if c.is_whitespace() {
+            Class::Space
+        } else {
+            Class::Other
+        }
+    };
+
+    let offset = offset.min(text.len());
+    let Some(c) = text[offset..].chars().next().or_else(|| text[..offset].chars().last()) else {
+        return (offset, offset);
+    };
+
+    let target = class(c);
+    if target == Class::Other {
+        let start = if text[offset..].starts_with(c) { offset } else { offset - c.len_utf8() };
+        return (start, start + c.len_utf8());
+    }
+
+    let start = text[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| class(*c) == target)
+        .last()
+        .map_or(offset, |(i, _)| i);
+
+    let end = text[offset..]
+        .char_indices()
+        .find(|(_, c)| class(*c) != target)
+        .map_or(text.len(), |(i, _)| offset + i);
+
+    (start, end)
+}
@@ -0,0 +1,777 @@
+use std::collections::HashMap;
+
+use gosub_html5::node::NodeId;
+use gosub_styling::render_tree::{RenderNodeData, RenderTree};
+
+use crate::color::parse_color;
+use crate::css::{find_unquoted, for_each_top_level, split_top_level};
+use crate::element_state::{ElementStates, PseudoClass};
+use crate::media::MediaQueryList;
+
+/// Selectors for the rules the styling crate can't cascade, because they depend on the state of an
+/// element. Only the common subset is supported: type, id, class and attribute selectors, the
+/// state pseudo-classes and descendant and child combinators.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    /// The compound selectors from right to left, each with the combinator to its left neighbour
+    compounds: Vec<(Compound, Combinator)>,
+    pub pseudo_element: Option<PseudoElement>,
+}
+
+/// The pseudo-elements that can be styled by the state rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PseudoElement {
+    Selection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Combinator {
+    Descendant,
+    Child,
+    /// The leftmost compound has nothing to its left
+    None,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Compound {
+    tag: Option<String>,
+    ids: Vec<String>,
+    classes: Vec<String>,
+    attributes: Vec<AttributeSelector>,
+    pseudo_classes: Vec<PseudoClass>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct AttributeSelector {
+    name: String,
+    value: Option<(AttributeOperator, String)>,
+    /// Values are compared case-insensitively with the `i` flag
+    case_sensitive: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AttributeOperator {
+    Equals,
+    Includes,
+    DashMatch,
+    Prefix,
+    Suffix,
+    Substring,
+}
+
+/// The state dependent rules together with the current state of the elements
+#[derive(Debug, Clone, Copy)]
+pub struct StateCascade<'a> {
+    pub rules: &'a StateRules,
+    pub states: &'a ElementStates,
+    /// The viewport in css pixels, which media queries are evaluated against
+    pub viewport: (f64, f64),
+}
+
+/// Everything the matcher needs to know about the document
+pub struct MatchContext<'a> {
+    pub render_tree: &'a RenderTree,
+    pub parents: &'a HashMap<NodeId, NodeId>,
+    pub states: &'a ElementStates,
+}
+
+impl Selector {
+    pub fn parse(selector: &str) -> Option<Self> {
+        let mut compounds = Vec::new();
+        let mut combinator = Combinator::None;
+
+        let mut selector = selector.trim().to_string();
+        let mut pseudo_element = None;
+
+        let lower = selector.to_ascii_lowercase();
+        if lower.ends_with("::selection") {
+            selector.truncate(selector.len() - "::selection".len());
+            pseudo_element = Some(PseudoElement::Selection);
+
+            // `::selection` on its own applies to every element
+            if selector.is_empty() || selector.ends_with(|c: char| c.is_whitespace() || c == '>') {
+                selector.push('*');
+            }
+        }
+
+        for token in selector_tokens(&selector) {
+            if token == ">" {
+                // a child combinator needs a compound on both sides
+                if compounds.is_empty() || combinator == Combinator::Child {
+                    return None;
+                }
+                combinator = Combinator::Child;
+                continue;
+            }
+
+            compounds.push((parse_compound(token)?, combinator));
+            combinator = Combinator::Descendant;
+        }
+
+        if compounds.is_empty() || combinator == Combinator::Child {
+            return None;
+        }
+
+        // matching starts at the subject, which is the rightmost compound
+        compounds.reverse();
+
+        Some(Self { compounds, pseudo_element })
+    }
+
+    /// The specificity as (ids, classes, types)
+    pub fn specificity(&self) -> (u32, u32, u32) {
+        self.compounds.iter().fold((0, 0, 0), |(a, b, c), (compound, _)| {
+            (
+                a + compound.ids.len() as u32,
+                b + (compound.classes.len() + compound.attributes.len() + compound.pseudo_classes.len()) as u32,
+                c + compound.tag.is_some() as u32,
+            )
+        })
+    }
+
+    pub fn uses(&self, pseudo: PseudoClass) -> bool {
+        self.compounds.iter().any(|(c, _)| c.pseudo_classes.contains(&pseudo))
+    }
+
+    pub fn matches(&self, id: NodeId, cx: &MatchContext) -> bool {
+        self.matches_from(0, id, cx)
+    }
+
+    fn matches_from(&self, index: usize, id: NodeId, cx: &MatchContext) -> bool {
+        let Some((compound, combinator)) = self.compounds.get(index) else {
+            return true;
+        };
+
+        if !compound.matches(id, cx) {
+            return false;
+        }
+
+        match combinator {
+            Combinator::None => true,
+            Combinator::Child => cx.parents.get(&id).is_some_and(|parent| self.matches_from(index + 1, *parent, cx)),
+            Combinator::Descendant => {
+                let mut current = cx.parents.get(&id);
+                while let Some(ancestor) = current {
+                    if self.matches_from(index + 1, *ancestor, cx) {
+                        return true;
+                    }
+                    current = cx.parents.get(ancestor);
+                }
+                false
+            }
+        }
+    }
+}
+
+impl Compound {
+    fn matches(&self, id: NodeId, cx: &MatchContext) -> bool {
+        let Some(RenderNodeData::Element(element)) = cx.render_tree.get_node(id).map(|n| &n.data) else {
+            return false;
+        };
+
+        if self.tag.as_ref().is_some_and(|tag| !tag.eq_ignore_ascii_case(&element.name)) {
+            return false;
+        }
+
+        let attribute = |name: &str| element.attributes.get(name).map(String::as_str);
+
+        if !self.ids.iter().all(|id| attribute("id") == Some(id.as_str())) {
+            return false;
+        }
+
+        let classes = attribute("class").unwrap_or_default();
+        if !self.classes.iter().all(|class| classes.split_whitespace().any(|c| c == class)) {
+            return false;
+        }
+
+        if !self.attributes.iter().all(|a| a.matches(attribute(&a.name))) {
+            return false;
+        }
+
+        self.pseudo_classes.iter().all(|pseudo| match pseudo {
+            PseudoClass::Link => is_link(element.name.as_str(), attribute("href")) && !cx.states.is(id, PseudoClass::Visited),
+            PseudoClass::Visited => is_link(element.name.as_str(), attribute("href")) && cx.states.is(id, PseudoClass::Visited),
+            pseudo => cx.states.is(id, *pseudo),
+        })
+    }
+}
+
+impl AttributeSelector {
+    fn matches(&self, value: Option<&str>) -> bool {
+        let Some(value) = value else {
+            return false;
+        };
+
+        let Some((operator, expected)) = &self.value else {
+            return true;
+        };
+
+        let (value, expected) = match self.case_sensitive {
+            true => (value.to_string(), expected.clone()),
+            false => (value.to_lowercase(), expected.to_lowercase()),
+        };
+        let (value, expected) = (value.as_str(), expected.as_str());
+
+        match operator {
+            AttributeOperator::Equals => value == expected,
+            AttributeOperator::Includes => value.split_whitespace().any(|v| v == expected),
+            AttributeOperator::DashMatch => value == expected || value.starts_with(&format!("{expected}-")),
+            AttributeOperator::Prefix => !expected.is_empty() && value.starts_with(expected),
+            AttributeOperator::Suffix => !expected.is_empty() && value.ends_with(expected),
+            AttributeOperator::Substring => !expected.is_empty() && value.contains(expected),
+        }
+    }
+}
+
+fn is_link(name: &str, href: Option<&str>) -> bool {
+    matches!(name, "a" | "area") && href.is_some()
+}
+
+/// Splits a selector into its compound selectors and combinators. Whitespace and combinators in
+/// attribute selectors and strings, like in `[title="a > b"]`, belong to the compound. The sibling
+/// combinators are returned as well, and make the selector invalid.
+fn selector_tokens(selector: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut start = 0;
+
+    for_each_top_level(selector, |i, c| {
+        if c.is_whitespace() || matches!(c, '>' | '+' | '~') {
+            if start < i {
+                result.push(&selector[start..i]);
+            }
+            if !c.is_whitespace() {
+                result.push(&selector[i..i + 1]);
+            }
+            start = i + c.len_utf8();
+        }
+    });
+
+    if start < selector.len() {
+        result.push(&selector[start..]);
+    }
+
+    result
+}
+
+/// Parses a compound selector like `a.nav:hover`, unsupported parts make the whole selector invalid
+fn parse_compound(token: &str) -> Option<Compound> {
+    let mut compound = Compound::default();
+    let mut rest = token;
+
+    let name_end = |s: &str| s.find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_')).unwrap_or(s.len());
+
+    if let Some(r) = rest.strip_prefix('*') {
+        rest = r;
+    } else {
+        let end = name_end(rest);
+        if end > 0 {
+            compound.tag = Some(rest[..end].to_ascii_lowercase());
+            rest = &rest[end..];
+        }
+    }
+
+    while let Some(c) = rest.chars().next() {
+        let after = &rest[1..];
+
+        match c {
+            '#' | '.' => {
+                let end = name_end(after);
+                if end == 0 {
+                    return None;
+                }
+
+                let name = after[..end].to_string();
+                if c == '#' {
+                    compound.ids.push(name);
+                } else {
+                    compound.classes.push(name);
+                }
+                rest = &after[end..];
+            }
+            '[' => {
+                let end = find_unquoted(after, ']')?;
+                compound.attributes.push(parse_attribute(&after[..end])?);
+                rest = &after[end + 1..];
+            }
+            ':' => {
+                // pseudo-elements can't be in a state
+                if after.starts_with(':') {
+                    return None;
+                }
+
+                let end = name_end(after);
+                compound.pseudo_classes.push(PseudoClass::parse(&after[..end])?);
+                rest = &after[end..];
+            }
+            _ => return None,
+        }
+    }
+
+    Some(compound)
+}
+
+fn parse_attribute(inner: &str) -> Option<AttributeSelector> {
+    let Some(eq) = inner.find('=') else {
+        return Some(AttributeSelector {
+            name: inner.trim().to_ascii_lowercase(),
+            value: None,
+            case_sensitive: true,
+        });
+    };
+
+    let (name, operator) = match inner[..eq].chars().last() {
+        Some('~') => (&inner[..eq - 1], AttributeOperator::Includes),
+        Some('|') => (&inner[..eq - 1], AttributeOperator::DashMatch),
+        Some('^') => (&inner[..eq - 1], AttributeOperator::Prefix),
+        Some('$') => (&inner[..eq - 1], AttributeOperator::Suffix),
+        Some('*') => (&inner[..eq - 1], AttributeOperator::Substring),
+        _ => (&inner[..eq], AttributeOperator::Equals),
+    };
+
+    // the value is an identifier or a string, optionally followed by the `i` or `s` flag
+    let rest = inner[eq + 1..].trim();
+    let (value, flag) = match rest.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let end = string_end(&rest[1..], quote)? + 1;
+            (&rest[1..end], rest[end + 1..].trim())
+        }
+        _ => rest
+            .split_once(char::is_whitespace)
+            .map_or((rest, ""), |(value, flag)| (value, flag.trim())),
+    };
+
+    let case_sensitive = match flag.to_ascii_lowercase().as_str() {
+        "" | "s" => true,
+        "i" => false,
+        _ => return None,
+    };
+
+    Some(AttributeSelector {
+        name: name.trim().to_ascii_lowercase(),
+        value: Some((operator, value.replace("\\", ""))),
+        case_sensitive,
+    })
+}
+
+/// The position of the quote that ends a string, skipping escaped quotes
+fn string_end(string: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+
+    for (i, c) in string.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == quote => return Some(i),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// A style rule of the document's style sheets, for one selector of its selector list
+#[derive(Debug, Clone)]
+pub struct StateRule {
+    pub selector: Selector,
+    pub declarations: Vec<Declaration>,
+    /// The media queries of the `@media` rules the rule is nested in, which all have to match
+    media: Vec<MediaQueryList>,
+    /// Position in the style sheets, later rules win over earlier ones with the same specificity
+    order: usize,
+}
+
+impl StateRule {
+    fn applies(&self, id: NodeId, cx: &MatchContext) -> bool {
+        self.media.iter().all(|media| media.matches(cx.viewport)) && self.selector.matches(id, cx)
+    }
+
+    fn cascade_key(&self, declaration: &Declaration) -> CascadeKey {
+        (declaration.important, false, self.selector.specificity(), self.order)
+    }
+}
+
+/// Where a declaration stands in the cascade of the author's style sheets, the larger key wins:
+/// importance first, then whether it is in a `style` attribute, then specificity and order
+type CascadeKey = (bool, bool, (u32, u32, u32), usize);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declaration {
+    pub property: String,
+    pub value: String,
+    pub important: bool,
+}
+
+/// The state dependent and `::selection` rules of the document's style sheets, cascaded on top of
+/// the styles the styling crate computed
+#[derive(Debug, Clone, Default)]
+pub struct StateRules {
+    rules: Vec<StateRule>,
+    /// The other rules, a state rule only overrides the styling crate's value of a property if it
+    /// wins the cascade against them
+    author: Vec<StateRule>,
+}
+
+impl StateRules {
+    /// Collects the rules with state pseudo-classes from the text of the given style sheets
+    pub fn parse<'a>(style_sheets: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut result = Self::default();
+        let mut order = 0;
+
+        for sheet in style_sheets {
+            for block in parse_rule_blocks(&strip_comments(sheet), &[]) {
+                let declarations = parse_declarations(&block.declarations);
+                if declarations.is_empty() {
+                    continue;
+                }
+
+                for selector in split_top_level(&block.selectors, ',') {
+                    let Some(selector) = Selector::parse(selector) else {
+                        continue;
+                    };
+
+                    let is_state_rule = selector.pseudo_element.is_some() || PseudoClass::ALL.iter().any(|p| selector.uses(*p));
+                    let rule = StateRule {
+                        selector,
+                        declarations: declarations.clone(),
+                        media: block.media.clone(),
+                        order,
+                    };
+                    order += 1;
+
+                    if is_state_rule {
+                        result.rules.push(rule);
+                    } else {
+                        result.author.push(rule);
+                    }
+                }
+            }
+        }
+
+        // without state rules the other rules are never looked at
+        if result.rules.is_empty() {
+            result.author.clear();
+        }
+
+        result
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Whether any rule depends on the pseudo-class, if not its changes don't need a restyle
+    pub fn uses(&self, pseudo: PseudoClass) -> bool {
+        self.rules.iter().any(|r| r.selector.uses(pseudo))
+    }
+
+    /// The declarations that apply to a node and win the cascade against the other author
+    /// declarations for their property, in cascade order so later ones win
+    pub fn declarations(&self, id: NodeId, cx: &MatchContext) -> Vec<&Declaration> {
+        let matched = self.matching(id, None, cx);
+        if matched.is_empty() {
+            return Vec::new();
+        }
+
+        let author = self.author_keys(id, cx);
+        matched
+            .into_iter()
+            .filter(|(key, declaration)| wins_cascade(declaration, *key, &author))
+            .map(|(_, declaration)| declaration)
+            .collect()
+    }
+
+    /// The declarations for the selection highlight of a node
+    pub fn selection_declarations(&self, id: NodeId, cx: &MatchContext) -> Vec<&Declaration> {
+        self.matching(id, Some(PseudoElement::Selection), cx)
+            .into_iter()
+            .map(|(_, d)| d)
+            .collect()
+    }
+
+    fn matching(&self, id: NodeId, pseudo_element: Option<PseudoElement>, cx: &MatchContext) -> Vec<(CascadeKey, &Declaration)> {
+        let mut matched = self
+            .rules
+            .iter()
+            .filter(|rule| rule.selector.pseudo_element == pseudo_element && rule.applies(id, cx))
+            .flat_map(|rule| rule.declarations.iter().map(move |d| (rule.cascade_key(d), d)))
+            .collect::<Vec<_>>();
+
+        matched.sort_by_key(|(key, _)| *key);
+        matched
+    }
+
+    /// The strongest cascade key of every property the other rules and the `style` attribute
+    /// declare for a node
+    fn author_keys(&self, id: NodeId, cx: &MatchContext) -> HashMap<String, CascadeKey> {
+        let mut keys: HashMap<String, CascadeKey> = HashMap::new();
+        let mut add = |property: &str, key: CascadeKey| {
+            let strongest = keys.entry(property.to_string()).or_insert(key);
+            *strongest = (*strongest).max(key);
+        };
+
+        for rule in self.author.iter().filter(|rule| rule.applies(id, cx)) {
+            for declaration in rule.declarations.iter() {
+                add(&declaration.property, rule.cascade_key(declaration));
+            }
+        }
+
+        if let Some(RenderNodeData::Element(element)) = cx.render_tree.get_node(id).map(|n| &n.data) {
+            for declaration in element.attributes.get("style").map(|s| parse_declarations(s)).unwrap_or_default() {
+                add(&declaration.property, (declaration.important, true, (0, 0, 0), 0));
+            }
+        }
+
+        keys
+    }
+}
+
+/// Whether a state declaration beats the author declarations of the same property, and of the
+/// shorthands that set it, like `margin` for `margin-top`
+fn wins_cascade(declaration: &Declaration, key: CascadeKey, author: &HashMap<String, CascadeKey>) -> bool {
+    let property = declaration.property.as_str();
+
+    author.iter().all(|(other, other_key)| {
+        let sets_property = property == other || property.strip_prefix(other.as_str()).is_some_and(|rest| rest.starts_with('-'));
+        !sets_property || key > *other_key
+    })
+}
+
+fn strip_comments(css: &str) -> String {
+    let mut result = String::with_capacity(css.len());
+    let mut rest = css;
+
+    while let Some(start) = rest.find("/*") {
+        result.push_str(&rest[..start]);
+        rest = match rest[start + 2..].find("*/") {
+            Some(end) => &rest[start + 2 + end + 2..],
+            None => "",
+        };
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// The selectors and declarations of a style rule, with the media queries it is nested in
+#[derive(Debug, Clone, PartialEq)]
+struct RuleBlock {
+    selectors: String,
+    declarations: String,
+    media: Vec<MediaQueryList>,
+}
+
+/// Splits a style sheet into its style rules. The rules in `@media` blocks keep their queries,
+/// other at-rules are skipped with everything nested in them.
+fn parse_rule_blocks(css: &str, media: &[MediaQueryList]) -> Vec<RuleBlock> {
+    let mut blocks = Vec::new();
+    let mut rest = css.trim_start();
+
+    while !rest.is_empty() {
+        let Some(open) = find_unquoted(rest, '{') else {
+            break;
+        };
+
+        if rest.starts_with('@') {
+            // statement at-rules like @import end at a semicolon, block at-rules at their block
+            if let Some(semi) = find_unquoted(rest, ';').filter(|semi| *semi < open) {
+                rest = rest[semi + 1..].trim_start();
+                continue;
+            }
+
+            let after = skip_block(&rest[open..]);
+            let block = rest[open + 1..rest.len() - after.len()].trim_end();
+            let inner = block.strip_suffix('}').unwrap_or(block);
+
+            let prelude = rest[..open].trim();
+            if let Some(query) = prelude.strip_prefix("@media") {
+                let mut nested = media.to_vec();
+                nested.push(MediaQueryList::parse(query));
+                blocks.extend(parse_rule_blocks(inner, &nested));
+            }
+
+            rest = after.trim_start();
+            continue;
+        }
+
+        let Some(close) = find_unquoted(&rest[open..], '}') else {
+            break;
+        };
+
+        blocks.push(RuleBlock {
+            selectors: rest[..open].trim().to_string(),
+            declarations: rest[open + 1..open + close].to_string(),
+            media: media.to_vec(),
+        });
+        rest = rest[open + close + 1..].trim_start();
+    }
+
+    blocks
+}
+
+/// Skips a `{ ... }` block including nested blocks and strings, returns what comes after it
+fn skip_block(css: &str) -> &str {
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (i, c) in css.char_indices() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '{') => depth += 1,
+            (None, '}') => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    return &css[i + 1..];
+                }
+            }
+            _ => {}
+        }
+    }
+
+    ""
+}
+
+fn parse_declarations(block: &str) -> Vec<Declaration> {
+    split_top_level(block, ';')
+        .into_iter()
+        .filter_map(|declaration| {
+            let (property, value) = declaration.split_once(':')?;
+            let property = property.trim().to_ascii_lowercase();
+            let mut value = value.trim();
+
+            let important = match value.to_ascii_lowercase().rfind("!important") {
+                Some(i) => {
+                    value = value[..i].trim();
+                    true
+                }
+                None => false,
+            };
+
+            if property.is_empty() || value.is_empty() {
+                return None;
+            }
+
+            // the styling crate expands `background` for the cascaded rules, here only a plain color
+            // is understood
+            let property = match property.as_str() {
+                "background" if parse_color(value, vello::peniko::Color::BLACK).is_some() => String::from("background-color"),
+                _ => property,
+            };
+
+            Some(Declaration {
+                property,
+                value: value.to_string(),
+                important,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenizes_selectors() {
+        assert_eq!(selector_tokens("nav > a:hover"), ["nav", ">", "a:hover"]);
+        assert_eq!(selector_tokens("ul>li  a"), ["ul", ">", "li", "a"]);
+        assert_eq!(selector_tokens(r#"a[title="a > b"] span"#), [r#"a[title="a > b"]"#, "span"]);
+        assert_eq!(selector_tokens("a[title='x]'] + b"), ["a[title='x]']", "+", "b"]);
+    }
+
+    #[test]
+    fn parses_attribute_selectors() {
+        let selector = Selector::parse(r#"a[title="a b"]:hover"#).unwrap();
+        let attribute = &selector.compounds[0].0.attributes[0];
+        assert_eq!(attribute.name, "title");
+        assert_eq!(attribute.value, Some((AttributeOperator::Equals, String::from("a b"))));
+
+        let selector = Selector::parse(r#"[data-x="\"]"]:focus"#).unwrap();
+        assert_eq!(
+            selector.compounds[0].0.attributes[0].value,
+            Some((AttributeOperator::Equals, String::from("\"]")))
+        );
+
+        let selector = Selector::parse("[lang|=EN i]:hover").unwrap();
+        assert!(!selector.compounds[0].0.attributes[0].case_sensitive);
+        assert!(selector.compounds[0].0.attributes[0].matches(Some("en-US")));
+
+        assert!(Selector::parse("[lang=en x]:hover").is_none());
+        assert!(Selector::parse("a + b:hover").is_none());
+        assert!(Selector::parse("a >").is_none());
+    }
+
+    #[test]
+    fn splits_rule_blocks() {
+        let blocks = parse_rule_blocks(r#"a[title="{"] { content: "}"; color: red } b { color: blue }"#, &[]);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].selectors, r#"a[title="{"]"#);
+        assert_eq!(parse_declarations(&blocks[0].declarations).len(), 2);
+        assert_eq!(blocks[1].selectors, "b");
+
+        let blocks = parse_rule_blocks(
+            "@import 'x.css'; @font-face { src: url(x) } @media print { a { b: c } } i { d: e }",
+            &[],
+        );
+        assert_eq!(blocks.len(), 2);
+        assert!(!blocks[0].media[0].matches((800.0, 600.0)));
+        assert_eq!(blocks[1].selectors, "i");
+        assert!(blocks[1].media.is_empty());
+
+        let blocks = parse_rule_blocks("@media screen { @media (min-width: 900px) { a { b: c } } }", &[]);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].media.len(), 2);
+        assert!(!blocks[0].media.iter().all(|m| m.matches((800.0, 600.0))));
+    }
+
+    #[test]
+    fn collects_state_and_author_rules() {
+        let rules = StateRules::parse(["a { color: red } a:hover, b { color: blue } @media print { i:focus { color: green } }"]);
+        assert_eq!(rules.rules.len(), 2);
+        assert_eq!(rules.author.len(), 2);
+        assert!(rules.uses(PseudoClass::Hover));
+
+        let rules = StateRules::parse(["a { color: red }"]);
+        assert!(rules.is_empty());
+        assert!(rules.author.is_empty());
+    }
+
+    #[test]
+    fn cascades_against_author_rules() {
+        let declaration = |property: &str, important| Declaration {
+            property: property.to_string(),
+            value: String::from("red"),
+            important,
+        };
+        let author = HashMap::from([
+            (String::from("color"), (false, false, (1, 0, 0), 0)),
+            (String::from("margin"), (false, false, (0, 0, 1), 0)),
+        ]);
+
+        // `a:hover` loses against `#id`, even when it comes later
+        assert!(!wins_cascade(&declaration("color", false), (false, false, (0, 1, 1), 5), &author));
+        assert!(wins_cascade(&declaration("color", false), (false, false, (1, 1, 0), 5), &author));
+        assert!(wins_cascade(&declaration("color", true), (true, false, (0, 1, 0), 5), &author));
+
+        // longhands compete with the shorthands that set them
+        assert!(!wins_cascade(
+            &declaration("margin-top", false),
+            (false, false, (0, 0, 1), 0),
+            &HashMap::from([(String::from("margin"), (false, false, (0, 0, 1), 1))])
+        ));
+        assert!(wins_cascade(
+            &declaration("margin-top", false),
+            (false, false, (0, 1, 1), 2),
+            &author
+        ));
+
+        // the `style` attribute beats every selector
+        let inline = HashMap::from([(String::from("color"), (false, true, (0, 0, 0), 0))]);
+        assert!(!wins_cascade(&declaration("color", false), (false, false, (1, 1, 1), 9), &inline));
+        assert!(wins_cascade(&declaration("color", true), (true, false, (0, 1, 0), 1), &inline));
+    }
+}
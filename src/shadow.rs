@@ -0,0 +1,270 @@
+use vello::kurbo::{Affine, Rect, RoundedRect, Vec2};
+use vello::peniko::{BlendMode, Color, Compose, Fill, Mix};
+use vello::Scene;
+
+use crate::color::parse_color;
+use crate::css::{split_top_level, tokens};
+use crate::units::LengthContext;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoxShadow {
+    pub offset_x: f64,
+    pub offset_y: f64,
+    pub blur: f64,
+    pub spread: f64,
+    pub color: Color,
+    pub inset: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextShadow {
+    pub offset_x: f64,
+    pub offset_y: f64,
+    pub blur: f64,
+    pub color: Color,
+}
+
+/// Parses a `box-shadow` value. A shadow without a color uses `currentColor`.
+pub fn parse_box_shadows(value: &str, ctx: &LengthContext, current_color: Color) -> Vec<BoxShadow> {
+    if value.trim() == "none" {
+        return Vec::new();
+    }
+
+    split_top_level(value, ',')
+        .into_iter()
+        .filter_map(|shadow| {
+            let mut lengths = Vec::with_capacity(4);
+            let mut color = None;
+            let mut inset = false;
+
+            for token in tokens(shadow) {
+                if token == "inset" {
+                    inset = true;
+                } else if let Some(len) = ctx.resolve(token, 0.0) {
+                    lengths.push(len);
+                } else {
+                    color = Some(parse_color(token, current_color)?);
+                }
+            }
+
+            if lengths.len() < 2 || lengths.len() > 4 {
+                return None;
+            }
+
+            Some(BoxShadow {
+                offset_x: lengths[0],
+                offset_y: lengths[1],
+                blur: lengths.get(2).copied().unwrap_or(0.0).max(0.0),
+                spread: lengths.get(3).copied().unwrap_or(0.0),
+                color: color.unwrap_or(current_color),
+                inset,
+            })
+        })
+        .collect()
+}
+
+/// Parses a `text-shadow` value, which is a `box-shadow` without spread and inset
+pub fn parse_text_shadows(value: &str, ctx: &LengthContext, current_color: Color) -> Vec<TextShadow> {
+    if value.trim() == "none" {
+        return Vec::new();
+    }
+
+    split_top_level(value, ',')
+        .into_iter()
+        .filter_map(|shadow| {
+            let mut lengths = Vec::with_capacity(3);
+            let mut color = None;
+
+            for token in tokens(shadow) {
+                if let Some(len) = ctx.resolve(token, 0.0) {
+                    lengths.push(len);
+                } else {
+                    color = Some(parse_color(token, current_color)?);
+                }
+            }
+
+            if lengths.len() < 2 || lengths.len() > 3 {
+                return None;
+            }
+
+            Some(TextShadow {
+                offset_x: lengths[0],
+                offset_y: lengths[1],
+                blur: lengths.get(2).copied().unwrap_or(0.0).max(0.0),
+                color: color.unwrap_or(current_color),
+            })
+        })
+        .collect()
+}
+
+/// The blur radius in css is twice the standard deviation of the gaussian
+fn std_dev(blur: f64) -> f64 {
+    blur / 2.0
+}
+
+/// Draws the outset shadows of a box. The shadows are painted in reverse order, since the first
+/// shadow in the list is the topmost one, and the box itself is cut out of them, so they also stay
+/// invisible behind a transparent background.
+pub fn draw_box_shadows(
+    scene: &mut Scene,
+    transform: Affine,
+    rect: Rect,
+    radius: f64,
+    shadows: &[BoxShadow],
+) {
+    for shadow in shadows.iter().rev().filter(|s| !s.inset) {
+        let color = shadow.color;
+        if color.a == 0 {
+            continue;
+        }
+
+        let shadow_rect = rect
+            .inflate(shadow.spread, shadow.spread)
+            + Vec2::new(shadow.offset_x, shadow.offset_y);
+
+        if shadow_rect.width() <= 0.0 || shadow_rect.height() <= 0.0 {
+            continue;
+        }
+
+        let shadow_radius = spread_radius(radius, shadow.spread);
+
+        // the blur reaches 3 standard deviations, everything beyond that is invisible anyway
+        let extent = std_dev(shadow.blur) * 3.0;
+        let bounds = shadow_rect.inflate(extent, extent).union(rect);
+
+        scene.push_layer(Mix::Normal, 1.0, transform, &bounds);
+
+        if shadow.blur > 0.0 {
+            scene.draw_blurred_rounded_rect(
+                transform,
+                shadow_rect,
+                color,
+                shadow_radius,
+                std_dev(shadow.blur),
+            );
+        } else {
+            let shape = RoundedRect::from_rect(shadow_rect, shadow_radius);
+            scene.fill(Fill::NonZero, transform, color, None, &shape);
+        }
+
+        let border_box = RoundedRect::from_rect(rect, radius);
+        scene.push_layer(
+            BlendMode::new(Mix::Normal, Compose::DestOut),
+            1.0,
+            transform,
+            &border_box,
+        );
+        scene.fill(Fill::NonZero, transform, Color::BLACK, None, &border_box);
+        scene.pop_layer();
+
+        scene.pop_layer();
+    }
+}
+
+/// Draws the inset shadows of a box, these are painted above the background and below the content,
+/// clipped to the padding box.
+pub fn draw_inset_shadows(
+    scene: &mut Scene,
+    transform: Affine,
+    padding_box: Rect,
+    radius: f64,
+    shadows: &[BoxShadow],
+) {
+    let clip = RoundedRect::from_rect(padding_box, radius);
+
+    for shadow in shadows.iter().rev().filter(|s| s.inset) {
+        let color = shadow.color;
+        if color.a == 0 {
+            continue;
+        }
+
+        scene.push_layer(Mix::Normal, 1.0, transform, &clip);
+        scene.fill(Fill::NonZero, transform, color, None, &padding_box);
+
+        // The hole is the padding box shrunk by the spread, everything outside of it is shadow
+        let hole = padding_box
+            .inflate(-shadow.spread, -shadow.spread)
+            + Vec2::new(shadow.offset_x, shadow.offset_y);
+
+        if hole.width() > 0.0 && hole.height() > 0.0 {
+            let hole_radius = spread_radius(radius, -shadow.spread);
+
+            scene.push_layer(
+                BlendMode::new(Mix::Normal, Compose::DestOut),
+                1.0,
+                transform,
+                &padding_box,
+            );
+
+            if shadow.blur > 0.0 {
+                scene.draw_blurred_rounded_rect(
+                    transform,
+                    hole,
+                    Color::BLACK,
+                    hole_radius,
+                    std_dev(shadow.blur),
+                );
+            } else {
+                let shape = RoundedRect::from_rect(hole, hole_radius);
+                scene.fill(Fill::NonZero, transform, Color::BLACK, None, &shape);
+            }
+
+            scene.pop_layer();
+        }
+
+        scene.pop_layer();
+    }
+}
+
+/// Radius of a shadow shape after applying the spread distance, see
+/// https://drafts.csswg.org/css-backgrounds/#shadow-shape
+fn spread_radius(radius: f64, spread: f64) -> f64 {
+    if radius <= 0.0 {
+        return 0.0;
+    }
+
+    if spread >= 0.0 {
+        let ratio = radius / spread.max(f64::EPSILON);
+        let factor = if ratio < 1.0 {
+            1.0 + (ratio - 1.0).powi(3)
+        } else {
+            1.0
+        };
+
+        radius + spread * factor
+    } else {
+        (radius + spread).max(0.0)
+    }
+}
+
+/// Vello has no generic blur filter yet, so blurred text is approximated by drawing the glyphs
+/// several times, offset along a gaussian kernel. Returns offsets together with the alpha weight
+/// of each sample, the weights sum to one so the samples have to be added up, not painted over
+/// each other. Every sample takes a layer to add it up, so there are at most 5×5 of them.
+pub fn blur_kernel(blur: f64) -> Vec<((f64, f64), f32)> {
+    if blur <= 0.0 {
+        return vec![((0.0, 0.0), 1.0)];
+    }
+
+    let sigma = std_dev(blur);
+    let steps = (blur.ceil() as i32).clamp(1, 2);
+    let step = blur / steps as f64;
+
+    let mut samples = Vec::new();
+    let mut total = 0.0;
+
+    for y in -steps..=steps {
+        for x in -steps..=steps {
+            let dx = x as f64 * step;
+            let dy = y as f64 * step;
+            let weight = (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp();
+            total += weight;
+            samples.push(((dx, dy), weight));
+        }
+    }
+
+    samples
+        .into_iter()
+        .map(|(offset, weight)| (offset, (weight / total) as f32))
+        .collect()
+}
@@ -0,0 +1,292 @@
+use std::rc::Rc;
+
+use gosub_html5::node::NodeId;
+use gosub_styling::render_tree::{RenderNodeData, RenderTree};
+use taffy::{NodeId as TaffyID, PrintTree, TaffyTree, TraversePartialTree};
+use vello::kurbo::{Affine, Rect, RoundedRect, Vec2};
+
+use crate::overflow::ScrollOffsets;
+use crate::style::{ComputedStyles, Display, Float};
+
+/// A clip region inherited from an ancestor with `overflow` other than `visible`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clip {
+    pub shape: RoundedRect,
+    /// Transform of the clipping element, the shape is in its untransformed coordinates
+    pub transform: Affine,
+}
+
+/// A node in paint order, together with everything inherited from its ancestors
+#[derive(Debug, Clone)]
+pub struct PaintNode {
+    pub id: TaffyID,
+    pub gosub_id: NodeId,
+    /// Position of the parent's border box, already adjusted for scroll offsets
+    pub origin: (f64, f64),
+    /// Clips of all ancestors up to the root, outermost first
+    pub clips: Rc<[Clip]>,
+    /// The transforms of all stacking contexts up to and including this node, composed into one.
+    /// Positions of the node are in the coordinate space before this transform is applied.
+    pub transform: Affine,
+}
+
+impl PaintNode {
+    /// Position of the node's own border box
+    pub fn position(&self, layout: &TaffyTree<NodeId>) -> (f64, f64) {
+        let location = layout.get_final_layout(self.id).location;
+        (
+            self.origin.0 + location.x as f64,
+            self.origin.1 + location.y as f64,
+        )
+    }
+
+    pub fn border_box(&self, layout: &TaffyTree<NodeId>) -> Rect {
+        let (x, y) = self.position(layout);
+        let size = layout.get_final_layout(self.id).size;
+        Rect::new(x, y, x + size.width as f64, y + size.height as f64)
+    }
+
+    pub fn padding_box(&self, layout: &TaffyTree<NodeId>) -> Rect {
+        let border = layout.get_final_layout(self.id).border;
+        let border_box = self.border_box(layout);
+
+        Rect::new(
+            border_box.x0 + border.left as f64,
+            border_box.y0 + border.top as f64,
+            border_box.x1 - border.right as f64,
+            border_box.y1 - border.bottom as f64,
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum PaintItem {
+    /// Paints a single node: the background of an element, or the glyphs of a text node
+    Node(PaintNode),
+    /// Paints the scrollbars of a scroll container above its content, with its current scroll offset
+    Scrollbars(PaintNode, Vec2),
+    /// Paints a stacking context, or an element painted as if it were one
+    Context(StackingContext),
+}
+
+impl PaintItem {
+    pub fn z_index(&self) -> i32 {
+        match self {
+            PaintItem::Context(context) => context.z_index,
+            _ => 0,
+        }
+    }
+}
+
+/// Layers 3 to 5 of the painting order, the in-flow content of a stacking context
+#[derive(Debug, Clone, Default)]
+pub struct Flow {
+    pub blocks: Vec<PaintItem>,
+    pub floats: Vec<PaintItem>,
+    pub inlines: Vec<PaintItem>,
+}
+
+/// Layers 2, 6 and 7 of the painting order, these only exist for real stacking contexts, since
+/// positioned elements with `z-index: auto` and floats hoist them into their parent context.
+#[derive(Debug, Clone, Default)]
+pub struct Layered {
+    pub negative: Vec<PaintItem>,
+    pub positioned: Vec<PaintItem>,
+    pub positive: Vec<PaintItem>,
+}
+
+/// A stacking context as described in https://www.w3.org/TR/CSS2/zindex.html
+#[derive(Debug, Clone)]
+pub struct StackingContext {
+    pub root: PaintNode,
+    pub z_index: i32,
+    /// Whether the root actually established a stacking context, elements painted like a
+    /// stacking context (positioned with `z-index: auto`, floats, inline-blocks) don't
+    pub atomic: bool,
+    pub flow: Flow,
+    pub layered: Layered,
+}
+
+impl StackingContext {
+    /// Builds the stacking context tree for the whole document. `transform` maps css pixels to the
+    /// surface, e.g. the scale factor of the window.
+    pub fn build(
+        layout: &TaffyTree<NodeId>,
+        render_tree: &RenderTree,
+        styles: &ComputedStyles,
+        root: TaffyID,
+        scroll: &ScrollOffsets,
+        transform: Affine,
+    ) -> Option<Self> {
+        let gosub_id = *layout.get_node_context(root)?;
+
+        let builder = Builder {
+            layout,
+            render_tree,
+            styles,
+            scroll,
+        };
+
+        let root = PaintNode {
+            id: root,
+            gosub_id,
+            origin: (0.0, 0.0),
+            clips: Rc::from([]),
+            transform,
+        };
+
+        Some(builder.context(root, 0))
+    }
+
+    /// All items of this context in painting order, the root itself is painted first and is not
+    /// part of the iterator. Nested contexts are returned as a single item.
+    pub fn items(&self) -> impl DoubleEndedIterator<Item = &PaintItem> {
+        self.layered
+            .negative
+            .iter()
+            .chain(&self.flow.blocks)
+            .chain(&self.flow.floats)
+            .chain(&self.flow.inlines)
+            .chain(&self.layered.positioned)
+            .chain(&self.layered.positive)
+    }
+}
+
+struct Builder<'a> {
+    layout: &'a TaffyTree<NodeId>,
+    render_tree: &'a RenderTree,
+    styles: &'a ComputedStyles,
+    scroll: &'a ScrollOffsets,
+}
+
+impl Builder<'_> {
+    fn context(&self, root: PaintNode, z_index: i32) -> StackingContext {
+        let mut flow = Flow::default();
+        let mut layered = Layered::default();
+
+        self.collect_children(&root, &mut flow, &mut layered);
+
+        // stable sorts, contexts with the same z-index stay in tree order
+        layered.negative.sort_by_key(PaintItem::z_index);
+        layered.positive.sort_by_key(PaintItem::z_index);
+
+        StackingContext {
+            root,
+            z_index,
+            atomic: true,
+            flow,
+            layered,
+        }
+    }
+
+    /// An element that is painted as if it created a stacking context, but its positioned
+    /// descendants and real stacking contexts belong to the parent context
+    fn pseudo_context(&self, root: PaintNode, layered: &mut Layered) -> StackingContext {
+        let mut flow = Flow::default();
+
+        self.collect_children(&root, &mut flow, layered);
+
+        StackingContext {
+            root,
+            z_index: 0,
+            atomic: false,
+            flow,
+            layered: Layered::default(),
+        }
+    }
+
+    fn collect_children(&self, parent: &PaintNode, flow: &mut Flow, layered: &mut Layered) {
+        let parent_style = self.styles.get(parent.gosub_id);
+        let overflow = parent_style.map(|s| s.overflow).unwrap_or_default();
+
+        let mut origin = parent.position(self.layout);
+        let mut clips = parent.clips.clone();
+        let mut scroll_offset = Vec2::ZERO;
+
+        if overflow.clips() {
+            let border = self.layout.get_final_layout(parent.id).border;
+            let border_box = parent.border_box(self.layout);
+            let padding_box = parent.padding_box(self.layout);
+
+            let radius = parent_style.map_or(0.0, |s| s.border_radius(border_box.width()));
+            let radius = (radius - border.left.max(border.top) as f64).max(0.0);
+
+            let mut chain = clips.to_vec();
+            chain.push(Clip {
+                shape: overflow.clip_shape(padding_box, radius),
+                transform: parent.transform,
+            });
+            clips = Rc::from(chain);
+
+            if overflow.is_scroll_container() {
+                scroll_offset = self.scroll.get(parent.gosub_id);
+                origin.0 -= scroll_offset.x;
+                origin.1 -= scroll_offset.y;
+            }
+        }
+
+        let in_flex_or_grid = parent_style.is_some_and(|s| s.display.is_flex_or_grid());
+
+        for child in self.layout.child_ids(parent.id) {
+            let Some(gosub_id) = self.layout.get_node_context(child).copied() else {
+                continue;
+            };
+
+            let mut node = PaintNode {
+                id: child,
+                gosub_id,
+                origin,
+                clips: clips.clone(),
+                transform: parent.transform,
+            };
+
+            let Some(render_node) = self.render_tree.get_node(gosub_id) else {
+                continue;
+            };
+
+            if matches!(render_node.data, RenderNodeData::Text(_)) {
+                flow.inlines.push(PaintItem::Node(node));
+                continue;
+            }
+
+            let Some(style) = self.styles.get(gosub_id) else {
+                continue;
+            };
+
+            let inline = style.display.is_inline_level();
+
+            if let Some(z_index) = style.stacking_z_index(in_flex_or_grid) {
+                if let Some(transform) = style.transform_matrix(node.border_box(self.layout)) {
+                    node.transform = parent.transform * transform;
+                }
+
+                let context = PaintItem::Context(self.context(node, z_index));
+                match z_index {
+                    z if z < 0 => layered.negative.push(context),
+                    0 => layered.positioned.push(context),
+                    _ => layered.positive.push(context),
+                }
+            } else if style.position.is_positioned() {
+                let context = self.pseudo_context(node, layered);
+                layered.positioned.push(PaintItem::Context(context));
+            } else if style.float != Float::None {
+                let context = self.pseudo_context(node, layered);
+                flow.floats.push(PaintItem::Context(context));
+            } else if style.display.is_atomic_inline() || (in_flex_or_grid && inline) {
+                // inline-blocks and flex or grid items are painted atomically with the inline content
+                let context = self.pseudo_context(node, layered);
+                flow.inlines.push(PaintItem::Context(context));
+            } else if style.display == Display::Inline {
+                flow.inlines.push(PaintItem::Node(node.clone()));
+                self.collect_children(&node, flow, layered);
+            } else {
+                flow.blocks.push(PaintItem::Node(node.clone()));
+                self.collect_children(&node, flow, layered);
+            }
+        }
+
+        if overflow.is_scroll_container() {
+            flow.inlines.push(PaintItem::Scrollbars(parent.clone(), scroll_offset));
+        }
+    }
+}
@@ -0,0 +1,1146 @@
+use std::collections::HashMap;
+
+use gosub_html5::node::NodeId;
+use gosub_styling::render_tree::{RenderNodeData, RenderTree};
+use vello::kurbo::{Affine, Rect};
+use vello::peniko::Color;
+
+use crate::compositing::Compositing;
+use crate::cursor::CursorStyle;
+use crate::color::{parse_color, to_css_string};
+use crate::css::{split_top_level, tokens, value_to_string};
+use crate::overflow::OverflowStyle;
+use crate::selector::{MatchContext, StateCascade};
+use crate::shadow::{parse_box_shadows, parse_text_shadows, BoxShadow, TextShadow};
+use crate::text::resolve_font_families;
+use crate::transform::{parse_transform, parse_transform_origin};
+use crate::units::{box_shorthand, LayoutLength, LengthContext, DEFAULT_FONT_SIZE};
+
+/// Elements that are inline-level when no `display` is set on them
+const INLINE_ELEMENTS: &[&str] = &[
+    "a", "abbr", "b", "bdi", "bdo", "br", "cite", "code", "data", "dfn", "em", "i", "img", "input",
+    "kbd", "label", "mark", "q", "s", "samp", "select", "small", "span", "strong", "sub", "sup",
+    "textarea", "time", "u", "var", "wbr",
+];
+
+/// Properties that are inherited by default, see https://www.w3.org/TR/CSS22/propidx.html and the
+/// property index of the newer css specs
+pub const INHERITED_PROPERTIES: &[&str] = &[
+    "border-collapse",
+    "border-spacing",
+    "caption-side",
+    "color",
+    "color-scheme",
+    "cursor",
+    "direction",
+    "empty-cells",
+    "font",
+    "font-family",
+    "font-feature-settings",
+    "font-kerning",
+    "font-size",
+    "font-size-adjust",
+    "font-stretch",
+    "font-style",
+    "font-variant",
+    "font-variation-settings",
+    "font-weight",
+    "hyphens",
+    "letter-spacing",
+    "line-break",
+    "line-height",
+    "list-style",
+    "list-style-image",
+    "list-style-position",
+    "list-style-type",
+    "orphans",
+    "overflow-wrap",
+    "pointer-events",
+    "quotes",
+    "tab-size",
+    "text-align",
+    "text-align-last",
+    "text-indent",
+    "text-justify",
+    "text-rendering",
+    "text-shadow",
+    "text-transform",
+    "text-underline-position",
+    "visibility",
+    "white-space",
+    "widows",
+    "word-break",
+    "word-spacing",
+    "word-wrap",
+    "writing-mode",
+];
+
+/// Initial values of the properties the renderer cares about, everything else has no initial
+/// value here and is treated as not set
+pub fn initial_value(property: &str) -> Option<&'static str> {
+    Some(match property {
+        "color" => "black",
+        "background-color" => "transparent",
+        "font-family" => "sans-serif",
+        "font-size" => "medium",
+        "font-style" => "normal",
+        "font-weight" => "normal",
+        "line-height" => "normal",
+        "letter-spacing" => "normal",
+        "word-spacing" => "normal",
+        "text-align" => "start",
+        "text-shadow" => "none",
+        "text-transform" => "none",
+        "white-space" => "normal",
+        "visibility" => "visible",
+        "cursor" => "auto",
+        "pointer-events" => "auto",
+        "display" => "inline",
+        "position" => "static",
+        "float" => "none",
+        "clear" => "none",
+        "z-index" => "auto",
+        "opacity" => "1",
+        "mix-blend-mode" => "normal",
+        "isolation" => "auto",
+        "overflow" => "visible",
+        "overflow-x" => "visible",
+        "overflow-y" => "visible",
+        "transform" => "none",
+        "transform-origin" => "50% 50%",
+        "box-shadow" => "none",
+        "border-radius" => "0",
+        "scroll-behavior" => "auto",
+        _ => return None,
+    })
+}
+
+pub fn is_inherited(property: &str) -> bool {
+    INHERITED_PROPERTIES.contains(&property)
+}
+
+/// The computed values of a single node after cascading and inheritance, as css strings
+#[derive(Debug, Clone, Default)]
+pub struct ComputedValues {
+    values: HashMap<String, String>,
+}
+
+impl ComputedValues {
+    /// The values a child starts with, the inherited properties of its parent
+    fn inherited(parent: &ComputedValues) -> Self {
+        let values = parent
+            .iter()
+            .filter(|(property, _)| is_inherited(property))
+            .map(|(property, value)| (property.to_string(), value.to_string()))
+            .collect();
+
+        Self { values }
+    }
+
+    pub fn get(&self, property: &str) -> Option<&str> {
+        self.values.get(property).map(String::as_str)
+    }
+
+    /// The computed value, or the initial value when the property is not set at all
+    pub fn get_or_initial(&self, property: &str) -> Option<&str> {
+        self.get(property).or_else(|| initial_value(property))
+    }
+
+    /// The computed font size in css pixels, this is always absolute after the style pass
+    pub fn font_size(&self) -> f64 {
+        self.get("font-size")
+            .and_then(|fs| fs.strip_suffix("px"))
+            .and_then(|fs| fs.parse().ok())
+            .unwrap_or(DEFAULT_FONT_SIZE)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Display {
+    None,
+    Contents,
+    #[default]
+    Inline,
+    Block,
+    InlineBlock,
+    FlowRoot,
+    ListItem,
+    Flex,
+    InlineFlex,
+    Grid,
+    InlineGrid,
+    Table,
+    InlineTable,
+}
+
+impl Display {
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value.trim() {
+            "none" => Display::None,
+            "contents" => Display::Contents,
+            "inline" | "inline flow" => Display::Inline,
+            "block" | "block flow" => Display::Block,
+            "inline-block" | "inline flow-root" => Display::InlineBlock,
+            "flow-root" | "block flow-root" => Display::FlowRoot,
+            "list-item" => Display::ListItem,
+            "flex" | "block flex" => Display::Flex,
+            "inline-flex" | "inline flex" => Display::InlineFlex,
+            "grid" | "block grid" => Display::Grid,
+            "inline-grid" | "inline grid" => Display::InlineGrid,
+            "table" => Display::Table,
+            "inline-table" => Display::InlineTable,
+            _ => return None,
+        })
+    }
+
+    pub fn is_inline_level(self) -> bool {
+        matches!(
+            self,
+            Display::Inline | Display::InlineBlock | Display::InlineFlex | Display::InlineGrid | Display::InlineTable
+        )
+    }
+
+    /// Inline-level boxes that are laid out as a single unit inside a line
+    pub fn is_atomic_inline(self) -> bool {
+        self.is_inline_level() && self != Display::Inline
+    }
+
+    pub fn is_flex_or_grid(self) -> bool {
+        matches!(self, Display::Flex | Display::InlineFlex | Display::Grid | Display::InlineGrid)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Position {
+    #[default]
+    Static,
+    Relative,
+    Absolute,
+    Fixed,
+    Sticky,
+}
+
+impl Position {
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value.trim() {
+            "static" => Position::Static,
+            "relative" => Position::Relative,
+            "absolute" => Position::Absolute,
+            "fixed" => Position::Fixed,
+            "sticky" => Position::Sticky,
+            _ => return None,
+        })
+    }
+
+    pub fn is_positioned(self) -> bool {
+        self != Position::Static
+    }
+
+    /// Absolutely positioned boxes are taken out of flow
+    pub fn is_out_of_flow(self) -> bool {
+        matches!(self, Position::Absolute | Position::Fixed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Float {
+    #[default]
+    None,
+    Left,
+    Right,
+}
+
+impl Float {
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value.trim() {
+            "none" => Float::None,
+            "left" | "inline-start" => Float::Left,
+            "right" | "inline-end" => Float::Right,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Clear {
+    #[default]
+    None,
+    Left,
+    Right,
+    Both,
+}
+
+impl Clear {
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value.trim() {
+            "none" => Clear::None,
+            "left" | "inline-start" => Clear::Left,
+            "right" | "inline-end" => Clear::Right,
+            "both" => Clear::Both,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Visibility {
+    #[default]
+    Visible,
+    Hidden,
+    Collapse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PointerEvents {
+    #[default]
+    Auto,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScrollBehavior {
+    #[default]
+    Auto,
+    Smooth,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FontStyle {
+    #[default]
+    Normal,
+    Italic,
+    Oblique,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WhiteSpace {
+    #[default]
+    Normal,
+    Pre,
+    Nowrap,
+    PreWrap,
+    PreLine,
+    BreakSpaces,
+}
+
+impl WhiteSpace {
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value.trim() {
+            "normal" => WhiteSpace::Normal,
+            "pre" => WhiteSpace::Pre,
+            "nowrap" => WhiteSpace::Nowrap,
+            "pre-wrap" => WhiteSpace::PreWrap,
+            "pre-line" => WhiteSpace::PreLine,
+            "break-spaces" => WhiteSpace::BreakSpaces,
+            _ => return None,
+        })
+    }
+
+    pub fn collapses_spaces(self) -> bool {
+        matches!(self, WhiteSpace::Normal | WhiteSpace::Nowrap | WhiteSpace::PreLine)
+    }
+
+    pub fn preserves_newlines(self) -> bool {
+        !matches!(self, WhiteSpace::Normal | WhiteSpace::Nowrap)
+    }
+
+    pub fn wraps(self) -> bool {
+        !matches!(self, WhiteSpace::Pre | WhiteSpace::Nowrap)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextAlign {
+    #[default]
+    Start,
+    End,
+    Left,
+    Right,
+    Center,
+    Justify,
+}
+
+impl TextAlign {
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value.trim() {
+            "start" => TextAlign::Start,
+            "end" => TextAlign::End,
+            "left" => TextAlign::Left,
+            "right" => TextAlign::Right,
+            "center" => TextAlign::Center,
+            "justify" => TextAlign::Justify,
+            _ => return None,
+        })
+    }
+}
+
+/// Everything needed to select and size a font
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontDescriptor {
+    /// Family names in order of preference, with generic families expanded
+    pub families: Vec<String>,
+    /// Font size in css pixels
+    pub size: f64,
+    pub weight: u16,
+    pub style: FontStyle,
+    /// Computed line height in css pixels, `normal` is 1.2 times the font size
+    pub line_height: f64,
+}
+
+impl Default for FontDescriptor {
+    fn default() -> Self {
+        Self {
+            families: resolve_font_families("sans-serif"),
+            size: DEFAULT_FONT_SIZE,
+            weight: 400,
+            style: FontStyle::Normal,
+            line_height: DEFAULT_FONT_SIZE * 1.2,
+        }
+    }
+}
+
+/// The colors selected text is painted with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SelectionColors {
+    pub color: Color,
+    pub background: Color,
+}
+
+impl Default for SelectionColors {
+    /// The `Highlight` and `HighlightText` system colors
+    fn default() -> Self {
+        Self {
+            color: Color::WHITE,
+            background: Color::rgb8(0x33, 0x90, 0xff),
+        }
+    }
+}
+
+impl SelectionColors {
+    fn with_declarations(self, declarations: &[(String, String)], current_color: Color) -> Self {
+        let mut colors = self;
+
+        for (property, value) in declarations {
+            match property.as_str() {
+                "color" => colors.color = parse_color(value, current_color).unwrap_or(colors.color),
+                "background-color" => colors.background = parse_color(value, current_color).unwrap_or(colors.background),
+                _ => {}
+            }
+        }
+
+        colors
+    }
+}
+
+/// The typed computed style of a node, produced once per node after the cascade
+#[derive(Debug, Clone, Default)]
+pub struct ComputedStyle {
+    pub display: Display,
+    pub position: Position,
+    pub float: Float,
+    pub clear: Clear,
+    pub z_index: Option<i32>,
+    pub visibility: Visibility,
+    pub pointer_events: PointerEvents,
+    pub cursor: CursorStyle,
+
+    pub color: Color,
+    pub background_color: Color,
+
+    /// Elliptical and per corner radii are not supported yet, the first radius is used for all corners
+    pub border_radius: Option<LayoutLength>,
+    pub box_shadows: Vec<BoxShadow>,
+    pub text_shadows: Vec<TextShadow>,
+    /// The colors of selected text, from the `::selection` rules
+    pub selection: SelectionColors,
+
+    pub compositing: Compositing,
+    pub overflow: OverflowStyle,
+    pub scroll_behavior: ScrollBehavior,
+    /// The raw transform list, it can only be turned into a matrix once the size of the reference
+    /// box is known, see [`ComputedStyle::transform_matrix`]
+    pub transform: Option<String>,
+    pub transform_origin: Option<String>,
+
+    pub font: FontDescriptor,
+    pub white_space: WhiteSpace,
+    pub text_align: TextAlign,
+    pub letter_spacing: f64,
+    pub word_spacing: f64,
+
+    /// Lengths in this style were resolved with this context
+    pub lengths: LengthContext,
+}
+
+impl ComputedStyle {
+    fn from_values(values: &ComputedValues, lengths: LengthContext, default_display: Display, parent: Option<&ComputedStyle>) -> Self {
+        let get = |property: &str| values.get_or_initial(property);
+
+        let parent_color = parent.map(|p| p.color).unwrap_or(Color::BLACK);
+        let color = get("color").and_then(|c| parse_color(c, parent_color)).unwrap_or(parent_color);
+        let background_color = get("background-color")
+            .and_then(|c| parse_color(c, color))
+            .unwrap_or(Color::TRANSPARENT);
+
+        let font_size = lengths.font_size;
+        let parent_weight = parent.map(|p| p.font.weight).unwrap_or(400);
+
+        let font = FontDescriptor {
+            families: resolve_font_families(get("font-family").unwrap_or("sans-serif")),
+            size: font_size,
+            weight: get("font-weight").and_then(|w| parse_font_weight(w, parent_weight)).unwrap_or(400),
+            style: match get("font-style").map(str::trim) {
+                Some("italic") => FontStyle::Italic,
+                Some(s) if s.starts_with("oblique") => FontStyle::Oblique,
+                _ => FontStyle::Normal,
+            },
+            line_height: get("line-height")
+                .and_then(|lh| parse_line_height(lh, &lengths))
+                .unwrap_or(font_size * 1.2),
+        };
+
+        let spacing = |property: &str| {
+            get(property)
+                .filter(|v| v.trim() != "normal")
+                .and_then(|v| lengths.resolve(v, 0.0))
+                .unwrap_or(0.0)
+        };
+
+        let transform = get("transform")
+            .map(str::trim)
+            .filter(|t| !t.is_empty() && *t != "none")
+            .map(str::to_string);
+
+        let position = get("position").and_then(Position::parse).unwrap_or_default();
+
+        // absolutely positioned boxes can't float, see https://www.w3.org/TR/CSS2/visuren.html#dis-pos-flo
+        let float = match position.is_out_of_flow() {
+            true => Float::None,
+            false => get("float").and_then(Float::parse).unwrap_or_default(),
+        };
+
+        Self {
+            display: values.get("display").and_then(Display::parse).unwrap_or(default_display),
+            position,
+            float,
+            clear: get("clear").and_then(Clear::parse).unwrap_or_default(),
+            z_index: get("z-index").and_then(|z| z.trim().parse().ok()),
+            visibility: match get("visibility").map(str::trim) {
+                Some("hidden") => Visibility::Hidden,
+                Some("collapse") => Visibility::Collapse,
+                _ => Visibility::Visible,
+            },
+            pointer_events: match get("pointer-events").map(str::trim) {
+                Some("none") => PointerEvents::None,
+                _ => PointerEvents::Auto,
+            },
+            cursor: get("cursor").and_then(CursorStyle::parse).unwrap_or_default(),
+
+            color,
+            background_color,
+
+            border_radius: get("border-radius").and_then(|r| tokens(r).first().and_then(|r| LayoutLength::parse(r, &lengths))),
+            box_shadows: get("box-shadow").map(|s| parse_box_shadows(s, &lengths, color)).unwrap_or_default(),
+            text_shadows: get("text-shadow").map(|s| parse_text_shadows(s, &lengths, color)).unwrap_or_default(),
+            selection: SelectionColors::default(),
+
+            compositing: Compositing::from_values(values),
+            overflow: OverflowStyle::from_values(values),
+            scroll_behavior: match get("scroll-behavior").map(str::trim) {
+                Some("smooth") => ScrollBehavior::Smooth,
+                _ => ScrollBehavior::Auto,
+            },
+            transform,
+            transform_origin: values.get("transform-origin").map(str::to_string),
+
+            font,
+            white_space: get("white-space").and_then(WhiteSpace::parse).unwrap_or_default(),
+            text_align: get("text-align").and_then(TextAlign::parse).unwrap_or_default(),
+            letter_spacing: spacing("letter-spacing"),
+            word_spacing: spacing("word-spacing"),
+
+            lengths,
+        }
+    }
+
+    /// Whether the element creates a stacking context no matter its `z-index`
+    pub fn always_creates_stacking_context(&self) -> bool {
+        matches!(self.position, Position::Fixed | Position::Sticky)
+            || self.compositing.needs_layer()
+            || self.transform.is_some()
+    }
+
+    /// The z-index of the stacking context this element creates, if it creates one
+    pub fn stacking_z_index(&self, in_flex_or_grid: bool) -> Option<i32> {
+        let positioned = self.position.is_positioned();
+
+        if self.always_creates_stacking_context() {
+            return Some(if positioned || in_flex_or_grid {
+                self.z_index.unwrap_or(0)
+            } else {
+                0
+            });
+        }
+
+        if positioned || in_flex_or_grid {
+            return self.z_index;
+        }
+
+        None
+    }
+
+    /// The border radius for a border box of the given width, percentages refer to the width
+    pub fn border_radius(&self, width: f64) -> f64 {
+        self.border_radius.map_or(0.0, |r| r.resolve(width))
+    }
+
+    /// The transform of the element in the coordinate space of its parent, with the
+    /// `transform-origin` applied. Returns `None` for `transform: none`.
+    pub fn transform_matrix(&self, border_box: Rect) -> Option<Affine> {
+        let size = (border_box.width(), border_box.height());
+        let matrix = parse_transform(self.transform.as_deref()?, size, &self.lengths)?;
+
+        let origin = self
+            .transform_origin
+            .as_deref()
+            .map(|o| parse_transform_origin(o, size, &self.lengths))
+            .unwrap_or((size.0 / 2.0, size.1 / 2.0));
+
+        let origin = (border_box.x0 + origin.0, border_box.y0 + origin.1);
+
+        Some(Affine::translate(origin) * matrix * Affine::translate((-origin.0, -origin.1)))
+    }
+}
+
+fn parse_font_weight(value: &str, parent: u16) -> Option<u16> {
+    Some(match value.trim() {
+        "normal" => 400,
+        "bold" => 700,
+        // https://drafts.csswg.org/css-fonts/#relative-weights
+        "bolder" => match parent {
+            0..=349 => 400,
+            350..=549 => 700,
+            _ => 900,
+        },
+        "lighter" => match parent {
+            0..=549 => 100,
+            550..=749 => 400,
+            _ => 700,
+        },
+        w => w.parse::<f32>().ok()?.clamp(1.0, 1000.0) as u16,
+    })
+}
+
+fn parse_line_height(value: &str, lengths: &LengthContext) -> Option<f64> {
+    let value = value.trim();
+
+    if value == "normal" {
+        return Some(lengths.font_size * 1.2);
+    }
+
+    if let Ok(factor) = value.parse::<f64>() {
+        return Some(factor * lengths.font_size);
+    }
+
+    lengths.resolve(value, lengths.font_size)
+}
+
+/// Resolves the lengths of inherited properties to pixels, descendants inherit the computed length
+/// instead of resolving `em` and percentages against their own font size again. A unitless
+/// `line-height` is inherited as a number and scales with the font size of each descendant.
+fn compute_inherited_lengths(values: &mut ComputedValues, lengths: &LengthContext) {
+    let px = |length: f64| format!("{length}px");
+    let mut computed = Vec::new();
+
+    if let Some(line_height) = values.get("line-height").map(str::trim) {
+        if line_height != "normal" && line_height.parse::<f64>().is_err() {
+            computed.push(("line-height", lengths.resolve(line_height, lengths.font_size).map(px)));
+        }
+    }
+
+    for property in ["letter-spacing", "word-spacing"] {
+        if let Some(spacing) = values.get(property).map(str::trim).filter(|s| *s != "normal") {
+            computed.push((property, lengths.resolve(spacing, 0.0).map(px)));
+        }
+    }
+
+    if let Some(shadows) = values.get("text-shadow") {
+        let shadows = split_top_level(shadows, ',')
+            .into_iter()
+            .map(|shadow| {
+                tokens(shadow)
+                    .into_iter()
+                    .map(|token| lengths.resolve(token, 0.0).map_or_else(|| token.to_string(), px))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        computed.push(("text-shadow", Some(shadows)));
+    }
+
+    // values that don't resolve are left for the parsers to reject
+    for (property, value) in computed {
+        if let Some(value) = value {
+            values.values.insert(property.to_string(), value);
+        }
+    }
+}
+
+/// Properties that never change the size or position of a box, changing them only needs a repaint
+const PAINT_ONLY_PROPERTIES: &[&str] = &[
+    "background-color",
+    "background-image",
+    "border-color",
+    "border-top-color",
+    "border-right-color",
+    "border-bottom-color",
+    "border-left-color",
+    "border-radius",
+    "box-shadow",
+    "color",
+    "cursor",
+    "filter",
+    "isolation",
+    "mix-blend-mode",
+    "opacity",
+    "outline",
+    "outline-color",
+    "outline-style",
+    "outline-width",
+    "pointer-events",
+    "scroll-behavior",
+    "text-decoration",
+    "text-decoration-color",
+    "text-decoration-line",
+    "text-decoration-style",
+    "text-shadow",
+    "transform",
+    "transform-origin",
+    "visibility",
+    "z-index",
+];
+
+/// How much of the rendering has to be redone after a restyle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StyleChange {
+    None,
+    Repaint,
+    Relayout,
+}
+
+impl StyleChange {
+    fn between(old: &ComputedValues, new: &ComputedValues) -> Self {
+        let changed = old
+            .iter()
+            .filter(|(property, value)| new.get(property) != Some(*value))
+            .map(|(property, _)| property)
+            .chain(new.iter().filter(|(property, _)| old.get(property).is_none()).map(|(property, _)| property));
+
+        changed.fold(StyleChange::None, |change, property| {
+            let needed = match PAINT_ONLY_PROPERTIES.contains(&property) {
+                true => StyleChange::Repaint,
+                false => StyleChange::Relayout,
+            };
+            change.max(needed)
+        })
+    }
+}
+
+/// Computed styles of every node in the render tree
+#[derive(Debug, Clone, Default)]
+pub struct ComputedStyles {
+    values: HashMap<NodeId, ComputedValues>,
+    styles: HashMap<NodeId, ComputedStyle>,
+    parents: HashMap<NodeId, NodeId>,
+    root_font_size: f64,
+    viewport: (f64, f64),
+    device_pixel_ratio: f64,
+}
+
+impl ComputedStyles {
+    /// Runs the style pass over the whole render tree, `viewport` is needed to compute font sizes
+    /// given in viewport units. The state dependent rules are cascaded on top of the styles from
+    /// the render tree.
+    pub fn compute(render_tree: &RenderTree, viewport: (f64, f64), device_pixel_ratio: f64, state: &StateCascade) -> Self {
+        let mut styles = Self {
+            values: HashMap::with_capacity(render_tree.nodes.len()),
+            styles: HashMap::with_capacity(render_tree.nodes.len()),
+            parents: HashMap::with_capacity(render_tree.nodes.len()),
+            root_font_size: DEFAULT_FONT_SIZE,
+            viewport,
+            device_pixel_ratio,
+        };
+
+        let root = ComputedValues::default();
+        styles.compute_node(render_tree, NodeId::root(), &root, None, state, None);
+
+        styles
+    }
+
+    /// Computes the styles of a node and its descendants again, after the state of elements
+    /// changed. Returns what has to be redone for the changes.
+    pub fn restyle(&mut self, render_tree: &RenderTree, id: NodeId, state: &StateCascade) -> StyleChange {
+        let parent_id = self.parents.get(&id).copied();
+        let parent = parent_id
+            .and_then(|p| self.values.get(&p))
+            .cloned()
+            .unwrap_or_default();
+
+        let mut change = StyleChange::None;
+        self.compute_node(render_tree, id, &parent, parent_id, state, Some(&mut change));
+
+        change
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.parents.get(&id).copied()
+    }
+
+    /// The node followed by its ancestors up to the root
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(Some(id), |id| self.parent(*id))
+    }
+
+    /// The typed computed style of a node
+    pub fn get(&self, id: NodeId) -> Option<&ComputedStyle> {
+        self.styles.get(&id)
+    }
+
+    /// The computed values of a node as css strings, for properties that have no typed
+    /// representation yet
+    pub fn values(&self, id: NodeId) -> Option<&ComputedValues> {
+        self.values.get(&id)
+    }
+
+    /// A length context for the node, the containing block has to be filled in by the caller
+    /// when percentages are involved
+    pub fn length_context(&self, id: NodeId) -> LengthContext {
+        match self.get(id) {
+            Some(style) => style.lengths,
+            None => self.base_context(),
+        }
+    }
+
+    fn base_context(&self) -> LengthContext {
+        let mut ctx = LengthContext::new(self.viewport);
+        ctx.root_font_size = self.root_font_size;
+        ctx.device_pixel_ratio = self.device_pixel_ratio;
+        ctx
+    }
+
+    /// Computes the styles of a subtree. When `change` is given the previous styles are compared
+    /// with the new ones, to find out how much of the rendering is out of date.
+    fn compute_node(
+        &mut self,
+        render_tree: &RenderTree,
+        id: NodeId,
+        parent: &ComputedValues,
+        parent_id: Option<NodeId>,
+        state: &StateCascade,
+        mut change: Option<&mut StyleChange>,
+    ) {
+        let Some(node) = render_tree.get_node(id) else {
+            return;
+        };
+
+        if let Some(parent_id) = parent_id {
+            self.parents.insert(id, parent_id);
+        }
+
+        let is_root = parent_id.is_none();
+        // inherited properties take the parent's value, unless they are specified
+        let mut values = ComputedValues::inherited(parent);
+        let mut selection = Vec::new();
+
+        let element = match &node.data {
+            RenderNodeData::Element(element) => Some(element.name.as_str()),
+            _ => None,
+        };
+
+        // the user agent styles come first, everything the author specifies overrides them
+        for (property, value) in element.map(user_agent_styles).unwrap_or_default() {
+            values.values.insert(property.to_string(), value.to_string());
+        }
+
+        // text nodes don't have any styles of their own
+        if !matches!(node.data, RenderNodeData::Text(_)) {
+            for (property, prop) in node.properties.properties.iter() {
+                let mut prop = prop.clone();
+                prop.compute_value();
+
+                let Some(value) = value_to_string(&prop.actual) else {
+                    continue;
+                };
+
+                match resolve_keyword(property, value.trim(), parent, element) {
+                    Some(value) => {
+                        values.values.insert(property.clone(), value);
+                    }
+                    None => {
+                        values.values.remove(property);
+                    }
+                }
+            }
+
+            let cx = MatchContext {
+                render_tree,
+                parents: &self.parents,
+                states: state.states,
+                viewport: self.viewport,
+            };
+
+            for declaration in state.rules.declarations(id, &cx) {
+                match resolve_keyword(&declaration.property, &declaration.value, parent, element) {
+                    Some(value) => {
+                        values.values.insert(declaration.property.clone(), value);
+                    }
+                    None => {
+                        values.values.remove(&declaration.property);
+                    }
+                }
+            }
+
+            selection = state
+                .rules
+                .selection_declarations(id, &cx)
+                .into_iter()
+                .map(|d| (d.property.clone(), d.value.clone()))
+                .collect();
+        }
+
+        let lengths = self.compute_font_size(&mut values, parent, is_root);
+        compute_inherited_lengths(&mut values, &lengths);
+
+        let default_display = match element.map(user_agent_display) {
+            Some("block") => Display::Block,
+            _ => Display::Inline,
+        };
+
+        let parent_style = parent_id.and_then(|p| self.styles.get(&p));
+        let mut style = ComputedStyle::from_values(&values, lengths, default_display, parent_style);
+
+        // the highlight inherits from the parent's highlight, not from the element itself
+        let parent_selection = parent_style.map(|p| p.selection).unwrap_or_default();
+        style.selection = parent_selection.with_declarations(&selection, style.color);
+
+        // `color` computes to an absolute color, so descendants don't resolve `currentColor` or
+        // `color-mix()` against their own parent again
+        values.values.insert(String::from("color"), to_css_string(style.color));
+
+        if let Some(change) = change.as_deref_mut() {
+            let old = self.values.get(&id).cloned().unwrap_or_default();
+            *change = (*change).max(StyleChange::between(&old, &values));
+        }
+
+        let children = node.children.clone();
+        self.styles.insert(id, style);
+        self.values.insert(id, values.clone());
+
+        for child in children {
+            self.compute_node(render_tree, child, &values, Some(id), state, change.as_deref_mut());
+        }
+    }
+
+    /// Font sizes are inherited as absolute lengths, otherwise `em` would compound on every level.
+    /// Returns the length context for the element.
+    fn compute_font_size(&mut self, values: &mut ComputedValues, parent: &ComputedValues, is_root: bool) -> LengthContext {
+        let mut ctx = self.base_context();
+        ctx.font_size = if is_root { DEFAULT_FONT_SIZE } else { parent.font_size() };
+
+        let font_size = values
+            .get("font-size")
+            .and_then(|fs| ctx.resolve_font_size(fs))
+            .unwrap_or(ctx.font_size);
+
+        if is_root {
+            self.root_font_size = font_size;
+            ctx.root_font_size = font_size;
+        }
+
+        values.values.insert(String::from("font-size"), format!("{font_size}px"));
+
+        ctx.with_font_size(font_size)
+    }
+}
+
+/// The box model styles of the user agent stylesheet that give documents their usual spacing, see
+/// https://html.spec.whatwg.org/multipage/rendering.html#the-css-user-agent-style-sheet-and-presentational-hints
+fn user_agent_styles(name: &str) -> &'static [(&'static str, &'static str)] {
+    match name {
+        "body" => &[("margin", "8px")],
+        "p" | "dl" | "pre" => &[("margin", "1em 0")],
+        "blockquote" | "figure" => &[("margin", "1em 40px")],
+        "ul" | "ol" | "menu" => &[("margin", "1em 0"), ("padding", "0 0 0 40px")],
+        "dd" => &[("margin", "0 0 0 40px")],
+        "h1" => &[("margin", "0.67em 0")],
+        "h2" => &[("margin", "0.83em 0")],
+        "h3" => &[("margin", "1em 0")],
+        "h4" => &[("margin", "1.33em 0")],
+        "h5" => &[("margin", "1.67em 0")],
+        "h6" => &[("margin", "2.33em 0")],
+        "hr" => &[("margin", "0.5em auto")],
+        _ => &[],
+    }
+}
+
+/// The display of an element in the user agent styles, everything but phrasing content is a block
+fn user_agent_display(name: &str) -> &'static str {
+    if INLINE_ELEMENTS.contains(&name) {
+        "inline"
+    } else {
+        "block"
+    }
+}
+
+/// The value the user agent styles give a property of an element, the sides of `margin` and
+/// `padding` are taken from their shorthand
+fn user_agent_value(name: &str, property: &str) -> Option<String> {
+    if property == "display" {
+        return Some(user_agent_display(name).to_string());
+    }
+
+    let styles = user_agent_styles(name);
+    if let Some((_, value)) = styles.iter().find(|(p, _)| *p == property) {
+        return Some(value.to_string());
+    }
+
+    let (shorthand, side) = property.split_once('-')?;
+    let index = ["top", "right", "bottom", "left"].iter().position(|s| *s == side)?;
+    let (_, value) = styles.iter().find(|(p, _)| *p == shorthand)?;
+
+    box_shorthand(value).map(|sides| sides[index].to_string())
+}
+
+/// Resolves the css-wide keywords of a property of `element`, returns `None` if the property ends
+/// up not being set
+fn resolve_keyword(property: &str, value: &str, parent: &ComputedValues, element: Option<&str>) -> Option<String> {
+    let inherit = || parent.get(property).or_else(|| initial_value(property)).map(str::to_string);
+    let initial = || initial_value(property).map(str::to_string);
+
+    // `revert` rolls back to the user agent styles, properties they don't set behave like `unset`
+    let user_agent = match (value, element) {
+        ("revert" | "revert-layer", Some(element)) => user_agent_value(element, property),
+        _ => None,
+    };
+    if user_agent.is_some() {
+        return user_agent;
+    }
+
+    match value {
+        "inherit" => inherit(),
+        "initial" => initial(),
+        "unset" | "revert" | "revert-layer" => {
+            if is_inherited(property) {
+                inherit()
+            } else {
+                initial()
+            }
+        }
+        v => Some(v.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> ComputedValues {
+        ComputedValues {
+            values: pairs.iter().map(|(p, v)| (p.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn inherits_only_inherited_properties() {
+        let parent = values(&[("color", "red"), ("font-size", "20px"), ("margin", "8px"), ("display", "flex")]);
+        let child = ComputedValues::inherited(&parent);
+
+        assert_eq!(child.get("color"), Some("red"));
+        assert_eq!(child.get("font-size"), Some("20px"));
+        assert_eq!(child.get("margin"), None);
+        assert_eq!(child.get("display"), None);
+    }
+
+    #[test]
+    fn inherit_takes_the_parent_value() {
+        let parent = values(&[("color", "red"), ("margin", "8px")]);
+
+        assert_eq!(resolve_keyword("color", "inherit", &parent, None), Some("red".to_string()));
+        assert_eq!(resolve_keyword("margin", "inherit", &parent, None), Some("8px".to_string()));
+        // without a parent value the initial value is inherited
+        assert_eq!(resolve_keyword("visibility", "inherit", &parent, None), Some("visible".to_string()));
+    }
+
+    #[test]
+    fn initial_takes_the_initial_value() {
+        let parent = values(&[("color", "red")]);
+
+        assert_eq!(resolve_keyword("color", "initial", &parent, None), Some("black".to_string()));
+        assert_eq!(resolve_keyword("display", "initial", &parent, None), Some("inline".to_string()));
+    }
+
+    #[test]
+    fn unset_inherits_or_resets() {
+        let parent = values(&[("color", "red"), ("display", "flex")]);
+
+        assert_eq!(resolve_keyword("color", "unset", &parent, None), Some("red".to_string()));
+        assert_eq!(resolve_keyword("display", "unset", &parent, None), Some("inline".to_string()));
+    }
+
+    #[test]
+    fn revert_rolls_back_to_the_user_agent() {
+        let parent = values(&[("color", "red")]);
+
+        assert_eq!(resolve_keyword("margin", "revert", &parent, Some("body")), Some("8px".to_string()));
+        assert_eq!(resolve_keyword("margin-top", "revert", &parent, Some("p")), Some("1em".to_string()));
+        assert_eq!(resolve_keyword("padding-left", "revert", &parent, Some("ul")), Some("40px".to_string()));
+        assert_eq!(resolve_keyword("display", "revert", &parent, Some("div")), Some("block".to_string()));
+        assert_eq!(resolve_keyword("display", "revert", &parent, Some("span")), Some("inline".to_string()));
+        // properties the user agent doesn't set behave like `unset`
+        assert_eq!(resolve_keyword("color", "revert", &parent, Some("p")), Some("red".to_string()));
+        assert_eq!(resolve_keyword("visibility", "revert", &parent, Some("div")), Some("visible".to_string()));
+    }
+
+    #[test]
+    fn inherits_computed_lengths() {
+        let parent_lengths = LengthContext::default().with_font_size(20.0);
+        let mut parent = values(&[("line-height", "150%"), ("letter-spacing", "0.1em"), ("text-shadow", "1em 2px red")]);
+        compute_inherited_lengths(&mut parent, &parent_lengths);
+
+        assert_eq!(parent.get("line-height"), Some("30px"));
+        assert_eq!(parent.get("letter-spacing"), Some("2px"));
+        assert_eq!(parent.get("text-shadow"), Some("20px 2px red"));
+
+        // a nested element with a different font size keeps the line height of its parent
+        let child_lengths = parent_lengths.with_font_size(10.0);
+        let mut child = ComputedValues::inherited(&parent);
+        compute_inherited_lengths(&mut child, &child_lengths);
+
+        assert_eq!(child.get("line-height"), Some("30px"));
+        assert_eq!(parse_line_height(child.get("line-height").unwrap(), &child_lengths), Some(30.0));
+        assert_eq!(child.get("letter-spacing"), Some("2px"));
+    }
+
+    #[test]
+    fn inherits_unitless_line_heights_as_numbers() {
+        let mut parent = values(&[("line-height", "1.5")]);
+        compute_inherited_lengths(&mut parent, &LengthContext::default().with_font_size(20.0));
+
+        let child_lengths = LengthContext::default().with_font_size(10.0);
+        let child = ComputedValues::inherited(&parent);
+
+        assert_eq!(child.get("line-height"), Some("1.5"));
+        assert_eq!(parse_line_height(child.get("line-height").unwrap(), &child_lengths), Some(15.0));
+    }
+
+    #[test]
+    fn other_values_are_kept() {
+        let parent = ComputedValues::default();
+        assert_eq!(resolve_keyword("color", "blue", &parent, None), Some("blue".to_string()));
+    }
+}
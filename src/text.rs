@@ -6,15 +6,55 @@ use vello::glyph::Glyph;
 use vello::kurbo::Affine;
 use vello::peniko::{Blob, BrushRef, Font, StyleRef};
 use vello::Scene;
-use vello::skrifa::{FontRef, MetadataProvider};
+use vello::skrifa::{FontRef, GlyphId, MetadataProvider};
 use vello::skrifa::instance::Size;
 
 use crate::FONT_CACHE;
 
+/// Concrete families that are tried for the generic font families, in order
+const GENERIC_FAMILIES: &[(&str, &[&str])] = &[
+    ("serif", &["C059", "DejaVu Serif", "Liberation Serif", "Times New Roman"]),
+    ("sans-serif", &["Nimbus Sans", "DejaVu Sans", "Liberation Sans", "Arial"]),
+    ("monospace", &["Nimbus Mono PS", "DejaVu Sans Mono", "Liberation Mono", "Courier New"]),
+    ("cursive", &["Z003", "Comic Sans MS"]),
+    ("system-ui", &["Cantarell", "DejaVu Sans", "Segoe UI"]),
+];
+
+/// Turns a `font-family` value into the list of family names to query, unquoted and with generic
+/// families expanded. Sans-serif is always appended as the last resort.
+pub fn resolve_font_families(font_family: &str) -> Vec<String> {
+    let mut families = Vec::new();
+
+    let generic = |name: &str| {
+        GENERIC_FAMILIES
+            .iter()
+            .find(|(generic, _)| generic.eq_ignore_ascii_case(name))
+            .map(|(_, families)| *families)
+    };
+
+    for family in font_family.split(',').chain(std::iter::once("sans-serif")) {
+        let family = family.trim().trim_matches(|c| c == '"' || c == '\'');
+        if family.is_empty() {
+            continue;
+        }
+
+        match generic(family) {
+            Some(concrete) => families.extend(concrete.iter().map(|f| f.to_string())),
+            None => families.push(family.to_string()),
+        }
+    }
+
+    families
+}
+
 pub struct TextRenderer {
     font: Font,
     font_size: f32,
     pub line_height: f32,
+    /// The distance from the top of a line to its baseline
+    pub ascent: f32,
+    /// The distance from the baseline to the bottom of a line, without the leading
+    pub descent: f32,
 }
 
 impl TextRenderer {
@@ -40,8 +80,9 @@ impl TextRenderer {
 
         let metrics = font_ref.metrics(fs, &var_loc);
         let line_height = metrics.ascent - metrics.descent + metrics.leading;
+        let (ascent, descent) = (metrics.ascent, -metrics.descent);
 
-        Self { font, font_size, line_height }
+        Self { font, font_size, line_height, ascent, descent }
     }
 
     pub fn new_with_font(font: Font, font_size: f32) -> Self {
@@ -54,8 +95,32 @@ impl TextRenderer {
 
         let metrics = font_ref.metrics(fs, &var_loc);
         let line_height = metrics.ascent - metrics.descent + metrics.leading;
+        let (ascent, descent) = (metrics.ascent, -metrics.descent);
+
+        Self { font, font_size, line_height, ascent, descent }
+    }
+
+    /// The horizontal advance of a glyph at the renderer's font size
+    pub fn glyph_advance(&self, glyph_id: u32) -> f32 {
+        let Some(font_ref) = to_font_ref(&self.font) else {
+            return 0.0;
+        };
+
+        let variations: &[(&str, f32)] = &[];
+        let var_loc = font_ref.axes().location(variations.iter().copied());
+        let glyph_metrics = font_ref.glyph_metrics(Size::new(self.font_size), &var_loc);
+
+        glyph_metrics.advance_width(GlyphId::new(glyph_id as u16)).unwrap_or_default()
+    }
+
+    /// The horizontal advance of the glyph the font has for a character
+    pub fn char_advance(&self, c: char) -> f32 {
+        let Some(font_ref) = to_font_ref(&self.font) else {
+            return 0.0;
+        };
 
-        Self { font, font_size, line_height }
+        let gid = font_ref.charmap().map(c).unwrap_or_default();
+        self.glyph_advance(gid.to_u16() as u32)
     }
 
     pub fn render_text<'a>(
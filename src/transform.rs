@@ -0,0 +1,186 @@
+use std::f64::consts::PI;
+
+use vello::kurbo::Affine;
+
+use crate::css::{split_top_level, tokens};
+use crate::units::LengthContext;
+
+/// Parses a list of transform functions into a single matrix. 3D functions are flattened onto the
+/// xy plane, which is what an orthographic projection without perspective would show.
+pub fn parse_transform(value: &str, size: (f64, f64), ctx: &LengthContext) -> Option<Affine> {
+    let value = value.trim();
+    if value.is_empty() || value == "none" {
+        return None;
+    }
+
+    let mut transform = Affine::IDENTITY;
+
+    for function in tokens(value) {
+        let (name, args) = function.split_once('(')?;
+        let args = args.strip_suffix(')')?;
+        let args = split_top_level(args, ',');
+
+        transform *= parse_function(name.trim(), &args, size, ctx)?;
+    }
+
+    Some(transform)
+}
+
+fn parse_function(name: &str, args: &[&str], size: (f64, f64), ctx: &LengthContext) -> Option<Affine> {
+    let len_x = |i: usize| args.get(i).and_then(|a| ctx.resolve(a, size.0));
+    let len_y = |i: usize| args.get(i).and_then(|a| ctx.resolve(a, size.1));
+    let number = |i: usize| args.get(i).and_then(|a| parse_number(a));
+    let angle = |i: usize| args.get(i).and_then(|a| parse_angle(a));
+
+    Some(match name {
+        "translate" => Affine::translate((len_x(0)?, len_y(1).unwrap_or(0.0))),
+        "translateX" => Affine::translate((len_x(0)?, 0.0)),
+        "translateY" => Affine::translate((0.0, len_y(0)?)),
+        "translateZ" => Affine::IDENTITY,
+        "translate3d" => Affine::translate((len_x(0)?, len_y(1)?)),
+
+        "scale" => {
+            let x = number(0)?;
+            Affine::scale_non_uniform(x, number(1).unwrap_or(x))
+        }
+        "scaleX" => Affine::scale_non_uniform(number(0)?, 1.0),
+        "scaleY" => Affine::scale_non_uniform(1.0, number(0)?),
+        "scaleZ" => Affine::IDENTITY,
+        "scale3d" => Affine::scale_non_uniform(number(0)?, number(1)?),
+
+        "rotate" | "rotateZ" => Affine::rotate(angle(0)?),
+        // rotating around the x or y axis only foreshortens the other axis when flattened
+        "rotateX" => Affine::scale_non_uniform(1.0, angle(0)?.cos()),
+        "rotateY" => Affine::scale_non_uniform(angle(0)?.cos(), 1.0),
+        "rotate3d" => rotate3d(number(0)?, number(1)?, number(2)?, angle(3)?),
+
+        "skew" => skew(angle(0)?, angle(1).unwrap_or(0.0)),
+        "skewX" => skew(angle(0)?, 0.0),
+        "skewY" => skew(0.0, angle(0)?),
+
+        "matrix" => {
+            if args.len() != 6 {
+                return None;
+            }
+            let mut m = [0.0; 6];
+            for (i, v) in m.iter_mut().enumerate() {
+                *v = number(i)?;
+            }
+            Affine::new(m)
+        }
+        "matrix3d" => {
+            if args.len() != 16 {
+                return None;
+            }
+            // column major 4x4 matrix, drop everything that involves z
+            Affine::new([
+                number(0)?,
+                number(1)?,
+                number(4)?,
+                number(5)?,
+                number(12)?,
+                number(13)?,
+            ])
+        }
+
+        "perspective" => Affine::IDENTITY,
+
+        _ => return None,
+    })
+}
+
+fn skew(ax: f64, ay: f64) -> Affine {
+    Affine::new([1.0, ay.tan(), ax.tan(), 1.0, 0.0, 0.0])
+}
+
+/// The 2D part of a 3D rotation around an arbitrary axis, see
+/// https://drafts.csswg.org/css-transforms-2/#Rotate3dDefined
+fn rotate3d(x: f64, y: f64, z: f64, angle: f64) -> Affine {
+    let len = (x * x + y * y + z * z).sqrt();
+    if len == 0.0 {
+        return Affine::IDENTITY;
+    }
+
+    let (x, y, z) = (x / len, y / len, z / len);
+    let sc = (angle / 2.0).sin() * (angle / 2.0).cos();
+    let sq = (angle / 2.0).sin().powi(2);
+
+    Affine::new([
+        1.0 - 2.0 * (y * y + z * z) * sq,
+        2.0 * (x * y * sq + z * sc),
+        2.0 * (x * y * sq - z * sc),
+        1.0 - 2.0 * (x * x + z * z) * sq,
+        0.0,
+        0.0,
+    ])
+}
+
+/// Parses `transform-origin` into an offset from the top left of the reference box
+pub fn parse_transform_origin(value: &str, size: (f64, f64), ctx: &LengthContext) -> (f64, f64) {
+    let mut x = None;
+    let mut y = None;
+    let mut pending_center = 0;
+
+    for token in tokens(value) {
+        match token {
+            "left" => x = Some(0.0),
+            "right" => x = Some(size.0),
+            "top" => y = Some(0.0),
+            "bottom" => y = Some(size.1),
+            "center" => pending_center += 1,
+            t => {
+                // lengths are always x first, then y
+                if x.is_none() {
+                    x = ctx.resolve(t, size.0);
+                } else if y.is_none() {
+                    y = ctx.resolve(t, size.1);
+                }
+            }
+        }
+    }
+
+    for _ in 0..pending_center {
+        if x.is_none() {
+            x = Some(size.0 / 2.0);
+        } else if y.is_none() {
+            y = Some(size.1 / 2.0);
+        }
+    }
+
+    (x.unwrap_or(size.0 / 2.0), y.unwrap_or(size.1 / 2.0))
+}
+
+fn parse_number(value: &str) -> Option<f64> {
+    if let Some(percentage) = value.strip_suffix('%') {
+        return Some(percentage.parse::<f64>().ok()? / 100.0);
+    }
+
+    value.parse().ok()
+}
+
+/// Parses an angle into radians
+pub fn parse_angle(value: &str) -> Option<f64> {
+    let value = value.trim();
+
+    if value == "0" {
+        return Some(0.0);
+    }
+
+    if let Some(deg) = value.strip_suffix("deg") {
+        return Some(deg.parse::<f64>().ok()?.to_radians());
+    }
+
+    if let Some(grad) = value.strip_suffix("grad") {
+        return Some(grad.parse::<f64>().ok()? * PI / 200.0);
+    }
+
+    if let Some(rad) = value.strip_suffix("rad") {
+        return rad.parse().ok();
+    }
+
+    if let Some(turn) = value.strip_suffix("turn") {
+        return Some(turn.parse::<f64>().ok()? * 2.0 * PI);
+    }
+
+    None
+}
@@ -0,0 +1,697 @@
+use gosub_html5::node::NodeId;
+use taffy::{Dimension, LengthPercentage, LengthPercentageAuto, NodeId as TaffyID, PrintTree, TaffyTree, TraversePartialTree};
+
+use crate::css::tokens;
+use crate::style::{ComputedStyles, ComputedValues};
+
+/// The initial value of `font-size`, which is `medium`
+pub const DEFAULT_FONT_SIZE: f64 = 16.0;
+
+/// Everything a length can be relative to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LengthContext {
+    /// Computed font size of the element, in css pixels
+    pub font_size: f64,
+    /// Computed font size of the root element, in css pixels
+    pub root_font_size: f64,
+    /// Size of the viewport, in css pixels
+    pub viewport: (f64, f64),
+    /// Size of the containing block, in css pixels
+    pub containing_block: (f64, f64),
+    /// Number of device pixels per css pixel
+    pub device_pixel_ratio: f64,
+}
+
+impl Default for LengthContext {
+    fn default() -> Self {
+        Self {
+            font_size: DEFAULT_FONT_SIZE,
+            root_font_size: DEFAULT_FONT_SIZE,
+            viewport: (1920.0, 1080.0),
+            containing_block: (0.0, 0.0),
+            device_pixel_ratio: 1.0,
+        }
+    }
+}
+
+impl LengthContext {
+    pub fn new(viewport: (f64, f64)) -> Self {
+        Self {
+            viewport,
+            containing_block: viewport,
+            ..Default::default()
+        }
+    }
+
+    /// Resolves a length or `calc()` expression into css pixels. Percentages are resolved against
+    /// `percent_base`.
+    pub fn resolve(&self, value: &str, percent_base: f64) -> Option<f64> {
+        let mut parser = Parser {
+            tokens: tokenize(value.trim())?,
+            pos: 0,
+            ctx: self,
+            percent_base,
+        };
+
+        let value = parser.expr()?;
+        if parser.pos != parser.tokens.len() {
+            return None;
+        }
+
+        // a plain number is only a valid length if it is zero
+        match value {
+            Value::Length(px) => Some(px),
+            Value::Number(n) if n == 0.0 => Some(0.0),
+            Value::Number(_) => None,
+        }
+    }
+
+    /// Resolves a horizontal length, percentages refer to the width of the containing block
+    pub fn resolve_x(&self, value: &str) -> Option<f64> {
+        self.resolve(value, self.containing_block.0)
+    }
+
+    /// Resolves a vertical length, percentages refer to the height of the containing block
+    pub fn resolve_y(&self, value: &str) -> Option<f64> {
+        self.resolve(value, self.containing_block.1)
+    }
+
+    /// Resolves a length into device pixels
+    pub fn resolve_device(&self, value: &str, percent_base: f64) -> Option<f64> {
+        self.resolve(value, percent_base).map(|px| self.to_device(px))
+    }
+
+    pub fn to_device(&self, px: f64) -> f64 {
+        px * self.device_pixel_ratio
+    }
+
+    /// Resolves a `font-size` value, where relative units and percentages refer to the parent's
+    /// font size, which is the `font_size` of this context.
+    pub fn resolve_font_size(&self, value: &str) -> Option<f64> {
+        let parent = self.font_size;
+
+        let keyword = match value.trim() {
+            "xx-small" => Some(DEFAULT_FONT_SIZE * 3.0 / 5.0),
+            "x-small" => Some(DEFAULT_FONT_SIZE * 3.0 / 4.0),
+            "small" => Some(DEFAULT_FONT_SIZE * 8.0 / 9.0),
+            "medium" => Some(DEFAULT_FONT_SIZE),
+            "large" => Some(DEFAULT_FONT_SIZE * 6.0 / 5.0),
+            "x-large" => Some(DEFAULT_FONT_SIZE * 3.0 / 2.0),
+            "xx-large" => Some(DEFAULT_FONT_SIZE * 2.0),
+            "xxx-large" => Some(DEFAULT_FONT_SIZE * 3.0),
+            "smaller" => Some(parent / 1.2),
+            "larger" => Some(parent * 1.2),
+            _ => None,
+        };
+
+        keyword.or_else(|| self.resolve(value, parent)).map(|fs| fs.max(0.0))
+    }
+
+    /// A copy of this context for an element with the given font size
+    pub fn with_font_size(&self, font_size: f64) -> Self {
+        Self { font_size, ..*self }
+    }
+
+    pub fn with_containing_block(&self, containing_block: (f64, f64)) -> Self {
+        Self {
+            containing_block,
+            ..*self
+        }
+    }
+
+    fn unit(&self, value: f64, unit: &str, percent_base: f64) -> Option<f64> {
+        let (vw, vh) = (self.viewport.0 / 100.0, self.viewport.1 / 100.0);
+
+        Some(match unit.to_ascii_lowercase().as_str() {
+            "px" => value,
+            "%" => value / 100.0 * percent_base,
+
+            "em" => value * self.font_size,
+            "rem" => value * self.root_font_size,
+            // without access to the font metrics, the usual fallback of half an em is used
+            "ex" | "ch" => value * self.font_size / 2.0,
+            "rex" | "rch" => value * self.root_font_size / 2.0,
+            "cap" => value * self.font_size * 0.7,
+            "ic" => value * self.font_size,
+            "lh" => value * self.font_size * 1.2,
+            "rlh" => value * self.root_font_size * 1.2,
+
+            "vw" | "svw" | "lvw" | "dvw" => value * vw,
+            "vh" | "svh" | "lvh" | "dvh" => value * vh,
+            "vi" | "svi" | "lvi" | "dvi" => value * vw,
+            "vb" | "svb" | "lvb" | "dvb" => value * vh,
+            "vmin" | "svmin" | "lvmin" | "dvmin" => value * vw.min(vh),
+            "vmax" | "svmax" | "lvmax" | "dvmax" => value * vw.max(vh),
+
+            "cqw" | "cqi" => value * self.containing_block.0 / 100.0,
+            "cqh" | "cqb" => value * self.containing_block.1 / 100.0,
+            "cqmin" => value * self.containing_block.0.min(self.containing_block.1) / 100.0,
+            "cqmax" => value * self.containing_block.0.max(self.containing_block.1) / 100.0,
+
+            "in" => value * 96.0,
+            "cm" => value * 96.0 / 2.54,
+            "mm" => value * 96.0 / 25.4,
+            "q" => value * 96.0 / 101.6,
+            "pt" => value * 96.0 / 72.0,
+            "pc" => value * 16.0,
+
+            _ => return None,
+        })
+    }
+}
+
+/// The length context of a layout node, with the parent's size as the containing block
+pub fn length_context(styles: &ComputedStyles, layout: &TaffyTree<NodeId>, id: TaffyID) -> LengthContext {
+    let ctx = match layout.get_node_context(id) {
+        Some(gosub_id) => styles.length_context(*gosub_id),
+        None => LengthContext::default(),
+    };
+
+    match layout.parent(id) {
+        Some(parent) => {
+            let size = layout.get_final_layout(parent).size;
+            ctx.with_containing_block((size.width as f64, size.height as f64))
+        }
+        None => ctx,
+    }
+}
+
+/// A length as taffy understands it, percentages are kept so taffy can resolve them against the
+/// containing block during layout
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayoutLength {
+    Auto,
+    Length(f32),
+    Percent(f32),
+}
+
+impl LayoutLength {
+    pub fn parse(value: &str, ctx: &LengthContext) -> Option<Self> {
+        let value = value.trim();
+
+        if value == "auto" {
+            return Some(LayoutLength::Auto);
+        }
+
+        if let Some(percentage) = value.strip_suffix('%') {
+            if let Ok(percentage) = percentage.trim().parse::<f32>() {
+                return Some(LayoutLength::Percent(percentage / 100.0));
+            }
+        }
+
+        // taffy can't mix lengths with percentages, so an expression with percentages is only kept
+        // if it has no other lengths, like `calc(100% / 3)`
+        let tokens = tokenize(value).unwrap_or_default();
+        if tokens.iter().any(|t| matches!(t, Token::Percentage(_))) {
+            if tokens.iter().any(|t| matches!(t, Token::Dimension(..))) {
+                return None;
+            }
+
+            return ctx.resolve(value, 100.0).map(|percent| LayoutLength::Percent((percent / 100.0) as f32));
+        }
+
+        ctx.resolve(value, 0.0).map(|px| LayoutLength::Length(px as f32))
+    }
+
+    pub fn to_dimension(self) -> Dimension {
+        match self {
+            LayoutLength::Auto => Dimension::Auto,
+            LayoutLength::Length(px) => Dimension::Length(px),
+            LayoutLength::Percent(p) => Dimension::Percent(p),
+        }
+    }
+
+    pub fn to_length_percentage_auto(self) -> LengthPercentageAuto {
+        match self {
+            LayoutLength::Auto => LengthPercentageAuto::Auto,
+            LayoutLength::Length(px) => LengthPercentageAuto::Length(px),
+            LayoutLength::Percent(p) => LengthPercentageAuto::Percent(p),
+        }
+    }
+
+    /// Resolves the length against `percent_base`, `auto` resolves to zero
+    pub fn resolve(self, percent_base: f64) -> f64 {
+        match self {
+            LayoutLength::Auto => 0.0,
+            LayoutLength::Length(px) => px as f64,
+            LayoutLength::Percent(p) => p as f64 * percent_base,
+        }
+    }
+
+    pub fn to_length_percentage(self) -> Option<LengthPercentage> {
+        match self {
+            LayoutLength::Auto => None,
+            LayoutLength::Length(px) => Some(LengthPercentage::Length(px)),
+            LayoutLength::Percent(p) => Some(LengthPercentage::Percent(p)),
+        }
+    }
+}
+
+/// Expands a 1 to 4 value box shorthand like `margin: 1em auto` into top, right, bottom and left
+pub(crate) fn box_shorthand(value: &str) -> Option<[&str; 4]> {
+    let values = tokens(value);
+
+    Some(match values.as_slice() {
+        [all] => [*all; 4],
+        [y, x] => [*y, *x, *y, *x],
+        [top, x, bottom] => [*top, *x, *bottom, *x],
+        [top, right, bottom, left] => [*top, *right, *bottom, *left],
+        _ => return None,
+    })
+}
+
+/// Reads the sides of a box property, first from the shorthand, then from the longhands
+fn box_sides<'a>(values: &'a ComputedValues, shorthand: &str, longhands: [&str; 4]) -> [Option<&'a str>; 4] {
+    let mut sides = [None; 4];
+
+    if let Some(shorthand) = values.get(shorthand).and_then(box_shorthand) {
+        for (side, value) in sides.iter_mut().zip(shorthand) {
+            *side = Some(value);
+        }
+    }
+
+    for (side, longhand) in sides.iter_mut().zip(longhands) {
+        if let Some(value) = values.get(longhand) {
+            *side = Some(value);
+        }
+    }
+
+    sides
+}
+
+/// Re-resolves all lengths of the taffy styles with the length resolver, so relative units like
+/// `rem` and `vw` work in layout as well
+pub fn apply_length_styles(tree: &mut TaffyTree<NodeId>, root: TaffyID, styles: &ComputedStyles) {
+    let mut stack = vec![root];
+
+    while let Some(id) = stack.pop() {
+        stack.extend(tree.child_ids(id));
+
+        let Some(gosub_id) = tree.get_node_context(id).copied() else {
+            continue;
+        };
+
+        let Some(values) = styles.values(gosub_id) else {
+            continue;
+        };
+
+        let Ok(style) = tree.style(id) else {
+            continue;
+        };
+
+        let ctx = styles.length_context(gosub_id);
+        let mut style = style.clone();
+
+        let parse = |value: Option<&str>| value.and_then(|v| LayoutLength::parse(v, &ctx));
+
+        let [top, right, bottom, left] = box_sides(values, "margin", ["margin-top", "margin-right", "margin-bottom", "margin-left"]);
+        for (side, value) in [
+            (&mut style.margin.top, top),
+            (&mut style.margin.right, right),
+            (&mut style.margin.bottom, bottom),
+            (&mut style.margin.left, left),
+        ] {
+            if let Some(length) = parse(value) {
+                *side = length.to_length_percentage_auto();
+            }
+        }
+
+        let [top, right, bottom, left] = box_sides(values, "padding", ["padding-top", "padding-right", "padding-bottom", "padding-left"]);
+        for (side, value) in [
+            (&mut style.padding.top, top),
+            (&mut style.padding.right, right),
+            (&mut style.padding.bottom, bottom),
+            (&mut style.padding.left, left),
+        ] {
+            if let Some(length) = parse(value).and_then(LayoutLength::to_length_percentage) {
+                *side = length;
+            }
+        }
+
+        let [top, right, bottom, left] = box_sides(values, "border-width", ["border-top-width", "border-right-width", "border-bottom-width", "border-left-width"]);
+        for (side, value) in [
+            (&mut style.border.top, top),
+            (&mut style.border.right, right),
+            (&mut style.border.bottom, bottom),
+            (&mut style.border.left, left),
+        ] {
+            if let Some(length) = parse(value).and_then(LayoutLength::to_length_percentage) {
+                *side = length;
+            }
+        }
+
+        for (side, name) in [
+            (&mut style.inset.top, "top"),
+            (&mut style.inset.right, "right"),
+            (&mut style.inset.bottom, "bottom"),
+            (&mut style.inset.left, "left"),
+        ] {
+            if let Some(length) = parse(values.get(name)) {
+                *side = length.to_length_percentage_auto();
+            }
+        }
+
+        for (dimension, name) in [
+            (&mut style.size.width, "width"),
+            (&mut style.size.height, "height"),
+            (&mut style.min_size.width, "min-width"),
+            (&mut style.min_size.height, "min-height"),
+            (&mut style.max_size.width, "max-width"),
+            (&mut style.max_size.height, "max-height"),
+        ] {
+            if let Some(length) = parse(values.get(name)) {
+                *dimension = length.to_dimension();
+            }
+        }
+
+        if let Err(e) = tree.set_style(id, style) {
+            eprintln!("Failed to set length styles: {e:?}");
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Number(f64),
+    Length(f64),
+}
+
+impl Value {
+    fn raw(self) -> f64 {
+        match self {
+            Value::Number(n) | Value::Length(n) => n,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Dimension(f64, String),
+    Percentage(f64),
+    /// A function name, including the opening parenthesis
+    Function(String),
+    Open,
+    Close,
+    Comma,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+}
+
+fn tokenize(input: &str) -> Option<Vec<Token>> {
+    let chars = input.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        // a sign belongs to the number if it can't be a binary operator here
+        let signed_number = (c == '-' || c == '+')
+            && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit() || *n == '.')
+            && !matches!(
+                tokens.last(),
+                Some(Token::Number(_) | Token::Dimension(..) | Token::Percentage(_) | Token::Close)
+            );
+
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' || signed_number {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // exponent, but not the start of a unit like `em`
+            if i + 1 < chars.len()
+                && (chars[i] == 'e' || chars[i] == 'E')
+                && (chars[i + 1].is_ascii_digit() || chars[i + 1] == '-' || chars[i + 1] == '+')
+            {
+                i += 2;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+
+            let number = chars[start..i].iter().collect::<String>().parse::<f64>().ok()?;
+
+            if chars.get(i) == Some(&'%') {
+                i += 1;
+                tokens.push(Token::Percentage(number));
+            } else {
+                let unit_start = i;
+                while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+
+                if unit_start == i {
+                    tokens.push(Token::Number(number));
+                } else {
+                    tokens.push(Token::Dimension(number, chars[unit_start..i].iter().collect()));
+                }
+            }
+        } else if c.is_ascii_alphabetic() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '-') {
+                i += 1;
+            }
+
+            if chars.get(i) != Some(&'(') {
+                return None;
+            }
+            i += 1;
+
+            tokens.push(Token::Function(chars[start..i - 1].iter().collect::<String>().to_ascii_lowercase()));
+        } else {
+            tokens.push(match c {
+                '(' => Token::Open,
+                ')' => Token::Close,
+                ',' => Token::Comma,
+                '+' => Token::Plus,
+                '-' => Token::Minus,
+                '*' => Token::Star,
+                '/' => Token::Slash,
+                _ => return None,
+            });
+            i += 1;
+        }
+    }
+
+    Some(tokens)
+}
+
+/// Evaluates `calc()`, `min()`, `max()` and `clamp()` expressions, see
+/// https://drafts.csswg.org/css-values/#calc-syntax
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    ctx: &'a LengthContext,
+    percent_base: f64,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn expect(&mut self, token: Token) -> Option<()> {
+        (self.next()? == token).then_some(())
+    }
+
+    fn expr(&mut self) -> Option<Value> {
+        let mut value = self.term()?;
+
+        loop {
+            match self.peek() {
+                Some(Token::Plus) => {
+                    self.pos += 1;
+                    value = add(value, self.term()?, 1.0)?;
+                }
+                Some(Token::Minus) => {
+                    self.pos += 1;
+                    value = add(value, self.term()?, -1.0)?;
+                }
+                _ => return Some(value),
+            }
+        }
+    }
+
+    fn term(&mut self) -> Option<Value> {
+        let mut value = self.factor()?;
+
+        loop {
+            match self.peek() {
+                Some(Token::Star) => {
+                    self.pos += 1;
+                    let rhs = self.factor()?;
+                    value = match (value, rhs) {
+                        (Value::Number(a), Value::Number(b)) => Value::Number(a * b),
+                        (Value::Length(a), Value::Number(b)) | (Value::Number(b), Value::Length(a)) => Value::Length(a * b),
+                        _ => return None,
+                    };
+                }
+                Some(Token::Slash) => {
+                    self.pos += 1;
+                    let Value::Number(rhs) = self.factor()? else {
+                        return None;
+                    };
+                    if rhs == 0.0 {
+                        return None;
+                    }
+                    value = match value {
+                        Value::Number(a) => Value::Number(a / rhs),
+                        Value::Length(a) => Value::Length(a / rhs),
+                    };
+                }
+                _ => return Some(value),
+            }
+        }
+    }
+
+    fn factor(&mut self) -> Option<Value> {
+        match self.next()? {
+            Token::Number(n) => Some(Value::Number(n)),
+            Token::Percentage(p) => Some(Value::Length(p / 100.0 * self.percent_base)),
+            Token::Dimension(v, unit) => Some(Value::Length(self.ctx.unit(v, &unit, self.percent_base)?)),
+            Token::Open => {
+                let value = self.expr()?;
+                self.expect(Token::Close)?;
+                Some(value)
+            }
+            Token::Function(name) => {
+                let mut args = vec![self.expr()?];
+                while self.peek() == Some(&Token::Comma) {
+                    self.pos += 1;
+                    args.push(self.expr()?);
+                }
+                self.expect(Token::Close)?;
+
+                match name.as_str() {
+                    "calc" if args.len() == 1 => Some(args[0]),
+                    "min" => fold(&args, f64::min),
+                    "max" => fold(&args, f64::max),
+                    "clamp" if args.len() == 3 => {
+                        let max = fold(&args[1..], f64::min)?;
+                        fold(&[args[0], max], f64::max)
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+fn add(a: Value, b: Value, sign: f64) -> Option<Value> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => Some(Value::Number(a + sign * b)),
+        (Value::Length(a), Value::Length(b)) => Some(Value::Length(a + sign * b)),
+        // `0` is allowed in place of a length
+        (Value::Length(a), Value::Number(b)) if b == 0.0 => Some(Value::Length(a)),
+        (Value::Number(a), Value::Length(b)) if a == 0.0 => Some(Value::Length(sign * b)),
+        _ => None,
+    }
+}
+
+/// Folds arguments that all have to be of the same type
+fn fold(args: &[Value], f: fn(f64, f64) -> f64) -> Option<Value> {
+    let first = *args.first()?;
+
+    let result = args[1..].iter().try_fold(first.raw(), |acc, arg| {
+        (std::mem::discriminant(arg) == std::mem::discriminant(&first)).then(|| f(acc, arg.raw()))
+    })?;
+
+    Some(match first {
+        Value::Number(_) => Value::Number(result),
+        Value::Length(_) => Value::Length(result),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx() -> LengthContext {
+        LengthContext {
+            font_size: 20.0,
+            root_font_size: 16.0,
+            viewport: (1000.0, 500.0),
+            containing_block: (400.0, 200.0),
+            device_pixel_ratio: 2.0,
+        }
+    }
+
+    #[test]
+    fn resolves_units() {
+        let ctx = ctx();
+
+        assert_eq!(ctx.resolve("10px", 0.0), Some(10.0));
+        assert_eq!(ctx.resolve("2em", 0.0), Some(40.0));
+        assert_eq!(ctx.resolve("2rem", 0.0), Some(32.0));
+        assert_eq!(ctx.resolve("10vw", 0.0), Some(100.0));
+        assert_eq!(ctx.resolve("1in", 0.0), Some(96.0));
+        assert_eq!(ctx.resolve("50%", 300.0), Some(150.0));
+        assert_eq!(ctx.resolve("0", 0.0), Some(0.0));
+        assert_eq!(ctx.resolve("5", 0.0), None);
+        assert_eq!(ctx.resolve("5furlongs", 0.0), None);
+    }
+
+    #[test]
+    fn evaluates_calc() {
+        let ctx = ctx();
+
+        assert_eq!(ctx.resolve("calc(10px + 2em)", 0.0), Some(50.0));
+        assert_eq!(ctx.resolve("calc((10px + 5px) * 2)", 0.0), Some(30.0));
+        assert_eq!(ctx.resolve("calc(100px / 4 - 5px)", 0.0), Some(20.0));
+        assert_eq!(ctx.resolve("calc(100% - 20px)", 300.0), Some(280.0));
+        assert_eq!(ctx.resolve("calc(-1 * 10px)", 0.0), Some(-10.0));
+        assert_eq!(ctx.resolve("min(10px, 2em)", 0.0), Some(10.0));
+        assert_eq!(ctx.resolve("max(10px, 2em)", 0.0), Some(40.0));
+        assert_eq!(ctx.resolve("clamp(10px, 50%, 100px)", 400.0), Some(100.0));
+
+        // units have to match
+        assert_eq!(ctx.resolve("calc(10px + 2)", 0.0), None);
+        assert_eq!(ctx.resolve("calc(10px * 2px)", 0.0), None);
+        assert_eq!(ctx.resolve("calc(10px / 0)", 0.0), None);
+        assert_eq!(ctx.resolve("calc(10px + 2em", 0.0), None);
+    }
+
+    #[test]
+    fn keeps_percentages_for_taffy() {
+        let ctx = ctx();
+
+        assert_eq!(LayoutLength::parse("auto", &ctx), Some(LayoutLength::Auto));
+        assert_eq!(LayoutLength::parse("25%", &ctx), Some(LayoutLength::Percent(0.25)));
+        assert_eq!(LayoutLength::parse("calc(10px + 1em)", &ctx), Some(LayoutLength::Length(30.0)));
+        assert_eq!(LayoutLength::parse("calc(50% * 2)", &ctx), Some(LayoutLength::Percent(1.0)));
+        assert_eq!(LayoutLength::parse("calc(10% + 15%)", &ctx), Some(LayoutLength::Percent(0.25)));
+        assert_eq!(LayoutLength::parse("min(10%, 20%)", &ctx), Some(LayoutLength::Percent(0.1)));
+        assert!(matches!(LayoutLength::parse("calc(100% / 3)", &ctx), Some(LayoutLength::Percent(p)) if (p - 1.0 / 3.0).abs() < 1e-6));
+    }
+
+    #[test]
+    fn drops_lengths_mixed_with_percentages() {
+        let ctx = ctx();
+
+        assert_eq!(LayoutLength::parse("calc(100% - 20px)", &ctx), None);
+        assert_eq!(LayoutLength::parse("calc(20px + 10%)", &ctx), None);
+        assert_eq!(LayoutLength::parse("min(50%, 100px)", &ctx), None);
+        assert_eq!(LayoutLength::parse("max(10%, 0px)", &ctx), None);
+    }
+
+    #[test]
+    fn expands_box_shorthands() {
+        assert_eq!(box_shorthand("1px"), Some(["1px"; 4]));
+        assert_eq!(box_shorthand("1px 2px"), Some(["1px", "2px", "1px", "2px"]));
+        assert_eq!(box_shorthand("1px 2px 3px"), Some(["1px", "2px", "3px", "2px"]));
+        assert_eq!(box_shorthand("1px 2px 3px 4px 5px"), None);
+    }
+}
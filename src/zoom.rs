@@ -0,0 +1,88 @@
+use vello::kurbo::{Affine, Point, Vec2};
+
+/// The page zoom levels Ctrl+Plus and Ctrl+Minus step through
+pub const ZOOM_LEVELS: [f64; 16] = [0.25, 0.33, 0.5, 0.67, 0.75, 0.8, 0.9, 1.0, 1.1, 1.25, 1.5, 1.75, 2.0, 2.5, 3.0, 4.0];
+
+/// The largest pinch zoom, the smallest one shows the whole viewport
+pub const MAX_VISUAL_ZOOM: f64 = 5.0;
+
+/// The next zoom level after `zoom`, or the one before it when zooming `out`
+pub fn step_zoom(zoom: f64, out: bool) -> f64 {
+    let next = match out {
+        true => ZOOM_LEVELS.iter().rev().find(|level| **level < zoom - 0.001),
+        false => ZOOM_LEVELS.iter().find(|level| **level > zoom + 0.001),
+    };
+
+    next.copied().unwrap_or(zoom)
+}
+
+/// Pinch zoom, which magnifies the painted page like a loupe without laying it out again. The
+/// offset is the top left of the magnified part, in window pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VisualZoom {
+    scale: f64,
+    offset: Vec2,
+    /// The size of the window in its logical pixels
+    viewport: (f64, f64),
+}
+
+impl Default for VisualZoom {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            offset: Vec2::ZERO,
+            viewport: (0.0, 0.0),
+        }
+    }
+}
+
+impl VisualZoom {
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    pub fn set_viewport(&mut self, viewport: (f64, f64)) {
+        self.viewport = viewport;
+        self.offset = self.clamp(self.offset);
+    }
+
+    /// Zooms by `factor` while the content under `center` stays where it is. Returns whether the
+    /// scale changed.
+    pub fn pinch(&mut self, center: Point, factor: f64) -> bool {
+        let scale = (self.scale * factor).clamp(1.0, MAX_VISUAL_ZOOM);
+        if scale == self.scale {
+            return false;
+        }
+
+        let content = self.to_content(center);
+        self.scale = scale;
+        self.offset = self.clamp(content.to_vec2() - center.to_vec2() / scale);
+
+        true
+    }
+
+    pub fn reset(&mut self) -> bool {
+        let changed = self.scale != 1.0;
+        self.scale = 1.0;
+        self.offset = Vec2::ZERO;
+        changed
+    }
+
+    /// Maps unmagnified window pixels to the window pixels they are painted at
+    pub fn transform(&self) -> Affine {
+        Affine::scale(self.scale) * Affine::translate(-self.offset)
+    }
+
+    /// The unmagnified position of a point of the window
+    pub fn to_content(&self, point: Point) -> Point {
+        (point.to_vec2() / self.scale + self.offset).to_point()
+    }
+
+    /// Keeps the magnified part inside the window
+    fn clamp(&self, offset: Vec2) -> Vec2 {
+        let max_x = self.viewport.0 * (1.0 - 1.0 / self.scale);
+        let max_y = self.viewport.1 * (1.0 - 1.0 / self.scale);
+
+        Vec2::new(offset.x.clamp(0.0, max_x.max(0.0)), offset.y.clamp(0.0, max_y.max(0.0)))
+    }
+}